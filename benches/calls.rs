@@ -0,0 +1,42 @@
+extern crate plasma;
+
+use std::time::{Duration, Instant};
+
+use plasma::interpreter::{Engine, Executor};
+
+const FIB: &str = "var fib = function(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } };
+                   fib(20)";
+
+// Runs `code` a few times on `engine` and reports the fastest run.
+fn bench(name: &str, engine: Engine, code: &str) {
+    let mut best = Duration::from_secs(3600);
+    for _ in 0..5 {
+        let mut exec = Executor::new().with_engine(engine);
+        let start = Instant::now();
+        exec.execute(code).unwrap();
+        best = best.min(start.elapsed());
+    }
+    println!("{:<28} {:?} {:>10.2?}", name, engine, best);
+}
+
+// A function returning right away from a body of `statements` more, so
+// what a call costs shows against the size of the body.
+fn early_return(statements: usize) -> String {
+    let body = "var x = [1, 2, 3].map(y => y * 2);".repeat(statements);
+    format!("var f = function(n) {{ if (true) {{ return n; }} {} }};
+             for (var i of \"{}\") {{ f(1); }}",
+            body,
+            "x".repeat(10000))
+}
+
+// Before the tree-walker evaluated the syntax tree by reference and shared
+// function bodies, it took about 290ms for fib(20), 78ms for the calls with
+// 1 statement and 4.4s for those with 100, against about 16ms, 6.6ms and
+// 7.2ms after, in release builds on the same machine.
+fn main() {
+    for engine in [Engine::TreeWalker, Engine::Vm].iter() {
+        bench("fib(20)", *engine, FIB);
+        bench("10000 calls, 1 statement", *engine, &early_return(1));
+        bench("10000 calls, 100 statements", *engine, &early_return(100));
+    }
+}
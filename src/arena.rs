@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+use std::ops::{Index, IndexMut};
+
+use token::Position;
+
+// The index of a node in the arena of its kind, which stays the same for
+// as long as the tree lives.
+pub trait Id: Copy {
+    fn new(index: usize) -> Self;
+    fn index(self) -> usize;
+}
+
+macro_rules! node_id {
+    ($name:ident) => {
+        #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+        pub struct $name(u32);
+
+        impl $crate::arena::Id for $name {
+            fn new(index: usize) -> $name {
+                $name(index as u32)
+            }
+
+            fn index(self) -> usize {
+                self.0 as usize
+            }
+        }
+    };
+}
+
+// Nodes of one kind stored one after the other, each with the position in
+// the source it starts at.
+#[derive(Clone, PartialEq)]
+pub struct Arena<I, T> {
+    nodes: Vec<T>,
+    positions: Vec<Position>,
+    id: PhantomData<I>,
+}
+
+impl<I: Id, T> Arena<I, T> {
+    pub fn alloc(&mut self, node: T, position: Position) -> I {
+        self.nodes.push(node);
+        self.positions.push(position);
+        I::new(self.nodes.len() - 1)
+    }
+
+    pub fn position(&self, id: I) -> Position {
+        self.positions[id.index()]
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+}
+
+impl<I, T> Default for Arena<I, T> {
+    fn default() -> Arena<I, T> {
+        Arena {
+            nodes: Vec::new(),
+            positions: Vec::new(),
+            id: PhantomData,
+        }
+    }
+}
+
+impl<I, T: Debug> Debug for Arena<I, T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_list().entries(&self.nodes).finish()
+    }
+}
+
+impl<I: Id, T> Index<I> for Arena<I, T> {
+    type Output = T;
+
+    fn index(&self, id: I) -> &T {
+        &self.nodes[id.index()]
+    }
+}
+
+impl<I: Id, T> IndexMut<I> for Arena<I, T> {
+    fn index_mut(&mut self, id: I) -> &mut T {
+        &mut self.nodes[id.index()]
+    }
+}
+
+// What a pass found out about the nodes of an arena, kept apart from them
+// so the tree is not changed by it.
+#[derive(Clone, PartialEq)]
+pub struct NodeMap<I, V> {
+    values: Vec<Option<V>>,
+    id: PhantomData<I>,
+}
+
+impl<I: Id, V> NodeMap<I, V> {
+    pub fn insert(&mut self, id: I, value: V) {
+        let index = id.index();
+        if index >= self.values.len() {
+            self.values.resize_with(index + 1, || None);
+        }
+        self.values[index] = Some(value);
+    }
+
+    pub fn get(&self, id: I) -> Option<&V> {
+        self.values.get(id.index()).and_then(|value| value.as_ref())
+    }
+
+    pub fn contains(&self, id: I) -> bool {
+        self.get(id).is_some()
+    }
+}
+
+impl<I, V> Default for NodeMap<I, V> {
+    fn default() -> NodeMap<I, V> {
+        NodeMap {
+            values: Vec::new(),
+            id: PhantomData,
+        }
+    }
+}
+
+impl<I, V: Debug> Debug for NodeMap<I, V> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_map()
+            .entries(self.values
+                         .iter()
+                         .enumerate()
+                         .filter_map(|(index, value)| value.as_ref().map(|v| (index, v))))
+            .finish()
+    }
+}
+
+// A name stored once however often the source mentions it.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Symbol(u32);
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Symbols {
+    names: Vec<String>,
+    indexes: HashMap<String, Symbol>,
+}
+
+impl Symbols {
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(&symbol) = self.indexes.get(name) {
+            return symbol;
+        }
+        let symbol = Symbol(self.names.len() as u32);
+        self.names.push(name.to_owned());
+        self.indexes.insert(name.to_owned(), symbol);
+        symbol
+    }
+
+    pub fn name(&self, symbol: Symbol) -> &str {
+        &self.names[symbol.0 as usize]
+    }
+}
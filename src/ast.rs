@@ -1,9 +1,18 @@
+use std::ops::{Index, IndexMut};
+
+use arena::{Arena, Symbol, Symbols};
 use types::*;
 
+node_id!(ExprId);
+node_id!(StmtId);
+node_id!(PatternId);
+node_id!(IdentId);
+node_id!(FuncId);
+
 #[allow(non_camel_case_types)]
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expression {
-    IDENT(Identifier),
+    IDENT(IdentId),
     BOOL(Boolean),
     INTEGER(IntegerLiteral),
     STRING(StringLiteral),
@@ -11,53 +20,35 @@ pub enum Expression {
     PREFIX(PrefixExpression),
     INFIX(InfixExpression),
     IF(IfExpression),
-    FUNC(FunctionLiteral),
+    FUNC(FuncId),
     CALL(CallExpression),
+    ASSIGN(AssignExpression),
+    SPREAD(SpreadElement),
+    TERNARY(TernaryExpression),
+    HASH(HashLiteral),
+    MEMBER(MemberExpression),
+    NEW(NewExpression),
 }
 
 impl Node for Expression {
-    fn to_string(&self) -> String {
+    fn to_string(&self, ast: &Ast) -> String {
         match *self {
-            Expression::IDENT(ref ident) => ident.to_string(),
-            Expression::BOOL(ref b) => b.to_string(),
-            Expression::INTEGER(ref int) => int.to_string(),
-            Expression::STRING(ref string) => string.to_string(),
-            Expression::ARRAY(ref arr) => arr.to_string(),
-            Expression::PREFIX(ref pre) => pre.to_string(),
-            Expression::INFIX(ref inf) => inf.to_string(),
-            Expression::IF(ref if_exp) => if_exp.to_string(),
-            Expression::FUNC(ref func) => func.to_string(),
-            Expression::CALL(ref call) => call.to_string(),
-        }
-    }
-
-    fn token_literal(&self) -> String {
-        match *self {
-            Expression::IDENT(ref ident) => ident.token_literal(),
-            Expression::BOOL(ref b) => b.token_literal(),
-            Expression::INTEGER(ref int) => int.token_literal(),
-            Expression::STRING(ref string) => string.token_literal(),
-            Expression::ARRAY(ref arr) => arr.token_literal(),
-            Expression::PREFIX(ref pre) => pre.token_literal(),
-            Expression::INFIX(ref inf) => inf.token_literal(),
-            Expression::IF(ref if_exp) => if_exp.token_literal(),
-            Expression::FUNC(ref func) => func.token_literal(),
-            Expression::CALL(ref call) => call.token_literal(),
-        }
-    }
-
-    fn node_type(&self) -> NodeType {
-        match *self {
-            Expression::IDENT(ref ident) => ident.node_type(),
-            Expression::BOOL(ref b) => b.node_type(),
-            Expression::INTEGER(ref int) => int.node_type(),
-            Expression::STRING(ref string) => string.node_type(),
-            Expression::ARRAY(ref arr) => arr.node_type(),
-            Expression::PREFIX(ref pre) => pre.node_type(),
-            Expression::INFIX(ref inf) => inf.node_type(),
-            Expression::IF(ref if_exp) => if_exp.node_type(),
-            Expression::FUNC(ref func) => func.node_type(),
-            Expression::CALL(ref call) => call.node_type(),
+            Expression::IDENT(ref ident) => ident.to_string(ast),
+            Expression::BOOL(ref b) => b.to_string(ast),
+            Expression::INTEGER(ref int) => int.to_string(ast),
+            Expression::STRING(ref string) => string.to_string(ast),
+            Expression::ARRAY(ref arr) => arr.to_string(ast),
+            Expression::PREFIX(ref pre) => pre.to_string(ast),
+            Expression::INFIX(ref inf) => inf.to_string(ast),
+            Expression::IF(ref if_exp) => if_exp.to_string(ast),
+            Expression::FUNC(ref func) => func.to_string(ast),
+            Expression::CALL(ref call) => call.to_string(ast),
+            Expression::ASSIGN(ref assign) => assign.to_string(ast),
+            Expression::SPREAD(ref spread) => spread.to_string(ast),
+            Expression::TERNARY(ref ternary) => ternary.to_string(ast),
+            Expression::HASH(ref hash) => hash.to_string(ast),
+            Expression::MEMBER(ref member) => member.to_string(ast),
+            Expression::NEW(ref new) => new.to_string(ast),
         }
     }
 }
@@ -69,46 +60,102 @@ pub enum Statement {
     EXPR_STMT(ExpressionStatement),
     BLOCK_STMT(BlockStatement),
     RETURN(ReturnStatement),
+    FOR_OF(ForOfStatement),
+    CLASS(ClassStatement),
+    THROW(ThrowStatement),
+    TRY(TryStatement),
 }
 
 impl Node for Statement {
-    fn to_string(&self) -> String {
+    fn to_string(&self, ast: &Ast) -> String {
         match *self {
-            Statement::VAR(ref let_stmt) => let_stmt.to_string(),
-            Statement::EXPR_STMT(ref expr_stmt) => expr_stmt.to_string(),
-            Statement::BLOCK_STMT(ref blk_stmt) => blk_stmt.to_string(),
-            Statement::RETURN(ref rtn_stmt) => rtn_stmt.to_string(),
+            Statement::VAR(ref let_stmt) => let_stmt.to_string(ast),
+            Statement::EXPR_STMT(ref expr_stmt) => expr_stmt.to_string(ast),
+            Statement::BLOCK_STMT(ref blk_stmt) => blk_stmt.to_string(ast),
+            Statement::RETURN(ref rtn_stmt) => rtn_stmt.to_string(ast),
+            Statement::FOR_OF(ref for_stmt) => for_stmt.to_string(ast),
+            Statement::CLASS(ref class) => class.to_string(ast),
+            Statement::THROW(ref throw) => throw.to_string(ast),
+            Statement::TRY(ref try_stmt) => try_stmt.to_string(ast),
         }
     }
+}
+
+// Binding targets of declarations, parameters and for...of heads.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    IDENT(IdentId),
+    ARRAY(ArrayPattern),
+    OBJECT(ObjectPattern),
+}
 
-    fn token_literal(&self) -> String {
+impl Node for Pattern {
+    fn to_string(&self, ast: &Ast) -> String {
         match *self {
-            Statement::VAR(ref let_stmt) => let_stmt.token_literal(),
-            Statement::EXPR_STMT(ref expr_stmt) => expr_stmt.token_literal(),
-            Statement::BLOCK_STMT(ref blk_stmt) => blk_stmt.token_literal(),
-            Statement::RETURN(ref rtn_stmt) => rtn_stmt.token_literal(),
+            Pattern::IDENT(ref ident) => ident.to_string(ast),
+            Pattern::ARRAY(ref arr) => arr.to_string(ast),
+            Pattern::OBJECT(ref obj) => obj.to_string(ast),
         }
     }
+}
 
-    fn node_type(&self) -> NodeType {
-        match *self {
-            Statement::VAR(ref let_stmt) => let_stmt.node_type(),
-            Statement::EXPR_STMT(ref expr_stmt) => expr_stmt.node_type(),
-            Statement::BLOCK_STMT(ref blk_stmt) => blk_stmt.node_type(),
-            Statement::RETURN(ref rtn_stmt) => rtn_stmt.node_type(),
-        }
+// How a node prints, which is how functions show their source.
+pub trait Node {
+    fn to_string(&self, ast: &Ast) -> String;
+}
+
+// The nodes of a script, each kind in an arena of its own, referring to
+// each other by their ids. Passes over the tree keep what they find out in
+// side tables indexed by these ids rather than in the nodes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Ast {
+    pub expressions: Arena<ExprId, Expression>,
+    pub statements: Arena<StmtId, Statement>,
+    pub patterns: Arena<PatternId, Pattern>,
+    pub identifiers: Arena<IdentId, Symbol>,
+    pub functions: Arena<FuncId, FunctionLiteral>,
+    pub symbols: Symbols,
+}
+
+impl Ast {
+    pub fn name(&self, ident: IdentId) -> &str {
+        self.symbols.name(self[ident])
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum NodeType {
-    Expression(Expression),
-    Statement(Statement),
-    Program(Program),
+impl Node for Symbol {
+    fn to_string(&self, ast: &Ast) -> String {
+        ast.symbols.name(*self).to_owned()
+    }
 }
 
-pub trait Node {
-    fn to_string(&self) -> String;
-    fn token_literal(&self) -> String;
-    fn node_type(&self) -> NodeType;
+macro_rules! ast_index {
+    ($id:ident, $node:ty, $arena:ident) => {
+        impl Index<$id> for Ast {
+            type Output = $node;
+
+            fn index(&self, id: $id) -> &$node {
+                &self.$arena[id]
+            }
+        }
+
+        impl IndexMut<$id> for Ast {
+            fn index_mut(&mut self, id: $id) -> &mut $node {
+                &mut self.$arena[id]
+            }
+        }
+
+        impl Node for $id {
+            fn to_string(&self, ast: &Ast) -> String {
+                ast[*self].to_string(ast)
+            }
+        }
+    };
 }
+
+ast_index!(ExprId, Expression, expressions);
+ast_index!(StmtId, Statement, statements);
+ast_index!(PatternId, Pattern, patterns);
+ast_index!(IdentId, Symbol, identifiers);
+ast_index!(FuncId, FunctionLiteral, functions);
@@ -1,20 +1,45 @@
 extern crate plasma;
 extern crate clap;
 
-use plasma::interpreter::Executor;
-use clap::{App, Arg};
+use plasma::interpreter::{self, Executor};
+use clap::{App, Arg, SubCommand};
 
 use std::io::{self, BufRead, Read, Write};
 use std::fs::File;
+use std::path::Path;
 
-const ERROR: &'static str = "[Error]";
+const ERROR: &str = "[Error]";
 
 fn main() {
     let matches = App::new("Plasma")
         .arg(Arg::with_name("file").takes_value(true).index(1))
+        .subcommand(SubCommand::with_name("disasm")
+                        .about("Prints the bytecode a source file compiles to")
+                        .arg(Arg::with_name("file").required(true).index(1)))
+        .subcommand(SubCommand::with_name("compile")
+                        .about("Compiles a source file to bytecode run without parsing it")
+                        .arg(Arg::with_name("file").required(true).index(1))
+                        .arg(Arg::with_name("output")
+                                 .short("o")
+                                 .takes_value(true)
+                                 .help("Where to write the program, file.plc by default")))
         .get_matches();
 
-    if matches.is_present("file") {
+    if let Some(matches) = matches.subcommand_matches("compile") {
+        let file_name = matches.value_of("file").unwrap();
+        let output = match matches.value_of("output") {
+            Some(output) => output.to_owned(),
+            None => Path::new(file_name).with_extension("plc").to_string_lossy().into_owned(),
+        };
+        compile_file(file_name, &output);
+    } else if let Some(matches) = matches.subcommand_matches("disasm") {
+        if let Some(code) = read_source(matches.value_of("file").unwrap()) {
+            match interpreter::disassemble(&code) {
+                Ok(listing) => print!("{}", listing),
+                Err(e) => println!("{}", e),
+            }
+        }
+    } else if matches.is_present("file") {
         let file_name = match matches.value_of("file") {
             Some(v) => v,
             None => {
@@ -51,27 +76,68 @@ fn repl() {
     }
 }
 
-fn exec_file(file_name: &str) {
-    let mut exec = Executor::new();
-
+fn read_file(file_name: &str) -> Option<Vec<u8>> {
     let mut file = match File::open(file_name) {
         Ok(f) => f,
         Err(_) => {
             println!("{} Source file not found.", ERROR);
-            return;
+            return None;
         }
     };
 
-    let mut code = String::new();
-    match file.read_to_string(&mut code) {
-        Ok(_) => {}
+    let mut bytes = Vec::new();
+    match file.read_to_end(&mut bytes) {
+        Ok(_) => Some(bytes),
+        Err(_) => {
+            println!("{} Source invalid.", ERROR);
+            None
+        }
+    }
+}
+
+fn read_source(file_name: &str) -> Option<String> {
+    match String::from_utf8(read_file(file_name)?) {
+        Ok(code) => Some(code),
         Err(_) => {
             println!("{} Source invalid.", ERROR);
+            None
+        }
+    }
+}
+
+fn compile_file(file_name: &str, output: &str) {
+    let code = match read_source(file_name) {
+        Some(code) => code,
+        None => return,
+    };
+    let program = match interpreter::compile_named(&code, file_name) {
+        Ok(program) => program,
+        Err(e) => {
+            println!("{}", e);
             return;
         }
+    };
+    if File::create(output).and_then(|mut file| file.write_all(&program)).is_err() {
+        println!("{} Cannot write {}.", ERROR, output);
     }
+}
+
+// Compiled programs are told from sources by their extension.
+fn exec_file(file_name: &str) {
+    let mut exec = Executor::new().with_source_name(file_name);
+    let result = if file_name.ends_with(".plc") {
+        match read_file(file_name) {
+            Some(program) => exec.execute_compiled(&program),
+            None => return,
+        }
+    } else {
+        match read_source(file_name) {
+            Some(code) => exec.execute(&code),
+            None => return,
+        }
+    };
 
-    match exec.execute(&code) {
+    match result {
         Ok(v) => println!("{}", v),
         Err(e) => println!("{}", e),
     }
@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+
+use evaluator::{apply_function, is_truthy};
+use memory::Meter;
+use runtime::Runtime;
+use object::{self, Object, ObjectType, Objecter};
+
+// A method of a builtin type, called with its receiver, the evaluated
+// arguments and the runtime the values it makes are accounted on.
+type Method = fn(&Object, Vec<Object>, &Runtime) -> Result<Object, object::Error>;
+
+lazy_static! {
+    static ref STRING_METHODS: HashMap<&'static str, Method> = {
+        let mut hm: HashMap<&'static str, Method> = HashMap::new();
+        hm.insert("len", string_len);
+        hm.insert("upper", string_upper);
+        hm.insert("lower", string_lower);
+        hm.insert("trim", string_trim);
+        hm.insert("split", string_split);
+        hm
+    };
+
+    static ref ARRAY_METHODS: HashMap<&'static str, Method> = {
+        let mut hm: HashMap<&'static str, Method> = HashMap::new();
+        hm.insert("len", array_len);
+        hm.insert("join", array_join);
+        hm.insert("push", array_push);
+        hm.insert("map", array_map);
+        hm.insert("filter", array_filter);
+        hm.insert("reduce", array_reduce);
+        hm
+    };
+
+    static ref HASH_METHODS: HashMap<&'static str, Method> = {
+        let mut hm: HashMap<&'static str, Method> = HashMap::new();
+        hm.insert("len", hash_len);
+        hm.insert("keys", hash_keys);
+        hm.insert("values", hash_values);
+        hm
+    };
+}
+
+fn methods(obj_type: &ObjectType) -> Option<&'static HashMap<&'static str, Method>> {
+    match *obj_type {
+        ObjectType::STRING => Some(&STRING_METHODS),
+        ObjectType::ARRAY => Some(&ARRAY_METHODS),
+        ObjectType::HASH => Some(&HASH_METHODS),
+        _ => None,
+    }
+}
+
+pub fn call_method(receiver: &Object,
+                   name: &str,
+                   args: Vec<Object>,
+                   runtime: &Runtime)
+                   -> Option<Object> {
+    let obj_type = receiver.obj_type();
+    let result = match methods(&obj_type).and_then(|table| table.get(name)) {
+        Some(method) => method(receiver, args, runtime),
+        None => Err(new_error(format!("{:?} has no method '{}'", obj_type, name))),
+    };
+    match result {
+        Ok(obj) => Some(obj),
+        Err(err) => Some(Object::ERROR(Box::new(err))),
+    }
+}
+
+fn string_len(receiver: &Object,
+              args: Vec<Object>,
+              _: &Runtime)
+              -> Result<Object, object::Error> {
+    check_arity(&args, 0)?;
+    Ok(new_integer(receiver.inspect().chars().count() as i64))
+}
+
+fn string_upper(receiver: &Object,
+                args: Vec<Object>,
+                runtime: &Runtime)
+                -> Result<Object, object::Error> {
+    check_arity(&args, 0)?;
+    Ok(new_string(receiver.inspect().to_uppercase(), &runtime.meter()))
+}
+
+fn string_lower(receiver: &Object,
+                args: Vec<Object>,
+                runtime: &Runtime)
+                -> Result<Object, object::Error> {
+    check_arity(&args, 0)?;
+    Ok(new_string(receiver.inspect().to_lowercase(), &runtime.meter()))
+}
+
+fn string_trim(receiver: &Object,
+               args: Vec<Object>,
+               runtime: &Runtime)
+               -> Result<Object, object::Error> {
+    check_arity(&args, 0)?;
+    Ok(new_string(receiver.inspect().trim().to_owned(), &runtime.meter()))
+}
+
+fn string_split(receiver: &Object,
+                args: Vec<Object>,
+                runtime: &Runtime)
+                -> Result<Object, object::Error> {
+    check_arity(&args, 1)?;
+    let sep = match args[0] {
+        Object::STRING(ref s) => s.value.clone(),
+        ref other => return Err(argument_error("split", "STRING", other)),
+    };
+    let value = receiver.inspect();
+    // Splitting on "" yields the characters, like JS does.
+    let parts: Vec<String> = if sep.is_empty() {
+        value.chars().map(|c| c.to_string()).collect()
+    } else {
+        value.split(&*sep).map(|s| s.to_owned()).collect()
+    };
+    let elements = parts.into_iter().map(|value| new_string(value, &runtime.meter())).collect();
+    Ok(Object::ARRAY(object::Array::new(elements, &runtime.meter())))
+}
+
+fn array_elements(receiver: &Object) -> Vec<Object> {
+    match *receiver {
+        Object::ARRAY(ref arr) => arr.to_vec(),
+        _ => Vec::new(),
+    }
+}
+
+fn array_len(receiver: &Object, args: Vec<Object>, _: &Runtime) -> Result<Object, object::Error> {
+    check_arity(&args, 0)?;
+    Ok(new_integer(array_elements(receiver).len() as i64))
+}
+
+fn array_join(receiver: &Object,
+              args: Vec<Object>,
+              runtime: &Runtime)
+              -> Result<Object, object::Error> {
+    // The separator is a comma unless given, like in JS.
+    let sep = match args.len() {
+        0 => ",".into(),
+        1 => {
+            match args[0] {
+                Object::STRING(ref s) => s.value.clone(),
+                ref other => return Err(argument_error("join", "STRING", other)),
+            }
+        }
+        n => {
+            return Err(new_error(format!("wrong number of arguments: expected 0 or 1, got {}",
+                                         n)))
+        }
+    };
+    let mut joined = String::new();
+    for (index, element) in array_elements(receiver).iter().enumerate() {
+        if index > 0 {
+            joined.push_str(&sep);
+        }
+        joined.push_str(&runtime.stringify(element)?);
+        runtime.meter().reserve(joined.len())?;
+    }
+    Ok(new_string(joined, &runtime.meter()))
+}
+
+// Adds the arguments to the end of the array, for every copy of it to see,
+// and returns its new length.
+fn array_push(receiver: &Object,
+              args: Vec<Object>,
+              runtime: &Runtime)
+              -> Result<Object, object::Error> {
+    if args.is_empty() {
+        return Err(new_error("wrong number of arguments: expected at least 1, got 0".to_owned()));
+    }
+    runtime.meter().reserve(args.len() * mem::size_of::<Object>())?;
+    match *receiver {
+        Object::ARRAY(ref arr) => {
+            for arg in args {
+                arr.push(arg);
+            }
+            Ok(new_integer(arr.len() as i64))
+        }
+        _ => Ok(new_integer(0)),
+    }
+}
+
+fn array_map(receiver: &Object,
+             args: Vec<Object>,
+             runtime: &Runtime)
+             -> Result<Object, object::Error> {
+    check_arity(&args, 1)?;
+    let mut elements = Vec::new();
+    for (i, el) in array_elements(receiver).into_iter().enumerate() {
+        elements.push(call(&args[0], vec![el, new_integer(i as i64)])?);
+    }
+    Ok(Object::ARRAY(object::Array::new(elements, &runtime.meter())))
+}
+
+fn array_filter(receiver: &Object,
+                args: Vec<Object>,
+                runtime: &Runtime)
+                -> Result<Object, object::Error> {
+    check_arity(&args, 1)?;
+    let mut elements = Vec::new();
+    for (i, el) in array_elements(receiver).into_iter().enumerate() {
+        if is_truthy(call(&args[0], vec![el.clone(), new_integer(i as i64)])?) {
+            elements.push(el);
+        }
+    }
+    Ok(Object::ARRAY(object::Array::new(elements, &runtime.meter())))
+}
+
+// reduce(f) starts from the first element, reduce(f, init) from init.
+fn array_reduce(receiver: &Object,
+                args: Vec<Object>,
+                _: &Runtime)
+                -> Result<Object, object::Error> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(new_error(format!("wrong number of arguments: expected 1 or 2, got {}",
+                                     args.len())));
+    }
+    let mut elements = array_elements(receiver).into_iter();
+    let mut acc = match args.get(1) {
+        Some(init) => init.clone(),
+        None => {
+            match elements.next() {
+                Some(first) => first,
+                None => {
+                    return Err(new_error("reduce of empty array with no initial value".to_owned()))
+                }
+            }
+        }
+    };
+    for el in elements {
+        acc = call(&args[0], vec![acc, el])?;
+    }
+    Ok(acc)
+}
+
+fn hash_pairs(receiver: &Object) -> Vec<(String, Object)> {
+    match *receiver {
+        Object::HASH(ref hash) => hash.pairs.borrow().clone(),
+        _ => Vec::new(),
+    }
+}
+
+fn hash_len(receiver: &Object, args: Vec<Object>, _: &Runtime) -> Result<Object, object::Error> {
+    check_arity(&args, 0)?;
+    Ok(new_integer(hash_pairs(receiver).len() as i64))
+}
+
+fn hash_keys(receiver: &Object,
+             args: Vec<Object>,
+             runtime: &Runtime)
+             -> Result<Object, object::Error> {
+    check_arity(&args, 0)?;
+    let elements = hash_pairs(receiver)
+        .into_iter()
+        .map(|(key, _)| new_string(key, &runtime.meter()))
+        .collect();
+    Ok(Object::ARRAY(object::Array::new(elements, &runtime.meter())))
+}
+
+fn hash_values(receiver: &Object,
+               args: Vec<Object>,
+               runtime: &Runtime)
+               -> Result<Object, object::Error> {
+    check_arity(&args, 0)?;
+    let elements = hash_pairs(receiver).into_iter().map(|(_, value)| value).collect();
+    Ok(Object::ARRAY(object::Array::new(elements, &runtime.meter())))
+}
+
+fn call(func: &Object, args: Vec<Object>) -> Result<Object, object::Error> {
+    match apply_function(func.clone(), args, None) {
+        Some(Object::ERROR(err)) => Err(*err),
+        Some(res) => Ok(res),
+        None => Ok(Object::NULL),
+    }
+}
+
+fn check_arity(args: &[Object], expected: usize) -> Result<(), object::Error> {
+    if args.len() != expected {
+        return Err(new_error(format!("wrong number of arguments: expected {}, got {}",
+                                     expected,
+                                     args.len())));
+    }
+    Ok(())
+}
+
+fn argument_error(method: &str, expected: &str, got: &Object) -> object::Error {
+    new_error(format!("argument to '{}' must be {}, got {:?}",
+                      method,
+                      expected,
+                      got.obj_type()))
+}
+
+fn new_error(message: String) -> object::Error {
+    object::Error::new(message)
+}
+
+fn new_integer(value: i64) -> Object {
+    Object::INTEGER(object::Integer { value })
+}
+
+fn new_string(value: String, meter: &Rc<Meter>) -> Object {
+    Object::STRING(object::Str::new(value, meter))
+}
@@ -0,0 +1,803 @@
+use std::rc::Rc;
+use std::str;
+
+use compiler::{Args, ClassTemplate, Code, Constant, Function, Op};
+use object;
+use parser;
+use token::Position;
+
+// Compiled programs saved to be run without their source. A file is
+//
+//   magic     the 4 bytes "\x7fPLC"
+//   version   u32, FORMAT_VERSION
+//   checksum  u32, the FNV-1a hash of everything after it
+//   source    the name of the source it was compiled from, for traces
+//   program   a code unit
+//
+// where a code unit is its constant pool, its slot count, its instructions,
+// then the position of each instruction, and functions and classes are
+// constants holding their own code units. Numbers are little-endian, strings are
+// their u32 length followed by their UTF-8 bytes.
+pub const MAGIC: &[u8] = b"\x7fPLC";
+pub const FORMAT_VERSION: u32 = 6;
+
+// The operand of a call whose arguments are spread into one array, in
+// place of their number.
+const SPREAD: u32 = u32::MAX;
+
+// Deeper nesting of functions than the parser lets through is corruption.
+const MAX_NESTING: usize = parser::MAX_DEPTH;
+
+// Each level of nesting the parser lets through opens two scopes at most,
+// like a for-of loop does for its bindings and its body.
+const MAX_SCOPES: usize = 2 * parser::MAX_DEPTH;
+
+pub fn encode(source: &str, code: &Code) -> Vec<u8> {
+    let mut body = Vec::new();
+    write_str(&mut body, source);
+    write_code(&mut body, code);
+
+    let mut out = MAGIC.to_vec();
+    write_u32(&mut out, FORMAT_VERSION);
+    write_u32(&mut out, checksum(&body));
+    out.extend(body);
+    out
+}
+
+// Loads a program written by `encode` and the name of its source, rejecting
+// files of another format version and those that are damaged, whose
+// instructions could refer to constants, slots or offsets that do not exist,
+// or take values the stack does not have.
+pub fn decode(bytes: &[u8]) -> Result<(String, Code), String> {
+    if !bytes.starts_with(MAGIC) {
+        return Err("not a compiled program".to_owned());
+    }
+    let mut reader = Reader {
+        bytes,
+        at: MAGIC.len(),
+        depth: 0,
+    };
+    let version = reader.u32()?;
+    if version != FORMAT_VERSION {
+        return Err(format!("unsupported format version {}, expected {}",
+                           version,
+                           FORMAT_VERSION));
+    }
+    let sum = reader.u32()?;
+    if sum != checksum(&bytes[reader.at..]) {
+        return Err("checksum mismatch".to_owned());
+    }
+
+    let source = reader.string()?;
+    let code = reader.code(false)?;
+    if reader.at != bytes.len() {
+        return Err("trailing bytes after the program".to_owned());
+    }
+    Ok((source, code))
+}
+
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter()
+        .fold(0x811c_9dc5, |hash, &byte| (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193))
+}
+
+fn write_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_str(out: &mut Vec<u8>, value: &str) {
+    write_u32(out, value.len() as u32);
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn write_code(out: &mut Vec<u8>, code: &Code) {
+    write_u32(out, code.constants.len() as u32);
+    for constant in &code.constants {
+        match *constant {
+            Constant::Integer(value) => {
+                out.push(0);
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+            Constant::String(ref value) => {
+                out.push(1);
+                write_str(out, &value.value);
+            }
+            Constant::Function(ref function) => {
+                out.push(2);
+                write_function(out, function);
+            }
+            Constant::Class(ref template) => {
+                out.push(3);
+                write_str(out, &template.name);
+                write_u32(out, template.methods.len() as u32);
+                for (name, method) in &template.methods {
+                    write_str(out, name);
+                    write_function(out, method);
+                }
+            }
+        }
+    }
+
+    write_u32(out, code.slots);
+    write_u32(out, code.instructions.len() as u32);
+    for op in &code.instructions {
+        write_op(out, *op);
+    }
+    for position in &code.positions {
+        match *position {
+            Some(position) => {
+                out.push(1);
+                write_u32(out, position.line as u32);
+                write_u32(out, position.column as u32);
+            }
+            None => out.push(0),
+        }
+    }
+}
+
+fn write_function(out: &mut Vec<u8>, function: &Function) {
+    write_u32(out, function.parameters as u32);
+    write_u32(out, function.required as u32);
+    out.push(function.arrow as u8);
+    write_str(out, &function.text);
+    write_code(out, &function.code);
+}
+
+fn arguments(args: Args) -> u32 {
+    match args {
+        Args::Values(count) => count,
+        Args::Spread => SPREAD,
+    }
+}
+
+fn write_op(out: &mut Vec<u8>, op: Op) {
+    let (tag, operands): (u8, &[u32]) = match op {
+        Op::Constant(index) => (0, &[index]),
+        Op::True => (1, &[]),
+        Op::False => (2, &[]),
+        Op::Null => (3, &[]),
+        Op::Pop => (4, &[]),
+        Op::Dup => (5, &[]),
+        Op::Error(index) => (6, &[index]),
+        Op::GetVar(index) => (7, &[index]),
+        Op::SetVar(index) => (8, &[index]),
+        Op::DeclareVar(index) => (9, &[index]),
+        Op::DeclareLet(index) => (10, &[index]),
+        Op::DeclareConst(index) => (11, &[index]),
+        Op::UnpackArray { count, rest, pattern } => (12, &[count, rest as u32, pattern]),
+        Op::CheckObject => (13, &[]),
+        Op::Destructure(index) => (14, &[index]),
+        Op::GetProperty(index) => (15, &[index]),
+        Op::SetProperty(index) => (16, &[index]),
+        Op::Not => (17, &[]),
+        Op::Negate => (18, &[]),
+        Op::Add => (19, &[]),
+        Op::Sub => (20, &[]),
+        Op::Mul => (21, &[]),
+        Op::Div => (22, &[]),
+        Op::Pow => (23, &[]),
+        Op::Less => (24, &[]),
+        Op::Greater => (25, &[]),
+        Op::Equal => (26, &[]),
+        Op::NotEqual => (27, &[]),
+        Op::Jump(target) => (28, &[target]),
+        Op::JumpIfFalse(target) => (29, &[target]),
+        Op::Array(count) => (30, &[count]),
+        Op::Spread => (31, &[]),
+        Op::Concat(count) => (32, &[count]),
+        Op::Hash(count) => (33, &[count]),
+        Op::Closure(index) => (34, &[index]),
+        Op::NameFunction(index) => (35, &[index]),
+        Op::Call(args) => (36, &[arguments(args)]),
+        Op::CallMethod { name, args } => (37, &[name, arguments(args)]),
+        Op::CallSuperMethod { name, args } => (38, &[name, arguments(args)]),
+        Op::SuperCall(args) => (39, &[arguments(args)]),
+        Op::New(args) => (40, &[arguments(args)]),
+        Op::Return => (41, &[]),
+        Op::Throw => (42, &[]),
+        Op::EnterBlock => (43, &[]),
+        Op::ExitBlock => (44, &[]),
+        Op::IntoIter => (45, &[]),
+        Op::Next(target) => (46, &[target]),
+        Op::Class { template, superclass } => (47, &[template, superclass as u32]),
+        Op::Try { handler, finalizer, end } => (48, &[handler, finalizer, end]),
+        Op::NextArg(target) => (49, &[target]),
+        Op::RestArgs => (50, &[]),
+        Op::Enter => (51, &[]),
+        Op::GetLocal { name, depth, slot } => (52, &[name, depth, slot]),
+        Op::SetLocal { name, depth, slot } => (53, &[name, depth, slot]),
+        Op::DeclareLocal { depth, slot, constant } => (54, &[depth, slot, constant as u32]),
+        Op::TailCall(args) => (55, &[arguments(args)]),
+        Op::TailCallMethod { name, args } => (56, &[name, arguments(args)]),
+        Op::DeclareVarOnce(index) => (57, &[index]),
+        Op::DeclareLocalOnce { depth, slot } => (58, &[depth, slot]),
+    };
+    out.push(tag);
+    for operand in operands {
+        write_u32(out, *operand);
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() - self.at < count {
+            return Err("unexpected end of file".to_owned());
+        }
+        let bytes = &self.bytes[self.at..self.at + count];
+        self.at += count;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, String> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            byte => Err(format!("invalid flag {}", byte)),
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn i64(&mut self) -> Result<i64, String> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(i64::from_le_bytes(bytes))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        match str::from_utf8(self.take(len)?) {
+            Ok(value) => Ok(value.to_owned()),
+            Err(_) => Err("invalid UTF-8 in a string".to_owned()),
+        }
+    }
+
+    // A code unit, the body of a function when `function` is set.
+    fn code(&mut self, function: bool) -> Result<Code, String> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING {
+            return Err("functions nested too deeply".to_owned());
+        }
+
+        let mut code = Code::default();
+        for _ in 0..self.u32()? {
+            let constant = match self.u8()? {
+                0 => Constant::Integer(self.i64()?),
+                1 => Constant::String(object::Str::constant(&self.string()?)),
+                2 => Constant::Function(self.function()?),
+                3 => {
+                    let name = self.string()?;
+                    let mut methods = Vec::new();
+                    for _ in 0..self.u32()? {
+                        methods.push((self.string()?, self.function()?));
+                    }
+                    Constant::Class(Rc::new(ClassTemplate { name, methods }))
+                }
+                tag => return Err(format!("invalid constant tag {}", tag)),
+            };
+            code.constants.push(constant);
+        }
+
+        // Every slot is declared by an instruction of its own.
+        code.slots = self.u32()?;
+        if code.slots as usize > self.bytes.len() {
+            return Err(format!("{} slots is more than the file declares", code.slots));
+        }
+        let count = self.u32()?;
+        for at in 0..count {
+            let op = self.op()?;
+            check_op(op, at, &code, count)?;
+            code.instructions.push(op);
+        }
+        for _ in 0..count {
+            let position = if self.bool()? {
+                Some(Position {
+                         line: self.u32()? as usize,
+                         column: self.u32()? as usize,
+                     })
+            } else {
+                None
+            };
+            code.positions.push(position);
+        }
+        Verifier::new(&code, function)?.run()?;
+
+        self.depth -= 1;
+        Ok(code)
+    }
+
+    fn function(&mut self) -> Result<Rc<Function>, String> {
+        let parameters = self.u32()? as usize;
+        let required = self.u32()? as usize;
+        if required > parameters {
+            return Err("more required arguments than parameters".to_owned());
+        }
+        Ok(Rc::new(Function {
+                       parameters,
+                       required,
+                       arrow: self.bool()?,
+                       text: self.string()?,
+                       code: self.code(true)?,
+                   }))
+    }
+
+    fn args(&mut self) -> Result<Args, String> {
+        match self.u32()? {
+            SPREAD => Ok(Args::Spread),
+            count => Ok(Args::Values(count)),
+        }
+    }
+
+    fn op(&mut self) -> Result<Op, String> {
+        let op = match self.u8()? {
+            0 => Op::Constant(self.u32()?),
+            1 => Op::True,
+            2 => Op::False,
+            3 => Op::Null,
+            4 => Op::Pop,
+            5 => Op::Dup,
+            6 => Op::Error(self.u32()?),
+            7 => Op::GetVar(self.u32()?),
+            8 => Op::SetVar(self.u32()?),
+            9 => Op::DeclareVar(self.u32()?),
+            10 => Op::DeclareLet(self.u32()?),
+            11 => Op::DeclareConst(self.u32()?),
+            12 => {
+                Op::UnpackArray {
+                    count: self.u32()?,
+                    rest: self.u32()? != 0,
+                    pattern: self.u32()?,
+                }
+            }
+            13 => Op::CheckObject,
+            14 => Op::Destructure(self.u32()?),
+            15 => Op::GetProperty(self.u32()?),
+            16 => Op::SetProperty(self.u32()?),
+            17 => Op::Not,
+            18 => Op::Negate,
+            19 => Op::Add,
+            20 => Op::Sub,
+            21 => Op::Mul,
+            22 => Op::Div,
+            23 => Op::Pow,
+            24 => Op::Less,
+            25 => Op::Greater,
+            26 => Op::Equal,
+            27 => Op::NotEqual,
+            28 => Op::Jump(self.u32()?),
+            29 => Op::JumpIfFalse(self.u32()?),
+            30 => Op::Array(self.u32()?),
+            31 => Op::Spread,
+            32 => Op::Concat(self.u32()?),
+            33 => Op::Hash(self.u32()?),
+            34 => Op::Closure(self.u32()?),
+            35 => Op::NameFunction(self.u32()?),
+            36 => Op::Call(self.args()?),
+            37 => {
+                Op::CallMethod {
+                    name: self.u32()?,
+                    args: self.args()?,
+                }
+            }
+            38 => {
+                Op::CallSuperMethod {
+                    name: self.u32()?,
+                    args: self.args()?,
+                }
+            }
+            39 => Op::SuperCall(self.args()?),
+            40 => Op::New(self.args()?),
+            41 => Op::Return,
+            42 => Op::Throw,
+            43 => Op::EnterBlock,
+            44 => Op::ExitBlock,
+            45 => Op::IntoIter,
+            46 => Op::Next(self.u32()?),
+            47 => {
+                Op::Class {
+                    template: self.u32()?,
+                    superclass: self.u32()? != 0,
+                }
+            }
+            48 => {
+                Op::Try {
+                    handler: self.u32()?,
+                    finalizer: self.u32()?,
+                    end: self.u32()?,
+                }
+            }
+            49 => Op::NextArg(self.u32()?),
+            50 => Op::RestArgs,
+            51 => Op::Enter,
+            52 => {
+                Op::GetLocal {
+                    name: self.u32()?,
+                    depth: self.u32()?,
+                    slot: self.u32()?,
+                }
+            }
+            53 => {
+                Op::SetLocal {
+                    name: self.u32()?,
+                    depth: self.u32()?,
+                    slot: self.u32()?,
+                }
+            }
+            54 => {
+                Op::DeclareLocal {
+                    depth: self.u32()?,
+                    slot: self.u32()?,
+                    constant: self.u32()? != 0,
+                }
+            }
+            55 => Op::TailCall(self.args()?),
+            56 => {
+                Op::TailCallMethod {
+                    name: self.u32()?,
+                    args: self.args()?,
+                }
+            }
+            57 => Op::DeclareVarOnce(self.u32()?),
+            58 => {
+                Op::DeclareLocalOnce {
+                    depth: self.u32()?,
+                    slot: self.u32()?,
+                }
+            }
+            tag => return Err(format!("invalid opcode {}", tag)),
+        };
+        Ok(op)
+    }
+}
+
+// Checks that the constants an instruction refers to are of the kind it
+// expects, that its slots and scopes exist and that its jumps land inside
+// the code.
+fn check_op(op: Op, at: u32, code: &Code, len: u32) -> Result<(), String> {
+    let constants = &code.constants;
+    let is_string = |index: u32| match constants.get(index as usize) {
+        Some(&Constant::String(_)) => Ok(()),
+        _ => Err(format!("constant {} is not a string", index)),
+    };
+    let is_target = |target: u32| if target <= len {
+        Ok(())
+    } else {
+        Err(format!("jump to {} out of the code", target))
+    };
+
+    match op {
+        Op::Constant(index) => {
+            match constants.get(index as usize) {
+                Some(&Constant::Integer(_)) | Some(&Constant::String(_)) => Ok(()),
+                _ => Err(format!("constant {} is not a value", index)),
+            }
+        }
+        Op::Error(index) |
+        Op::GetVar(index) |
+        Op::SetVar(index) |
+        Op::DeclareVar(index) |
+        Op::DeclareVarOnce(index) |
+        Op::DeclareLet(index) |
+        Op::DeclareConst(index) |
+        Op::UnpackArray { pattern: index, .. } |
+        Op::Destructure(index) |
+        Op::GetProperty(index) |
+        Op::SetProperty(index) |
+        Op::NameFunction(index) |
+        Op::CallMethod { name: index, .. } |
+        Op::CallSuperMethod { name: index, .. } |
+        Op::TailCallMethod { name: index, .. } => is_string(index),
+        Op::GetLocal { name, depth, slot } | Op::SetLocal { name, depth, slot } => {
+            is_string(name)?;
+            check_slot(depth, slot, code)
+        }
+        Op::DeclareLocal { depth, slot, .. } | Op::DeclareLocalOnce { depth, slot } => {
+            check_slot(depth, slot, code)
+        }
+        Op::Closure(index) => {
+            match constants.get(index as usize) {
+                Some(&Constant::Function(_)) => Ok(()),
+                _ => Err(format!("constant {} is not a function", index)),
+            }
+        }
+        Op::Class { template, .. } => {
+            match constants.get(template as usize) {
+                Some(&Constant::Class(_)) => Ok(()),
+                _ => Err(format!("constant {} is not a class", template)),
+            }
+        }
+        Op::Jump(target) | Op::JumpIfFalse(target) | Op::Next(target) | Op::NextArg(target) => {
+            is_target(target)
+        }
+        Op::Try { handler, finalizer, end } => {
+            if at < handler && handler <= finalizer && finalizer <= end {
+                is_target(end)
+            } else {
+                Err("try blocks out of order".to_owned())
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_slot(depth: u32, slot: u32, code: &Code) -> Result<(), String> {
+    if depth as usize >= MAX_SCOPES {
+        Err(format!("scope {} out is deeper than scopes nest", depth))
+    } else if slot >= code.slots {
+        Err(format!("slot {} out of the {} of the code", slot, code.slots))
+    } else {
+        Ok(())
+    }
+}
+
+// The values an instruction takes from the top of the stack and those it
+// pushes once it is done, calls pushing what the function returns and
+// `Next` what it does for an item.
+fn stack_effect(op: Op) -> (usize, usize) {
+    let args = |args: Args| match args {
+        Args::Values(count) => count as usize,
+        Args::Spread => 1,
+    };
+    match op {
+        Op::Constant(_) | Op::True | Op::False | Op::Null | Op::GetVar(_) |
+        Op::GetLocal { .. } | Op::Closure(_) | Op::RestArgs => (0, 1),
+        Op::Pop | Op::DeclareVar(_) | Op::DeclareLet(_) | Op::DeclareConst(_) |
+        Op::DeclareLocal { .. } | Op::JumpIfFalse(_) | Op::Return | Op::Throw => (1, 0),
+        Op::SetVar(_) | Op::SetLocal { .. } | Op::CheckObject | Op::Destructure(_) |
+        Op::GetProperty(_) | Op::Not | Op::Negate | Op::Spread | Op::NameFunction(_) => (1, 1),
+        Op::Dup | Op::IntoIter => (1, 2),
+        Op::SetProperty(_) | Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Pow | Op::Less |
+        Op::Greater | Op::Equal | Op::NotEqual => (2, 1),
+        Op::UnpackArray { count, rest, .. } => (1, count as usize + rest as usize),
+        Op::Array(count) | Op::Concat(count) => (count as usize, 1),
+        Op::Hash(count) => ((count as usize).saturating_mul(2), 1),
+        Op::Call(arguments) |
+        Op::CallMethod { args: arguments, .. } |
+        Op::CallSuperMethod { args: arguments, .. } |
+        Op::TailCall(arguments) |
+        Op::TailCallMethod { args: arguments, .. } |
+        Op::New(arguments) => (args(arguments) + 1, 1),
+        Op::SuperCall(arguments) => (args(arguments), 1),
+        Op::Next(_) => (2, 3),
+        Op::Class { superclass, .. } => (superclass as usize, 1),
+        Op::Error(_) | Op::Jump(_) | Op::EnterBlock | Op::ExitBlock | Op::Try { .. } |
+        Op::NextArg(_) | Op::Enter | Op::DeclareVarOnce(_) | Op::DeclareLocalOnce { .. } => (0, 0),
+    }
+}
+
+// The height of the stack of a frame and the blocks it is in when it runs
+// an instruction.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct State {
+    height: usize,
+    blocks: usize,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct TryRange {
+    at: usize,
+    handler: usize,
+    finalizer: usize,
+    end: usize,
+}
+
+impl TryRange {
+    // Where the block the instruction at `ip` is in ends.
+    fn boundary(&self, ip: usize) -> usize {
+        if ip < self.handler {
+            self.handler
+        } else if ip < self.finalizer {
+            self.finalizer
+        } else {
+            self.end
+        }
+    }
+}
+
+// Follows the state of the frame along every path through a code unit,
+// rejecting instructions that take more values than the stack has, leave
+// blocks they are not in or are reached in different states, and try
+// statements that do not nest. This is what bounds the counts of the
+// instructions taking values, which the vm trusts.
+struct Verifier<'a> {
+    code: &'a Code,
+    function: bool,
+    tries: Vec<TryRange>,
+    // The innermost try statement around each instruction, and the one
+    // around each try statement.
+    inner: Vec<Option<usize>>,
+    outer: Vec<Option<usize>>,
+    states: Vec<Option<State>>,
+    work: Vec<usize>,
+}
+
+impl<'a> Verifier<'a> {
+    fn new(code: &'a Code, function: bool) -> Result<Verifier<'a>, String> {
+        let len = code.instructions.len();
+        let mut verifier = Verifier {
+            code,
+            function,
+            tries: Vec::new(),
+            inner: Vec::with_capacity(len),
+            outer: Vec::new(),
+            states: vec![None; len + 1],
+            work: Vec::new(),
+        };
+        let mut open: Vec<usize> = Vec::new();
+        for (at, op) in code.instructions.iter().enumerate() {
+            while open.last().is_some_and(|&index| verifier.tries[index].end <= at) {
+                open.pop();
+            }
+            if let Op::Try { handler, finalizer, end } = *op {
+                let range = TryRange {
+                    at,
+                    handler: handler as usize,
+                    finalizer: finalizer as usize,
+                    end: end as usize,
+                };
+                if let Some(&index) = open.last() {
+                    if range.end > verifier.tries[index].boundary(at) {
+                        return Err(format!("try statement at {} is not nested", at));
+                    }
+                }
+                if open.len() == parser::MAX_DEPTH {
+                    return Err("try statements nested too deeply".to_owned());
+                }
+                verifier.outer.push(open.last().cloned());
+                open.push(verifier.tries.len());
+                verifier.tries.push(range);
+            }
+            verifier.inner.push(open.last().cloned());
+        }
+        Ok(verifier)
+    }
+
+    fn run(mut self) -> Result<(), String> {
+        let len = self.code.instructions.len();
+        self.enter(0, State { height: 0, blocks: 0 })?;
+        while let Some(ip) = self.work.pop() {
+            let state = match self.states[ip] {
+                Some(state) => state,
+                None => continue,
+            };
+            if ip == len {
+                if state.height == 0 {
+                    return Err("the code ends without a value".to_owned());
+                }
+                continue;
+            }
+            let op = self.code.instructions[ip];
+            let (takes, pushes) = stack_effect(op);
+            let height = match state.height.checked_sub(takes) {
+                Some(height) => height,
+                None => return Err(format!("instruction {} takes more values than there are", ip)),
+            };
+            let after = match height.checked_add(pushes) {
+                Some(height) => State { height, ..state },
+                None => return Err(format!("instruction {} overflows the stack", ip)),
+            };
+            match op {
+                Op::Return | Op::Throw | Op::Error(_) | Op::TailCall(_) => {}
+                Op::Jump(target) => self.reach(ip, target as usize, after)?,
+                Op::JumpIfFalse(target) => {
+                    self.reach(ip, target as usize, after)?;
+                    self.reach(ip, ip + 1, after)?;
+                }
+                Op::Next(done) => {
+                    self.reach(ip, done as usize, State { height, ..state })?;
+                    self.reach(ip, ip + 1, after)?;
+                }
+                Op::NextArg(target) => {
+                    self.reach(ip, target as usize, State { height: height + 1, ..state })?;
+                    self.reach(ip, ip + 1, after)?;
+                }
+                Op::EnterBlock => {
+                    if state.blocks + 1 >= MAX_SCOPES {
+                        return Err("blocks nested too deeply".to_owned());
+                    }
+                    self.reach(ip, ip + 1, State { blocks: state.blocks + 1, ..state })?;
+                }
+                Op::ExitBlock => {
+                    match state.blocks.checked_sub(1) {
+                        Some(blocks) => self.reach(ip, ip + 1, State { blocks, ..state })?,
+                        None => return Err(format!("instruction {} leaves no block", ip)),
+                    }
+                }
+                Op::DeclareLocal { depth, .. } | Op::DeclareLocalOnce { depth, .. } => {
+                    // A function declares in its own scope too, a program
+                    // only in its blocks.
+                    if depth as usize >= state.blocks + self.function as usize {
+                        return Err(format!("instruction {} declares out of the code", ip));
+                    }
+                    self.reach(ip, ip + 1, after)?;
+                }
+                Op::Try { handler, finalizer, end } => {
+                    // The vm enters a catch block with the error and a
+                    // finally block as the try statement started.
+                    if handler < finalizer {
+                        self.enter(handler as usize, State { height: height + 1, ..state })?;
+                    }
+                    if finalizer < end {
+                        self.enter(finalizer as usize, state)?;
+                    }
+                    self.reach(ip, ip + 1, after)?;
+                }
+                _ => self.reach(ip, ip + 1, after)?,
+            }
+        }
+        Ok(())
+    }
+
+    // Goes from `from` to `to`, completing the blocks of try statements that
+    // end there like the vm does: their value waits for the finally block,
+    // or is the value of the statement.
+    fn reach(&mut self, mut from: usize, mut to: usize, mut state: State) -> Result<(), String> {
+        let mut current = self.inner[from];
+        while let Some(index) = current {
+            let statement = self.tries[index];
+            if to != statement.boundary(from) {
+                break;
+            }
+            let start = match self.states[statement.at] {
+                Some(start) => start,
+                None => return Err(format!("try statement at {} is skipped", statement.at)),
+            };
+            if state.height != start.height + 1 {
+                return Err(format!("block of the try statement at {} leaves no value",
+                                   statement.at));
+            }
+            if to <= statement.finalizer && statement.finalizer < statement.end {
+                return self.enter(statement.finalizer, start);
+            }
+            to = statement.end;
+            state = State {
+                height: start.height + 1,
+                ..start
+            };
+            from = statement.at;
+            current = self.outer[index];
+        }
+        // Only the instructions of a block of a try statement jump into it.
+        let mut current = self.inner.get(to).cloned().unwrap_or(None);
+        while let Some(index) = current {
+            let statement = self.tries[index];
+            if statement.at < to &&
+               (from < statement.at || statement.end <= from ||
+                statement.boundary(from) != statement.boundary(to)) {
+                return Err(format!("jump from {} into the try statement at {}",
+                                   from,
+                                   statement.at));
+            }
+            current = self.outer[index];
+        }
+        self.enter(to, state)
+    }
+
+    fn enter(&mut self, ip: usize, state: State) -> Result<(), String> {
+        match self.states[ip] {
+            Some(seen) if seen != state => {
+                Err(format!("instruction {} is reached with different stack heights", ip))
+            }
+            Some(_) => Ok(()),
+            None => {
+                self.states[ip] = Some(state);
+                self.work.push(ip);
+                Ok(())
+            }
+        }
+    }
+}
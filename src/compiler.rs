@@ -0,0 +1,769 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use arena::Symbol;
+use ast::*;
+use evaluator::required_arguments;
+use object::{self, render_function};
+use token::{Position, TokenType};
+use types::{self, Program};
+
+// The instructions of the vm. They work on an operand stack, taking their
+// operands from the top of it and pushing their result, and refer to names,
+// literals and nested functions by their index in the constant pool of the
+// code they are in.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Op {
+    Constant(u32),
+    True,
+    False,
+    Null,
+    Pop,
+    Dup,
+    // Raises an error with the message at the given constant.
+    Error(u32),
+
+    // Variables by name, for globals.
+    GetVar(u32),
+    SetVar(u32),
+    DeclareVar(u32),
+    DeclareLet(u32),
+    DeclareConst(u32),
+    // Variables in the slot the resolver gave them, with their name for
+    // the errors.
+    GetLocal { name: u32, depth: u32, slot: u32 },
+    SetLocal { name: u32, depth: u32, slot: u32 },
+    DeclareLocal { depth: u32, slot: u32, constant: bool },
+    // Declares a `var` without a value as null, unless it already is.
+    DeclareVarOnce(u32),
+    DeclareLocalOnce { depth: u32, slot: u32 },
+    // Replaces an array with `count` of its elements, the first on top, and
+    // the array of the others below them when the pattern has a rest.
+    UnpackArray { count: u32, rest: bool, pattern: u32 },
+    // Fails unless the top of the stack is an object to destructure.
+    CheckObject,
+    // Replaces an object with the property it must have.
+    Destructure(u32),
+    GetProperty(u32),
+    SetProperty(u32),
+
+    Not,
+    Negate,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Less,
+    Greater,
+    Equal,
+    NotEqual,
+
+    Jump(u32),
+    JumpIfFalse(u32),
+
+    Array(u32),
+    // Fails unless the top of the stack is an array to spread.
+    Spread,
+    Concat(u32),
+    Hash(u32),
+    Closure(u32),
+    // Names the function on top of the stack after a variable, unless it
+    // has a name already.
+    NameFunction(u32),
+
+    // Calls take their arguments from the top of the stack, with the callee
+    // or the object of the method below them.
+    Call(Args),
+    CallMethod { name: u32, args: Args },
+    CallSuperMethod { name: u32, args: Args },
+    // Calls a function returns the result of, in place of the call of the
+    // function that made them.
+    TailCall(Args),
+    TailCallMethod { name: u32, args: Args },
+    SuperCall(Args),
+    New(Args),
+    Return,
+    Throw,
+
+    EnterBlock,
+    ExitBlock,
+    // Replaces an iterable with the array of its items and the index of
+    // the next one.
+    IntoIter,
+    // Pushes the next item, or drops the iterator and jumps when done.
+    Next(u32),
+    // Pushes the class made from a template, extending the class on top of
+    // the stack when it has a superclass.
+    Class { template: u32, superclass: bool },
+    // Runs the try block up to `handler`, the catch block up to `finalizer`
+    // and the finally block up to `end`, either block being empty when the
+    // statement has none.
+    Try { handler: u32, finalizer: u32, end: u32 },
+
+    // Pushes the next argument of the call and jumps, or falls through to
+    // the code of the default value when there is none left.
+    NextArg(u32),
+    RestArgs,
+    // Enters the call once its parameters are bound.
+    Enter,
+}
+
+// The arguments of a call: that many values, or one array of them when
+// some are spread.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Args {
+    Values(u32),
+    Spread,
+}
+
+#[derive(Debug)]
+pub enum Constant {
+    Integer(i64),
+    String(object::Str),
+    Function(Rc<Function>),
+    Class(Rc<ClassTemplate>),
+}
+
+// A unit of bytecode, a program or the body of a function, with the
+// constants it refers to and the position in the source each instruction
+// comes from. Errors raised by an instruction without a position are
+// traced by the caller, like those of binding arguments.
+#[derive(Debug, Default)]
+pub struct Code {
+    pub instructions: Vec<Op>,
+    pub positions: Vec<Option<Position>>,
+    pub constants: Vec<Constant>,
+    // One more than the highest slot its instructions use, which the
+    // scopes it runs in have room for.
+    pub slots: u32,
+}
+
+#[derive(Debug)]
+pub struct Function {
+    pub parameters: usize,
+    pub required: usize,
+    pub arrow: bool,
+    // How the function prints.
+    pub text: String,
+    pub code: Code,
+}
+
+#[derive(Debug)]
+pub struct ClassTemplate {
+    pub name: String,
+    pub methods: Vec<(String, Rc<Function>)>,
+}
+
+pub fn compile(program: &Program) -> Code {
+    let mut compiler = Compiler::new(program, vec![true]);
+    compiler.compile_statements(&program.statements, None);
+    compiler.code
+}
+
+struct Compiler<'a> {
+    program: &'a Program,
+    code: Code,
+    // Indexes of the strings already in the constant pool.
+    strings: HashMap<String, u32>,
+    // The scopes the resolver counted around the code, the innermost last,
+    // and whether each has an environment at runtime. Blocks declaring no
+    // names of their own get none.
+    scopes: Vec<bool>,
+}
+
+// Statements that leave a value, the last of which is the value of the
+// block or program they are in.
+fn has_value(stmt: &Statement) -> bool {
+    match *stmt {
+        Statement::EXPR_STMT(ref exp_stmt) => exp_stmt.expression.is_some(),
+        Statement::BLOCK_STMT(_) | Statement::FOR_OF(_) | Statement::TRY(_) => true,
+        Statement::VAR(_) | Statement::RETURN(_) | Statement::CLASS(_) |
+        Statement::THROW(_) => false,
+    }
+}
+
+// Whether a block declares names of its own with `let`, `const` or a class.
+fn declares_names(statements: &[StmtId], ast: &Ast) -> bool {
+    statements.iter().any(|&stmt| match ast[stmt] {
+                              Statement::VAR(ref var_stmt) => var_stmt.kind != TokenType::VAR,
+                              Statement::CLASS(_) => true,
+                              _ => false,
+                          })
+}
+
+fn is_super(exp: ExprId, ast: &Ast) -> bool {
+    match ast[exp] {
+        Expression::IDENT(ident) => ast.name(ident) == "super",
+        _ => false,
+    }
+}
+
+impl<'a> Compiler<'a> {
+    fn new(program: &'a Program, scopes: Vec<bool>) -> Compiler<'a> {
+        Compiler {
+            program,
+            code: Code::default(),
+            strings: HashMap::new(),
+            scopes,
+        }
+    }
+
+    fn emit(&mut self, op: Op, position: Option<Position>) -> usize {
+        match op {
+            Op::GetLocal { slot, .. } |
+            Op::SetLocal { slot, .. } |
+            Op::DeclareLocal { slot, .. } |
+            Op::DeclareLocalOnce { slot, .. } => {
+                self.code.slots = self.code.slots.max(slot + 1);
+            }
+            _ => {}
+        }
+        self.code.instructions.push(op);
+        self.code.positions.push(position);
+        self.code.instructions.len() - 1
+    }
+
+    fn here(&self) -> u32 {
+        self.code.instructions.len() as u32
+    }
+
+    // Points the jump at `at` to the next instruction.
+    fn patch(&mut self, at: usize) {
+        let target = self.here();
+        self.code.instructions[at] = match self.code.instructions[at] {
+            Op::Jump(_) => Op::Jump(target),
+            Op::JumpIfFalse(_) => Op::JumpIfFalse(target),
+            Op::NextArg(_) => Op::NextArg(target),
+            Op::Next(_) => Op::Next(target),
+            op => op,
+        };
+    }
+
+    fn constant(&mut self, constant: Constant) -> u32 {
+        self.code.constants.push(constant);
+        self.code.constants.len() as u32 - 1
+    }
+
+    fn string(&mut self, value: &str) -> u32 {
+        if let Some(&index) = self.strings.get(value) {
+            return index;
+        }
+        let index = self.constant(Constant::String(object::Str::constant(value)));
+        self.strings.insert(value.to_owned(), index);
+        index
+    }
+
+    fn symbol(&mut self, symbol: Symbol) -> u32 {
+        let program = self.program;
+        self.string(program.ast.symbols.name(symbol))
+    }
+
+    // Enters a scope of the resolver, which gets an environment when it
+    // declares names.
+    fn enter_scope(&mut self, scoped: bool, position: Option<Position>) {
+        if scoped {
+            self.emit(Op::EnterBlock, position);
+        }
+        self.scopes.push(scoped);
+    }
+
+    fn exit_scope(&mut self, position: Option<Position>) {
+        if self.scopes.pop() == Some(true) {
+            self.emit(Op::ExitBlock, position);
+        }
+    }
+
+    // The slot of `ident`, its depth counting the environments between
+    // rather than the scopes of the resolver.
+    fn slot(&self, ident: IdentId) -> Option<(u32, u32)> {
+        let slot = self.program.slots.get(ident)?;
+        let scopes = &self.scopes[self.scopes.len() - slot.depth..];
+        let depth = scopes.iter().filter(|&&scoped| scoped).count();
+        Some((depth as u32, slot.index as u32))
+    }
+
+    fn statement_position(&self, stmt: StmtId) -> Option<Position> {
+        Some(self.program.ast.statements.position(stmt))
+    }
+
+    // Leaves the value of the last statement that has one, or null.
+    fn compile_statements(&mut self, statements: &[StmtId], position: Option<Position>) {
+        let ast = &self.program.ast;
+        let last = statements.iter().rposition(|&stmt| has_value(&ast[stmt]));
+        for (i, &stmt) in statements.iter().enumerate() {
+            self.compile_statement(stmt);
+            if has_value(&ast[stmt]) && Some(i) != last {
+                let position = self.statement_position(stmt);
+                self.emit(Op::Pop, position);
+            }
+        }
+        if last.is_none() {
+            self.emit(Op::Null, position);
+        }
+    }
+
+    // Compiles a statement for its value, null when it has none.
+    fn compile_value(&mut self, stmt: StmtId) {
+        self.compile_statement(stmt);
+        if !has_value(&self.program.ast[stmt]) {
+            let position = self.statement_position(stmt);
+            self.emit(Op::Null, position);
+        }
+    }
+
+    fn compile_statement(&mut self, stmt: StmtId) {
+        let program = self.program;
+        let position = self.statement_position(stmt);
+        match program.ast[stmt] {
+            Statement::VAR(ref var_stmt) => {
+                match (var_stmt.value, &program.ast[var_stmt.name]) {
+                    // Redeclaring a `var` without a value keeps the one it has.
+                    (None, &Pattern::IDENT(ident)) if var_stmt.kind == TokenType::VAR => {
+                        self.declare_once(ident, position);
+                    }
+                    (value, pattern) => {
+                        match value {
+                            Some(value) => self.compile_expression(value),
+                            None => {
+                                self.emit(Op::Null, position);
+                            }
+                        }
+                        if let Pattern::IDENT(ident) = *pattern {
+                            let name = self.string(program.ast.name(ident));
+                            self.emit(Op::NameFunction(name), position);
+                        }
+                        self.compile_pattern(var_stmt.name, var_stmt.kind, position);
+                    }
+                }
+            }
+            Statement::EXPR_STMT(ref exp_stmt) => {
+                if let Some(exp) = exp_stmt.expression {
+                    self.compile_expression(exp);
+                }
+            }
+            Statement::BLOCK_STMT(ref block) => {
+                self.enter_scope(declares_names(&block.statements, &program.ast), position);
+                self.compile_statements(&block.statements, position);
+                self.exit_scope(position);
+            }
+            Statement::RETURN(ref rtn) => {
+                match rtn.return_value {
+                    Some(value) => self.compile_expression(value),
+                    None => {
+                        self.emit(Op::Null, position);
+                    }
+                }
+                self.emit(Op::Return, position);
+            }
+            Statement::FOR_OF(ref for_stmt) => self.compile_for_of(for_stmt, position),
+            Statement::CLASS(ref class_stmt) => self.compile_class(class_stmt, position),
+            Statement::THROW(ref throw) => {
+                self.compile_expression(throw.value);
+                self.emit(Op::Throw, position);
+            }
+            Statement::TRY(ref try_stmt) => self.compile_try(try_stmt, position),
+        }
+    }
+
+    fn compile_for_of(&mut self, for_stmt: &types::ForOfStatement, position: Option<Position>) {
+        self.compile_expression(for_stmt.iterable);
+        self.emit(Op::IntoIter, position);
+        let start = self.here();
+        let next = self.emit(Op::Next(0), position);
+        // The body enters its scope again on each pass, so closures made in
+        // it keep the let/const bindings of their own iteration.
+        self.enter_scope(for_stmt.kind != TokenType::VAR, position);
+        self.compile_pattern(for_stmt.pattern, for_stmt.kind, position);
+        self.compile_statement(for_stmt.body);
+        if has_value(&self.program.ast[for_stmt.body]) {
+            self.emit(Op::Pop, position);
+        }
+        self.exit_scope(position);
+        self.emit(Op::Jump(start), position);
+        self.patch(next);
+        self.emit(Op::Null, position);
+    }
+
+    fn compile_class(&mut self, class_stmt: &types::ClassStatement, position: Option<Position>) {
+        let program = self.program;
+        if let Some(superclass) = class_stmt.superclass {
+            self.compile_expression(superclass);
+        }
+        // Methods close over the scope binding `super`.
+        self.scopes.push(true);
+        let methods = class_stmt.methods
+            .iter()
+            .map(|&(name, method)| {
+                     let method = compile_function(program, method, &self.scopes);
+                     (program.ast.symbols.name(name).to_owned(), method)
+                 })
+            .collect();
+        self.scopes.pop();
+        let template = self.constant(Constant::Class(Rc::new(ClassTemplate {
+                                                                 name: program.ast
+                                                                     .name(class_stmt.name)
+                                                                     .to_owned(),
+                                                                 methods,
+                                                             })));
+        self.emit(Op::Class {
+                      template,
+                      superclass: class_stmt.superclass.is_some(),
+                  },
+                  position);
+        self.declare(class_stmt.name, TokenType::LET, position);
+    }
+
+    fn compile_try(&mut self, try_stmt: &types::TryStatement, position: Option<Position>) {
+        let at = self.emit(Op::Try {
+                               handler: 0,
+                               finalizer: 0,
+                               end: 0,
+                           },
+                           position);
+        self.compile_value(try_stmt.block);
+
+        let handler = self.here();
+        if let Some(catch) = try_stmt.handler {
+            // The caught error is on top of the stack.
+            self.enter_scope(try_stmt.param.is_some(), position);
+            match try_stmt.param {
+                Some(param) => self.compile_pattern(param, TokenType::LET, position),
+                None => {
+                    self.emit(Op::Pop, position);
+                }
+            }
+            self.compile_value(catch);
+            self.exit_scope(position);
+        }
+
+        let finalizer = self.here();
+        if let Some(finally) = try_stmt.finalizer {
+            self.compile_value(finally);
+        }
+        let end = self.here();
+        self.code.instructions[at] = Op::Try {
+            handler,
+            finalizer,
+            end,
+        };
+    }
+
+    // Declares the variable on top of the stack with the `var`, `let` or
+    // `const` token kind.
+    fn declare(&mut self, ident: IdentId, kind: TokenType, position: Option<Position>) {
+        let program = self.program;
+        let op = match self.slot(ident) {
+            Some((depth, slot)) => {
+                Op::DeclareLocal {
+                    depth,
+                    slot,
+                    constant: kind == TokenType::CONST,
+                }
+            }
+            None => {
+                let name = self.string(program.ast.name(ident));
+                match kind {
+                    TokenType::LET => Op::DeclareLet(name),
+                    TokenType::CONST => Op::DeclareConst(name),
+                    _ => Op::DeclareVar(name),
+                }
+            }
+        };
+        self.emit(op, position);
+    }
+
+    // Declares a `var` without a value, leaving it as it is when declared
+    // before.
+    fn declare_once(&mut self, ident: IdentId, position: Option<Position>) {
+        let op = match self.slot(ident) {
+            Some((depth, slot)) => Op::DeclareLocalOnce { depth, slot },
+            None => Op::DeclareVarOnce(self.string(self.program.ast.name(ident))),
+        };
+        self.emit(op, position);
+    }
+
+    // Binds the value on top of the stack to `pattern`, as declared by the
+    // `var`, `let` or `const` token kind.
+    fn compile_pattern(&mut self, pattern: PatternId, kind: TokenType, position: Option<Position>) {
+        let program = self.program;
+        match program.ast[pattern] {
+            Pattern::IDENT(ident) => self.declare(ident, kind, position),
+            Pattern::ARRAY(ref arr) => {
+                let text = self.string(&arr.to_string(&program.ast));
+                self.emit(Op::UnpackArray {
+                              count: arr.elements.len() as u32,
+                              rest: arr.rest.is_some(),
+                              pattern: text,
+                          },
+                          position);
+                for &element in &arr.elements {
+                    self.compile_pattern(element, kind, position);
+                }
+                if let Some(rest) = arr.rest {
+                    self.compile_pattern(rest, kind, position);
+                }
+            }
+            Pattern::OBJECT(ref obj) => {
+                self.emit(Op::CheckObject, position);
+                for &(key, pattern) in &obj.properties {
+                    let key = self.symbol(key);
+                    self.emit(Op::Dup, position);
+                    self.emit(Op::Destructure(key), position);
+                    self.compile_pattern(pattern, kind, position);
+                }
+                self.emit(Op::Pop, position);
+            }
+        }
+    }
+
+    fn compile_expression(&mut self, exp: ExprId) {
+        let program = self.program;
+        let position = Some(program.ast.expressions.position(exp));
+        match program.ast[exp] {
+            Expression::IDENT(ident) => {
+                let name = self.string(program.ast.name(ident));
+                let op = match self.slot(ident) {
+                    Some((depth, slot)) => Op::GetLocal { name, depth, slot },
+                    None => Op::GetVar(name),
+                };
+                self.emit(op, position);
+            }
+            Expression::BOOL(ref b) => {
+                self.emit(if b.value { Op::True } else { Op::False }, position);
+            }
+            Expression::INTEGER(ref int) => {
+                let index = self.constant(Constant::Integer(int.value));
+                self.emit(Op::Constant(index), position);
+            }
+            Expression::STRING(ref string) => {
+                let index = self.string(&string.value);
+                self.emit(Op::Constant(index), position);
+            }
+            Expression::ARRAY(ref arr) => self.compile_elements(&arr.elements, position),
+            Expression::PREFIX(ref prefix) => {
+                self.compile_expression(prefix.right);
+                let op = match prefix.operator {
+                    "!" => Op::Not,
+                    "-" => Op::Negate,
+                    op => Op::Error(self.string(&format!("unknown operator: {}", op))),
+                };
+                self.emit(op, position);
+            }
+            Expression::INFIX(ref infix) => {
+                self.compile_expression(infix.left);
+                self.compile_expression(infix.right);
+                let op = match infix.operator {
+                    "+" => Op::Add,
+                    "-" => Op::Sub,
+                    "*" => Op::Mul,
+                    "/" => Op::Div,
+                    "^" => Op::Pow,
+                    "<" => Op::Less,
+                    ">" => Op::Greater,
+                    "==" => Op::Equal,
+                    "!=" => Op::NotEqual,
+                    op => Op::Error(self.string(&format!("unknown operator: {}", op))),
+                };
+                self.emit(op, position);
+            }
+            Expression::IF(ref if_exp) => {
+                self.compile_expression(if_exp.condition);
+                let jump_else = self.emit(Op::JumpIfFalse(0), position);
+                self.compile_value(if_exp.consequence);
+                let jump_end = self.emit(Op::Jump(0), position);
+                self.patch(jump_else);
+                match if_exp.alternative {
+                    Some(alt) => self.compile_value(alt),
+                    None => {
+                        self.emit(Op::Null, position);
+                    }
+                }
+                self.patch(jump_end);
+            }
+            Expression::TERNARY(ref ternary) => {
+                self.compile_expression(ternary.condition);
+                let jump_else = self.emit(Op::JumpIfFalse(0), position);
+                self.compile_expression(ternary.consequence);
+                let jump_end = self.emit(Op::Jump(0), position);
+                self.patch(jump_else);
+                self.compile_expression(ternary.alternative);
+                self.patch(jump_end);
+            }
+            Expression::FUNC(func) => {
+                let function = compile_function(program, func, &self.scopes);
+                let index = self.constant(Constant::Function(function));
+                self.emit(Op::Closure(index), position);
+            }
+            Expression::CALL(ref call) => {
+                self.compile_call(call, program.tail_calls.contains(exp), position)
+            }
+            Expression::ASSIGN(ref assign) => {
+                match program.ast[assign.target] {
+                    Expression::IDENT(ident) => {
+                        self.compile_expression(assign.value);
+                        let name = self.string(program.ast.name(ident));
+                        let op = match self.slot(ident) {
+                            Some((depth, slot)) => Op::SetLocal { name, depth, slot },
+                            None => Op::SetVar(name),
+                        };
+                        self.emit(op, position);
+                    }
+                    Expression::MEMBER(ref member) => {
+                        self.compile_expression(member.object);
+                        self.compile_expression(assign.value);
+                        let name = self.symbol(member.property);
+                        self.emit(Op::SetProperty(name), position);
+                    }
+                    ref target => {
+                        let msg = format!("invalid assignment target {}",
+                                          target.to_string(&program.ast));
+                        let msg = self.string(&msg);
+                        self.emit(Op::Error(msg), position);
+                    }
+                }
+            }
+            Expression::SPREAD(_) => {
+                let msg = self.string("spread syntax is only allowed in calls and array literals");
+                self.emit(Op::Error(msg), position);
+            }
+            Expression::HASH(ref hash) => {
+                for &(key, value) in &hash.pairs {
+                    let key = self.symbol(key);
+                    self.emit(Op::Constant(key), position);
+                    self.compile_expression(value);
+                }
+                self.emit(Op::Hash(hash.pairs.len() as u32), position);
+            }
+            Expression::MEMBER(ref member) => {
+                self.compile_expression(member.object);
+                let name = self.symbol(member.property);
+                self.emit(Op::GetProperty(name), position);
+            }
+            Expression::NEW(ref new) => {
+                self.compile_expression(new.class);
+                let args = self.compile_arguments(&new.arguments, position);
+                self.emit(Op::New(args), position);
+            }
+        }
+    }
+
+    fn compile_call(&mut self,
+                    call: &types::CallExpression,
+                    tail: bool,
+                    position: Option<Position>) {
+        let ast = &self.program.ast;
+        if is_super(call.function, ast) {
+            let args = self.compile_arguments(&call.arguments, position);
+            self.emit(Op::SuperCall(args), position);
+            return;
+        }
+        match ast[call.function] {
+            Expression::MEMBER(ref member) => {
+                self.compile_expression(member.object);
+                let args = self.compile_arguments(&call.arguments, position);
+                let name = self.symbol(member.property);
+                // Methods reached through `super` keep the current `this`.
+                if is_super(member.object, ast) {
+                    self.emit(Op::CallSuperMethod { name, args }, position);
+                } else if tail {
+                    self.emit(Op::TailCallMethod { name, args }, position);
+                } else {
+                    self.emit(Op::CallMethod { name, args }, position);
+                }
+            }
+            _ => {
+                self.compile_expression(call.function);
+                let args = self.compile_arguments(&call.arguments, position);
+                self.emit(if tail { Op::TailCall(args) } else { Op::Call(args) }, position);
+            }
+        }
+    }
+
+    // Leaves the arguments of a call on the stack, in an array when some
+    // are spread.
+    fn compile_arguments(&mut self, arguments: &[ExprId], position: Option<Position>) -> Args {
+        let ast = &self.program.ast;
+        if arguments.iter().any(|&arg| matches!(ast[arg], Expression::SPREAD(_))) {
+            self.compile_elements(arguments, position);
+            return Args::Spread;
+        }
+        for &arg in arguments {
+            self.compile_expression(arg);
+        }
+        Args::Values(arguments.len() as u32)
+    }
+
+    // Builds the array of the elements of an array literal or of the
+    // arguments of a call, spreading runs of plain elements between spread
+    // ones.
+    fn compile_elements(&mut self, elements: &[ExprId], position: Option<Position>) {
+        let program = self.program;
+        let mut parts = 0;
+        let mut plain = 0;
+        for &element in elements {
+            match program.ast[element] {
+                Expression::SPREAD(ref spread) => {
+                    if plain > 0 {
+                        self.emit(Op::Array(plain), position);
+                        parts += 1;
+                        plain = 0;
+                    }
+                    self.compile_expression(spread.value);
+                    self.emit(Op::Spread, position);
+                    parts += 1;
+                }
+                _ => {
+                    self.compile_expression(element);
+                    plain += 1;
+                }
+            }
+        }
+        if parts == 0 {
+            self.emit(Op::Array(plain), position);
+            return;
+        }
+        if plain > 0 {
+            self.emit(Op::Array(plain), position);
+            parts += 1;
+        }
+        self.emit(Op::Concat(parts), position);
+    }
+}
+
+// Functions bind their arguments before entering the call, the errors this
+// raises being traced at the call. The call has a scope of its own inside
+// the `scopes` around the function.
+fn compile_function(program: &Program, func: FuncId, scopes: &[bool]) -> Rc<Function> {
+    let func = &program.ast[func];
+    let mut scopes = scopes.to_vec();
+    scopes.push(true);
+    let mut compiler = Compiler::new(program, scopes);
+    for param in &func.parameters {
+        if param.rest {
+            compiler.emit(Op::RestArgs, None);
+        } else {
+            let next = compiler.emit(Op::NextArg(0), None);
+            match param.default {
+                Some(default) => compiler.compile_expression(default),
+                None => {
+                    compiler.emit(Op::Null, None);
+                }
+            }
+            compiler.patch(next);
+        }
+        compiler.compile_pattern(param.name, TokenType::VAR, None);
+    }
+    compiler.emit(Op::Enter, None);
+    compiler.compile_value(func.body);
+    let position = compiler.statement_position(func.body);
+    compiler.emit(Op::Return, position);
+
+    Rc::new(Function {
+                parameters: func.parameters.len(),
+                required: required_arguments(&func.parameters),
+                arrow: func.arrow,
+                text: render_function(&program.ast, func),
+                code: compiler.code,
+            })
+}
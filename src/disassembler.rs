@@ -0,0 +1,171 @@
+use std::fmt::Write;
+
+use compiler::{Args, Code, Constant, Op};
+
+// Lists the instructions of `code` one per line, with their offset, the
+// position in the source they come from, their operands and the constants
+// these refer to, followed by the listings of the functions and methods it
+// defines. These are labelled after the code they are in and the index of
+// their constant, `<script>/2` or `<script>/2/speak` for a method.
+pub fn disassemble(code: &Code) -> String {
+    let mut out = String::new();
+    disassemble_code(code, "<script>", &mut out);
+    out
+}
+
+fn disassemble_code(code: &Code, label: &str, out: &mut String) {
+    writeln!(out, "== {} ==", label).unwrap();
+    for (offset, op) in code.instructions.iter().enumerate() {
+        let position = match code.positions[offset] {
+            Some(pos) => pos.to_string(),
+            None => "-".to_owned(),
+        };
+        let (name, operands) = describe(*op, code, label);
+        let line = format!("{:04} {:>7}  {:<15} {}", offset, position, name, operands);
+        writeln!(out, "{}", line.trim_end()).unwrap();
+    }
+
+    for (index, constant) in code.constants.iter().enumerate() {
+        match *constant {
+            Constant::Function(ref function) => {
+                out.push('\n');
+                disassemble_code(&function.code, &format!("{}/{}", label, index), out);
+            }
+            Constant::Class(ref template) => {
+                for (name, method) in &template.methods {
+                    out.push('\n');
+                    let label = format!("{}/{}/{}", label, index, name);
+                    disassemble_code(&method.code, &label, out);
+                }
+            }
+            Constant::Integer(_) | Constant::String(_) => {}
+        }
+    }
+}
+
+// How a constant shows next to the instructions using it.
+fn constant(code: &Code, index: u32, label: &str) -> String {
+    let value = match code.constants[index as usize] {
+        Constant::Integer(value) => value.to_string(),
+        Constant::String(ref value) => format!("{:?}", value.value),
+        Constant::Function(ref function) => {
+            let kind = if function.arrow { "arrow" } else { "function" };
+            format!("{} {}/{} (arity {})", kind, label, index, function.parameters)
+        }
+        Constant::Class(ref template) => format!("class {}", template.name),
+    };
+    format!("{:<4} ; {}", index, value)
+}
+
+fn local(code: &Code, name: u32, depth: u32, slot: u32, label: &str) -> String {
+    format!("{} at depth {}, slot {}", constant(code, name, label), depth, slot)
+}
+
+// The number of arguments of a call, or `spread` when they are one array.
+fn arguments(args: Args) -> String {
+    match args {
+        Args::Values(count) => count.to_string(),
+        Args::Spread => "spread".to_owned(),
+    }
+}
+
+fn method(code: &Code, name: u32, args: Args, label: &str) -> String {
+    format!("{}, {}", arguments(args), constant(code, name, label))
+}
+
+fn target(offset: u32) -> String {
+    format!("-> {:04}", offset)
+}
+
+fn describe(op: Op, code: &Code, label: &str) -> (&'static str, String) {
+    match op {
+        Op::Constant(index) => ("CONSTANT", constant(code, index, label)),
+        Op::True => ("TRUE", String::new()),
+        Op::False => ("FALSE", String::new()),
+        Op::Null => ("NULL", String::new()),
+        Op::Pop => ("POP", String::new()),
+        Op::Dup => ("DUP", String::new()),
+        Op::Error(index) => ("ERROR", constant(code, index, label)),
+
+        Op::GetVar(index) => ("GET_VAR", constant(code, index, label)),
+        Op::SetVar(index) => ("SET_VAR", constant(code, index, label)),
+        Op::DeclareVar(index) => ("DECLARE_VAR", constant(code, index, label)),
+        Op::DeclareVarOnce(index) => ("DECLARE_VAR_ONCE", constant(code, index, label)),
+        Op::DeclareLet(index) => ("DECLARE_LET", constant(code, index, label)),
+        Op::DeclareConst(index) => ("DECLARE_CONST", constant(code, index, label)),
+        Op::GetLocal { name, depth, slot } => ("GET_LOCAL", local(code, name, depth, slot, label)),
+        Op::SetLocal { name, depth, slot } => ("SET_LOCAL", local(code, name, depth, slot, label)),
+        Op::DeclareLocal { depth, slot, constant } => {
+            let constant = if constant { ", constant" } else { "" };
+            ("DECLARE_LOCAL", format!("depth {}, slot {}{}", depth, slot, constant))
+        }
+        Op::DeclareLocalOnce { depth, slot } => {
+            ("DECLARE_LOCAL_ONCE", format!("depth {}, slot {}", depth, slot))
+        }
+        Op::UnpackArray { count, rest, pattern } => {
+            let rest = if rest { " + rest" } else { "" };
+            ("UNPACK_ARRAY",
+             format!("{}{}, {}", count, rest, constant(code, pattern, label)))
+        }
+        Op::CheckObject => ("CHECK_OBJECT", String::new()),
+        Op::Destructure(index) => ("DESTRUCTURE", constant(code, index, label)),
+        Op::GetProperty(index) => ("GET_PROPERTY", constant(code, index, label)),
+        Op::SetProperty(index) => ("SET_PROPERTY", constant(code, index, label)),
+
+        Op::Not => ("NOT", String::new()),
+        Op::Negate => ("NEGATE", String::new()),
+        Op::Add => ("ADD", String::new()),
+        Op::Sub => ("SUB", String::new()),
+        Op::Mul => ("MUL", String::new()),
+        Op::Div => ("DIV", String::new()),
+        Op::Pow => ("POW", String::new()),
+        Op::Less => ("LESS", String::new()),
+        Op::Greater => ("GREATER", String::new()),
+        Op::Equal => ("EQUAL", String::new()),
+        Op::NotEqual => ("NOT_EQUAL", String::new()),
+
+        Op::Jump(offset) => ("JUMP", target(offset)),
+        Op::JumpIfFalse(offset) => ("JUMP_IF_FALSE", target(offset)),
+
+        Op::Array(count) => ("ARRAY", count.to_string()),
+        Op::Spread => ("SPREAD", String::new()),
+        Op::Concat(count) => ("CONCAT", count.to_string()),
+        Op::Hash(count) => ("HASH", count.to_string()),
+        Op::Closure(index) => ("CLOSURE", constant(code, index, label)),
+        Op::NameFunction(index) => ("NAME_FUNCTION", constant(code, index, label)),
+
+        Op::Call(args) => ("CALL", arguments(args)),
+        Op::CallMethod { name, args } => ("CALL_METHOD", method(code, name, args, label)),
+        Op::CallSuperMethod { name, args } => {
+            ("CALL_SUPER_METHOD", method(code, name, args, label))
+        }
+        Op::TailCall(args) => ("TAIL_CALL", arguments(args)),
+        Op::TailCallMethod { name, args } => {
+            ("TAIL_CALL_METHOD", method(code, name, args, label))
+        }
+        Op::SuperCall(args) => ("SUPER_CALL", arguments(args)),
+        Op::New(args) => ("NEW", arguments(args)),
+        Op::Return => ("RETURN", String::new()),
+        Op::Throw => ("THROW", String::new()),
+
+        Op::EnterBlock => ("ENTER_BLOCK", String::new()),
+        Op::ExitBlock => ("EXIT_BLOCK", String::new()),
+        Op::IntoIter => ("INTO_ITER", String::new()),
+        Op::Next(offset) => ("NEXT", target(offset)),
+        Op::Class { template, superclass } => {
+            let extends = if superclass { " extends" } else { "" };
+            ("CLASS", format!("{}{}", constant(code, template, label), extends))
+        }
+        Op::Try { handler, finalizer, end } => {
+            ("TRY",
+             format!("catch {}, finally {}, end {}",
+                     target(handler),
+                     target(finalizer),
+                     target(end)))
+        }
+
+        Op::NextArg(offset) => ("NEXT_ARG", target(offset)),
+        Op::RestArgs => ("REST_ARGS", String::new()),
+        Op::Enter => ("ENTER", String::new()),
+    }
+}
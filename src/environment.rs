@@ -1,38 +1,229 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Debug, Formatter};
+use std::rc::Rc;
 
 use object::*;
+use runtime::Runtime;
 
-#[derive(Debug, Default, Clone, PartialEq)]
+pub type Env = Rc<RefCell<Environment>>;
+
+// `var` declarations are hoisted to the closest Function scope, `let` and
+// `const` live in the scope they are declared in.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum ScopeKind {
+    #[default]
+    Function,
+    Block,
+}
+
+// A variable the resolver gave a slot to.
+#[derive(Clone)]
+struct Local {
+    value: Object,
+    constant: bool,
+}
+
+// Globals, and the names the resolver leaves alone like `this`, are in
+// `store`, the other variables in `locals` by their slot, empty until
+// their declaration has run.
+#[derive(Default, Clone)]
 pub struct Environment {
     store: HashMap<String, Object>,
-    out: Option<Box<Environment>>,
+    constants: HashSet<String>,
+    locals: Vec<Option<Local>>,
+    // How many slots compiled code can declare in the scope, those of the
+    // code it runs. The resolver of the tree-walker sets no limit.
+    slots: Option<usize>,
+    kind: ScopeKind,
+    out: Option<Env>,
+    runtime: Rc<Runtime>,
 }
 
 impl Environment {
-    pub fn new() -> Environment {
-        Environment {
+    pub fn new_enclosed(out: &Env, kind: ScopeKind) -> Env {
+        Rc::new(RefCell::new(Environment {
             store: HashMap::new(),
-            out: None,
-        }
+            constants: HashSet::new(),
+            locals: Vec::new(),
+            slots: out.borrow().slots,
+            kind,
+            out: Some(out.clone()),
+            runtime: out.borrow().runtime(),
+        }))
+    }
+
+    // The scope for a function to close over. Only such scopes can be in a
+    // cycle, through the functions kept in them, which memory::collect
+    // looks for.
+    pub fn capture(env: &Env) -> Env {
+        env.borrow().runtime.track(env);
+        env.clone()
+    }
+
+    // Every scope enclosed in another shares the runtime of the outermost.
+    pub fn runtime(&self) -> Rc<Runtime> {
+        self.runtime.clone()
+    }
+
+    // The scope this one is enclosed in, none for the outermost.
+    pub fn outer(&self) -> Option<Env> {
+        self.out.clone()
     }
 
-    pub fn new_enclosed(mut self) -> Environment {
-        let env = Environment::new();
-        self.out = Some(Box::new(env));
-        *self.out.unwrap()
+    // The values of the variables of this scope, and the scope it is in.
+    pub fn values(&self) -> (Vec<Object>, Option<Env>) {
+        let locals = self.locals.iter().flatten().map(|local| local.value.clone());
+        (self.store.values().cloned().chain(locals).collect(), self.out.clone())
     }
 
-    pub fn get(&mut self, name: &str) -> Option<Object> {
+    // Drops the variables of the scope, and with them what they hold.
+    pub fn clear(&mut self) {
+        self.store.clear();
+        self.constants.clear();
+        self.locals.clear();
+        self.out = None;
+    }
+
+    pub fn get(&self, name: &str) -> Option<Object> {
         if let Some(obj) = self.store.get(name) {
             return Some(obj.clone());
-        } else if let Some(ref mut out_env) = self.out {
-            return out_env.get(name);
+        } else if let Some(ref out_env) = self.out {
+            return out_env.borrow().get(name);
         }
         None
     }
 
+    // Whether this scope itself binds `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.store.contains_key(name)
+    }
+
     pub fn set(&mut self, name: &str, obj: Object) -> Option<Object> {
-        let o = self.store.insert(name.to_owned(), obj);
-        return o;
+        self.store.insert(name.to_owned(), obj)
+    }
+
+    // Binds a `let` or `const` in this scope, redeclaring a name in the
+    // same block is an error.
+    pub fn declare(&mut self, name: &str, obj: Object, constant: bool) -> Result<(), String> {
+        if self.store.contains_key(name) {
+            return Err(format!("identifier '{}' has already been declared", name));
+        }
+        if constant {
+            self.constants.insert(name.to_owned());
+        }
+        self.store.insert(name.to_owned(), obj);
+        Ok(())
+    }
+
+    // Updates an existing binding in the closest scope that defines it.
+    pub fn assign(&mut self, name: &str, obj: Object) -> Result<Object, String> {
+        if self.store.contains_key(name) {
+            if self.constants.contains(name) {
+                return Err(format!("assignment to constant variable '{}'", name));
+            }
+            self.store.insert(name.to_owned(), obj.clone());
+            return Ok(obj);
+        }
+        match self.out {
+            Some(ref out_env) => out_env.borrow_mut().assign(name, obj),
+            None => Err(format!("identifier not found: {}", name)),
+        }
+    }
+
+    pub fn get_local(&self, depth: usize, index: usize) -> Option<Object> {
+        if depth > 0 {
+            return self.out.as_ref()?.borrow().get_local(depth - 1, index);
+        }
+        match self.locals.get(index) {
+            Some(Some(local)) => Some(local.value.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn set_slots(&mut self, count: usize) {
+        self.slots = Some(count);
+    }
+
+    // Declares the variable in the slot `index` of the scope `depth` out,
+    // the resolver having ruled out conflicting declarations.
+    pub fn set_local(&mut self,
+                     depth: usize,
+                     index: usize,
+                     obj: Object,
+                     constant: bool)
+                     -> Result<(), String> {
+        if depth > 0 {
+            return match self.out {
+                       Some(ref out_env) => {
+                           out_env.borrow_mut().set_local(depth - 1, index, obj, constant)
+                       }
+                       None => Err(format!("no scope {} out to declare in", depth)),
+                   };
+        }
+        if self.slots.is_some_and(|slots| index >= slots) {
+            return Err(format!("slot {} is out of the scope", index));
+        }
+        if self.locals.len() <= index {
+            self.locals.resize(index + 1, None);
+        }
+        self.locals[index] = Some(Local {
+                                      value: obj,
+                                      constant,
+                                  });
+        Ok(())
+    }
+
+    pub fn assign_local(&mut self,
+                        depth: usize,
+                        index: usize,
+                        name: &str,
+                        obj: Object)
+                        -> Result<Object, String> {
+        if depth > 0 {
+            return match self.out {
+                       Some(ref out_env) => {
+                           out_env.borrow_mut().assign_local(depth - 1, index, name, obj)
+                       }
+                       None => Err(format!("identifier not found: {}", name)),
+                   };
+        }
+        match self.locals.get_mut(index) {
+            Some(&mut Some(ref local)) if local.constant => {
+                Err(format!("assignment to constant variable '{}'", name))
+            }
+            Some(&mut Some(ref mut local)) => {
+                local.value = obj.clone();
+                Ok(obj)
+            }
+            _ => Err(format!("identifier not found: {}", name)),
+        }
+    }
+
+    // Returns the scope `var` declarations made from `env` belong to.
+    pub fn function_scope(env: &Env) -> Env {
+        let mut scope = env.clone();
+        loop {
+            let out = {
+                let inner = scope.borrow();
+                match (inner.kind, &inner.out) {
+                    (ScopeKind::Block, Some(out)) => out.clone(),
+                    _ => break,
+                }
+            };
+            scope = out;
+        }
+        scope
+    }
+}
+
+// Functions hold on to the environment they were created in, which usually
+// holds the function itself, so only the bound names are printed.
+impl Debug for Environment {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Environment")
+            .field("names", &self.store.keys().collect::<Vec<_>>())
+            .field("kind", &self.kind)
+            .finish()
     }
 }
@@ -1,167 +1,823 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::mem;
+use std::rc::Rc;
+
 use ast::*;
-use environment::Environment;
+use builtins;
+use environment::{Env, Environment, ScopeKind};
+use memory::Meter;
+use runtime::Runtime;
+use token::{Position, TokenType};
 use types::{self, Program};
 use object::{self, Object, ObjectType, Objecter};
+use vm;
+
+fn eval_statement(stmt: StmtId, prog: &Rc<Program>, env: &Env) -> Option<Object> {
+    let position = prog.ast.statements.position(stmt);
+    if let Err(err) = env.borrow().runtime().step() {
+        return Some(Object::ERROR(trace_error(Box::new(err), position, env)));
+    }
+    traced(eval_statement_type(stmt, prog, env), position, env)
+}
 
-pub fn eval<'a>(node: &'a NodeType, mut env: &mut Environment) -> Option<Object> {
-    match *node {
-        NodeType::Program(ref prog) => eval_program(prog, env),
-        NodeType::Expression(ref exp) => eval_expression_type(exp, env),
-        NodeType::Statement(ref stmt) => eval_statement_type(stmt, env),
-        _ => panic!("INVALID EXPRESSION"),
+fn eval_expression(exp: ExprId, prog: &Rc<Program>, env: &Env) -> Option<Object> {
+    let position = prog.ast.expressions.position(exp);
+    if let Err(err) = env.borrow().runtime().step() {
+        return Some(Object::ERROR(trace_error(Box::new(err), position, env)));
+    }
+    traced(eval_expression_type(exp, prog, env), position, env)
+}
+
+fn traced(result: Option<Object>, position: Position, env: &Env) -> Option<Object> {
+    match result {
+        Some(Object::ERROR(err)) if err.trace.is_none() => {
+            Some(Object::ERROR(trace_error(err, position, env)))
+        }
+        result => result,
     }
 }
 
-fn eval_program(program: &Program, env: &mut Environment) -> Option<Object> {
+// Errors are traced by the innermost node they come out of, which is where
+// they were raised unless they say otherwise. Kept out of line since the
+// nodes it traces are evaluated far more often than they fail.
+#[inline(never)]
+fn trace_error(mut err: Box<object::Error>,
+               position: Position,
+               env: &Env)
+               -> Box<object::Error> {
+    let position = err.position.unwrap_or(position);
+    err.position = Some(position);
+    err.trace = Some(env.borrow().runtime().trace(position));
+    err
+}
+
+// Functions the program defines keep it alive, so it is shared with them.
+pub fn eval_program(program: &Rc<Program>, env: &Env) -> Option<Object> {
     let mut result: Option<Object> = None;
 
-    for stmt in &*program.statements {
-        result = eval(&NodeType::Statement(*stmt.clone()), env);
-        match result {
-            Some(ref r) => {
-                match *r {
-                    Object::RETURN_VAL(ref res) => return Some(*res.value.clone()),
-                    Object::ERROR(_) => return Some(r.clone()),
-                    _ => continue,
+    for &stmt in &program.statements {
+        match eval_statement(stmt, program, env) {
+            Some(Object::RETURN_VAL(res)) => return Some(*res.value),
+            Some(Object::ERROR(err)) => return Some(Object::ERROR(err)),
+            res => result = res,
+        }
+    }
+    result
+}
+
+// Every kind of expression is evaluated by a function of its own, kept out
+// of line, which keeps this frame small since it is on the native stack once
+// per nested expression. The same goes for statements.
+#[inline(never)]
+fn eval_expression_type(exp: ExprId, prog: &Rc<Program>, env: &Env) -> Option<Object> {
+    match prog.ast[exp] {
+        Expression::PREFIX(ref prefix) => eval_prefix(prefix, prog, env),
+        Expression::INFIX(ref infix) => eval_infix(infix, prog, env),
+        Expression::IDENT(ident) => eval_identifier(ident, prog, env),
+        Expression::INTEGER(ref int) => Some(Object::INTEGER(object::Integer { value: int.value })),
+        Expression::BOOL(ref bo) => native_boolean_object(bo.value),
+        Expression::STRING(ref str_lit) => {
+            Some(Object::STRING(object::Str::new(str_lit.value.clone(), &meter(env))))
+        }
+        Expression::IF(ref if_exp) => eval_if_expression(if_exp, prog, env),
+        Expression::FUNC(func) => eval_function_literal(func, prog, env),
+        Expression::ARRAY(ref arr) => eval_array_literal(arr, prog, env),
+        Expression::HASH(ref hash) => eval_hash_literal(hash, prog, env),
+        Expression::MEMBER(ref member) => eval_member_expression(member, prog, env),
+        Expression::ASSIGN(ref assign) => eval_assign_expression(assign, prog, env),
+        Expression::CALL(ref call) => {
+            eval_call_expression(call, prog.tail_calls.contains(exp), prog, env)
+        }
+        Expression::NEW(ref new) => {
+            eval_new_expression(new, prog.ast.expressions.position(exp), prog, env)
+        }
+        Expression::TERNARY(ref ternary) => eval_ternary_expression(ternary, prog, env),
+        Expression::SPREAD(_) => {
+            new_error("spread syntax is only allowed in calls and array literals".to_owned())
+        }
+    }
+}
+
+#[inline(never)]
+fn eval_prefix(prefix: &types::PrefixExpression, prog: &Rc<Program>, env: &Env) -> Option<Object> {
+    let right = eval_expression(prefix.right, prog, env)?;
+    if is_error(&right) {
+        return Some(right);
+    }
+    eval_prefix_expression(prefix.operator, right)
+}
+
+#[inline(never)]
+fn eval_infix(infix: &types::InfixExpression, prog: &Rc<Program>, env: &Env) -> Option<Object> {
+    let left = eval_expression(infix.left, prog, env)?;
+    if is_error(&left) {
+        return Some(left);
+    }
+    let right = eval_expression(infix.right, prog, env)?;
+    if is_error(&right) {
+        return Some(right);
+    }
+    eval_infix_expression(infix.operator, left, right, &env.borrow().runtime())
+}
+
+#[inline(never)]
+fn eval_function_literal(func: FuncId, prog: &Rc<Program>, env: &Env) -> Option<Object> {
+    Some(Object::FUNCTION(Rc::new(object::Func {
+                                      name: None,
+                                      body: object::Body::Ast(prog.clone(), func),
+                                      env: Environment::capture(env),
+                                      arrow: prog.ast[func].arrow,
+                                  })))
+}
+
+#[inline(never)]
+fn eval_array_literal(arr: &types::ArrayLiteral, prog: &Rc<Program>, env: &Env) -> Option<Object> {
+    let elements = match eval_arguments(&arr.elements, prog, env) {
+        Ok(elements) => elements,
+        Err(err) => return Some(Object::ERROR(Box::new(err))),
+    };
+    match new_array(elements, &meter(env)) {
+        Ok(array) => Some(array),
+        Err(err) => Some(Object::ERROR(Box::new(err))),
+    }
+}
+
+// The array of an array literal. Spreading can grow an array fast, so its
+// size is checked up front.
+pub fn new_array(elements: Vec<Object>, meter: &Rc<Meter>) -> Result<Object, object::Error> {
+    meter.reserve(elements.len() * mem::size_of::<Object>())?;
+    Ok(Object::ARRAY(object::Array::new(elements, meter)))
+}
+
+#[inline(never)]
+fn eval_hash_literal(hash: &types::HashLiteral, prog: &Rc<Program>, env: &Env) -> Option<Object> {
+    let mut pairs = Vec::new();
+    for &(key, value) in &hash.pairs {
+        let value = eval_expression(value, prog, env)?;
+        if is_error(&value) {
+            return Some(value);
+        }
+        pairs.push((prog.ast.symbols.name(key).to_owned(), value));
+    }
+    Some(Object::HASH(object::Hash::new(pairs, &meter(env))))
+}
+
+#[inline(never)]
+fn eval_member_expression(member: &types::MemberExpression,
+                          prog: &Rc<Program>,
+                          env: &Env)
+                          -> Option<Object> {
+    let obj = eval_expression(member.object, prog, env)?;
+    if is_error(&obj) {
+        return Some(obj);
+    }
+    get_property(&obj, prog.ast.symbols.name(member.property))
+}
+
+#[inline(never)]
+fn eval_call_expression(call: &types::CallExpression,
+                        tail: bool,
+                        prog: &Rc<Program>,
+                        env: &Env)
+                        -> Option<Object> {
+    if is_super(call.function, &prog.ast) {
+        return eval_super_call(call, prog, env);
+    }
+    let position = prog.ast.expressions.position(call.function);
+    // Calling a member binds `this` to the object it was read from,
+    // methods reached through `super` keep the current `this`.
+    let (func, this) = match prog.ast[call.function] {
+        Expression::MEMBER(ref member) => {
+            let obj = eval_expression(member.object, prog, env)?;
+            if is_error(&obj) {
+                return Some(obj);
+            }
+            let name = prog.ast.symbols.name(member.property);
+            let func = match lookup_property(&obj, name) {
+                Some(func) => func,
+                // Otherwise it is one of the methods of the builtin type.
+                None => {
+                    return match eval_arguments(&call.arguments, prog, env) {
+                               Ok(args) => {
+                                   enter_call(position, env);
+                                   builtins::call_method(&obj, name, args, &env.borrow().runtime())
+                               }
+                               Err(err) => Some(Object::ERROR(Box::new(err))),
+                           }
                 }
+            };
+            let this = if is_super(member.object, &prog.ast) {
+                env.borrow().get("this")
+            } else {
+                Some(obj)
+            };
+            (func, this)
+        }
+        _ => (eval_expression(call.function, prog, env)?, None),
+    };
+    if is_error(&func) {
+        return Some(func);
+    }
+    match eval_arguments(&call.arguments, prog, env) {
+        Ok(args) => {
+            enter_call(position, env);
+            if tail {
+                tail_call(func, args, this)
+            } else {
+                apply_function(func, args, this)
             }
-            None => continue,
         }
+        Err(err) => Some(Object::ERROR(Box::new(err))),
+    }
+}
+
+// Leaves the call for apply_function to make once the function making it
+// has returned.
+fn tail_call(function: Object, args: Vec<Object>, this: Option<Object>) -> Option<Object> {
+    Some(Object::TAIL_CALL(Box::new(object::TailCall {
+                                        function,
+                                        args,
+                                        this,
+                                    })))
+}
+
+// Records where the current frame is calling from, once the arguments,
+// which may make calls of their own, are evaluated.
+fn enter_call(position: Position, env: &Env) {
+    env.borrow().runtime().set_position(position);
+}
+
+#[inline(never)]
+fn eval_new_expression(new: &types::NewExpression,
+                       position: Position,
+                       prog: &Rc<Program>,
+                       env: &Env)
+                       -> Option<Object> {
+    let class = match eval_expression(new.class, prog, env)? {
+        Object::CLASS(class) => class,
+        Object::ERROR(err) => return Some(Object::ERROR(err)),
+        other => return new_error(format!("{} is not a class", other.inspect())),
+    };
+    let args = match eval_arguments(&new.arguments, prog, env) {
+        Ok(args) => args,
+        Err(err) => return Some(Object::ERROR(Box::new(err))),
+    };
+    let instance = Object::INSTANCE(object::Instance {
+                                        class: class.clone(),
+                                        fields: object::Hash::new(Vec::new(), &meter(env)),
+                                    });
+    enter_call(position, env);
+    match construct(&class, args, instance.clone()) {
+        Some(Object::ERROR(err)) => Some(Object::ERROR(err)),
+        _ => Some(instance),
     }
-    result
 }
 
-fn eval_expression_type(exp: &Expression, env: &mut Environment) -> Option<Object> {
-    match *exp {
-        Expression::PREFIX(ref prefix) => {
-            if let Some(right) = eval(&NodeType::Expression(*prefix.right.clone()), env) {
-                if is_error(&right) {
-                    return Some(right);
+#[inline(never)]
+fn eval_ternary_expression(ternary: &types::TernaryExpression,
+                           prog: &Rc<Program>,
+                           env: &Env)
+                           -> Option<Object> {
+    let condition = eval_expression(ternary.condition, prog, env)?;
+    if is_error(&condition) {
+        return Some(condition);
+    }
+    if is_truthy(condition) {
+        eval_expression(ternary.consequence, prog, env)
+    } else {
+        eval_expression(ternary.alternative, prog, env)
+    }
+}
+
+#[inline(never)]
+fn eval_statement_type(stmt: StmtId, prog: &Rc<Program>, env: &Env) -> Option<Object> {
+    match prog.ast[stmt] {
+        Statement::VAR(ref var_stmt) => eval_var_statement(var_stmt, prog, env),
+        Statement::FOR_OF(ref for_stmt) => eval_for_of_statement(for_stmt, prog, env),
+        Statement::CLASS(ref class_stmt) => eval_class_statement(class_stmt, prog, env),
+        Statement::THROW(ref throw) => eval_throw_statement(throw, prog, env),
+        Statement::TRY(ref try_stmt) => eval_try_statement(try_stmt, prog, env),
+        Statement::EXPR_STMT(ref exp_stmt) => {
+            match exp_stmt.expression {
+                Some(expr) => eval_expression(expr, prog, env),
+                None => None,
+            }
+        }
+        Statement::BLOCK_STMT(ref blk_stmt) => eval_block(blk_stmt, prog, env),
+        Statement::RETURN(ref rtn) => eval_return_statement(rtn, prog, env),
+    }
+}
+
+#[inline(never)]
+fn eval_throw_statement(throw: &types::ThrowStatement,
+                        prog: &Rc<Program>,
+                        env: &Env)
+                        -> Option<Object> {
+    let value = eval_expression(throw.value, prog, env)?;
+    if is_error(&value) {
+        return Some(value);
+    }
+    Some(Object::ERROR(Box::new(object::Error::thrown(value))))
+}
+
+#[inline(never)]
+fn eval_return_statement(rtn: &types::ReturnStatement,
+                         prog: &Rc<Program>,
+                         env: &Env)
+                         -> Option<Object> {
+    // A bare `return;` returns null.
+    let value = match rtn.return_value {
+        Some(rtn_val) => eval_expression(rtn_val, prog, env)?,
+        None => Object::NULL,
+    };
+    if is_error(&value) {
+        return Some(value);
+    }
+    Some(Object::RETURN_VAL(object::Return { value: Box::new(value) }))
+}
+
+#[inline(never)]
+fn eval_assign_expression(assign: &types::AssignExpression,
+                          prog: &Rc<Program>,
+                          env: &Env)
+                          -> Option<Object> {
+    match prog.ast[assign.target] {
+        Expression::IDENT(ident) => {
+            let val = eval_expression(assign.value, prog, env)?;
+            if is_error(&val) {
+                return Some(val);
+            }
+            let name = prog.ast.name(ident);
+            let assigned = match prog.slots.get(ident) {
+                Some(slot) => env.borrow_mut().assign_local(slot.depth, slot.index, name, val),
+                None => env.borrow_mut().assign(name, val),
+            };
+            match assigned {
+                Ok(v) => Some(v),
+                Err(msg) => new_error(msg),
+            }
+        }
+        Expression::MEMBER(ref member) => {
+            let obj = eval_expression(member.object, prog, env)?;
+            if is_error(&obj) {
+                return Some(obj);
+            }
+            let val = eval_expression(assign.value, prog, env)?;
+            if is_error(&val) {
+                return Some(val);
+            }
+            let name = prog.ast.symbols.name(member.property);
+            match obj {
+                Object::HASH(hash) |
+                Object::INSTANCE(object::Instance { fields: hash, .. }) => {
+                    hash.set(name, val.clone());
+                    Some(val)
+                }
+                other => {
+                    new_error(format!("cannot set property '{}' of {:?}", name, other.obj_type()))
                 }
-                return eval_prefix_expression(&prefix.operator, right);
             }
-            None
         }
-        Expression::INFIX(ref infix) => {
-            if let Some(left) = eval(&NodeType::Expression(*infix.clone().left), env) {
-                if let Some(right) = eval(&NodeType::Expression(*infix.clone().right), env) {
-                    return eval_infix_expression(&infix.operator, left, right);
+        _ => {
+            new_error(format!("invalid assignment target {}",
+                              assign.target.to_string(&prog.ast)))
+        }
+    }
+}
+
+// Reads `obj.name`, a property missing from an object reads as null.
+pub fn get_property(obj: &Object, name: &str) -> Option<Object> {
+    match *obj {
+        Object::HASH(_) | Object::INSTANCE(_) | Object::CLASS(_) => {
+            Some(lookup_property(obj, name).unwrap_or(Object::NULL))
+        }
+        _ => new_error(format!("cannot read property '{}' of {:?}", name, obj.obj_type())),
+    }
+}
+
+// Instances see their own fields first and then the methods of their class.
+pub fn lookup_property(obj: &Object, name: &str) -> Option<Object> {
+    match *obj {
+        Object::HASH(ref hash) => hash.get(name),
+        Object::INSTANCE(ref instance) => {
+            instance
+                .fields
+                .get(name)
+                .or_else(|| instance.class.find_method(name).map(Object::FUNCTION))
+        }
+        Object::CLASS(ref class) => class.find_method(name).map(Object::FUNCTION),
+        _ => None,
+    }
+}
+
+fn is_super(exp: ExprId, ast: &Ast) -> bool {
+    match ast[exp] {
+        Expression::IDENT(ident) => ast.name(ident) == "super",
+        _ => false,
+    }
+}
+
+#[inline(never)]
+fn eval_class_statement(class_stmt: &types::ClassStatement,
+                        prog: &Rc<Program>,
+                        env: &Env)
+                        -> Option<Object> {
+    let class_name = prog.ast.name(class_stmt.name);
+    let superclass = match class_stmt.superclass {
+        Some(exp) => {
+            match eval_expression(exp, prog, env)? {
+                Object::CLASS(class) => Some(class),
+                Object::ERROR(err) => return Some(Object::ERROR(err)),
+                other => {
+                    return new_error(format!("class {} cannot extend {}",
+                                             class_name,
+                                             other.inspect()))
                 }
             }
-            None
         }
-        Expression::IDENT(ref ident) => return eval_identifier(ident, env),
-        Expression::INTEGER(ref int) => {
-            return Some(Object::INTEGER(object::Integer { value: int.value }))
+        None => None,
+    };
+
+    // Methods close over a scope binding `super` to the parent class, which
+    // is how super(...) and super.method(...) find it.
+    let class_env = Environment::new_enclosed(env, ScopeKind::Block);
+    if let Some(ref sup) = superclass {
+        class_env.borrow_mut().set("super", Object::CLASS(sup.clone()));
+    }
+    let mut methods = HashMap::new();
+    for &(name, method) in &class_stmt.methods {
+        let name = prog.ast.symbols.name(name);
+        methods.insert(name.to_owned(),
+                       Rc::new(object::Func {
+                                   name: Some(method_name(class_name, name)),
+                                   body: object::Body::Ast(prog.clone(), method),
+                                   env: Environment::capture(&class_env),
+                                   arrow: false,
+                               }));
+    }
+
+    let class = Object::CLASS(Rc::new(object::Class {
+                                          name: class_name.to_owned(),
+                                          superclass,
+                                          methods,
+                                      }));
+    match bind_identifier(class_stmt.name, class, prog, env, Binding::Let) {
+        Ok(()) => None,
+        Err(err) => Some(Object::ERROR(Box::new(err))),
+    }
+}
+
+// Methods show up in traces as `Class.method`, constructors as `new Class`.
+pub fn method_name(class: &str, method: &str) -> String {
+    if method == "constructor" {
+        format!("new {}", class)
+    } else {
+        format!("{}.{}", class, method)
+    }
+}
+
+// Runs the constructor `class` has or inherits on `this`, classes without
+// one accept any arguments.
+fn construct(class: &Rc<object::Class>, args: Vec<Object>, this: Object) -> Option<Object> {
+    match class.find_method("constructor") {
+        Some(ctor) => apply_function(Object::FUNCTION(ctor), args, Some(this)),
+        None => None,
+    }
+}
+
+fn eval_super_call(call: &types::CallExpression, prog: &Rc<Program>, env: &Env) -> Option<Object> {
+    let (sup, this) = {
+        let scope = env.borrow();
+        (scope.get("super"), scope.get("this"))
+    };
+    let sup = match (sup, this.clone()) {
+        (Some(Object::CLASS(sup)), Some(Object::INSTANCE(_))) => sup,
+        _ => return new_error("'super' call outside of a subclass constructor".to_owned()),
+    };
+    let args = match eval_arguments(&call.arguments, prog, env) {
+        Ok(args) => args,
+        Err(err) => return Some(Object::ERROR(Box::new(err))),
+    };
+    enter_call(prog.ast.expressions.position(call.function), env);
+    match construct(&sup, args, this.unwrap_or(Object::NULL)) {
+        Some(Object::ERROR(err)) => Some(Object::ERROR(err)),
+        _ => Some(Object::NULL),
+    }
+}
+
+#[inline(never)]
+fn eval_var_statement(var_stmt: &types::VarStatement,
+                      prog: &Rc<Program>,
+                      env: &Env)
+                      -> Option<Object> {
+    let val = match var_stmt.value {
+        Some(value) => eval_expression(value, prog, env)?,
+        // Redeclaring a `var` without a value keeps the one it has.
+        None if var_stmt.kind == TokenType::VAR && is_declared(var_stmt.name, prog, env) => {
+            return None
         }
-        Expression::BOOL(ref bo) => return native_boolean_object(bo.value),
-        Expression::STRING(ref str_lit) => {
-            return Some(Object::STRING(object::Str { value: str_lit.clone().value }))
+        None => Object::NULL,
+    };
+    if is_error(&val) {
+        return Some(val);
+    }
+    // Anonymous functions take the name of the variable they initialize.
+    let val = match (&prog.ast[var_stmt.name], val) {
+        (&Pattern::IDENT(ident), Object::FUNCTION(ref func)) if func.name.is_none() => {
+            let name = Some(prog.ast.name(ident).to_owned());
+            Object::FUNCTION(Rc::new(object::Func { name, ..(**func).clone() }))
         }
-        Expression::IF(ref if_exp) => return eval_if_expression(if_exp.clone(), env),
-        Expression::FUNC(ref func) => {
-            return Some(Object::FUNCTION(object::Func {
-                                             parameters: func.parameters.clone(),
-                                             body: func.body.clone(),
-                                             // Env should be a ref instead of a clone
-                                             env: env.clone(),
-                                         }));
+        (_, val) => val,
+    };
+
+    match bind_pattern(var_stmt.name, val, prog, env, Binding::from(var_stmt.kind)) {
+        Ok(()) => None,
+        Err(err) => Some(Object::ERROR(Box::new(err))),
+    }
+}
+
+#[inline(never)]
+fn eval_for_of_statement(for_stmt: &types::ForOfStatement,
+                         prog: &Rc<Program>,
+                         env: &Env)
+                         -> Option<Object> {
+    let iterable = eval_expression(for_stmt.iterable, prog, env)?;
+    let meter = meter(env);
+    // Arrays are read as the loop goes, it sees the elements pushed to them
+    // by its body.
+    let items = match iterable {
+        Object::ARRAY(arr) => arr,
+        Object::STRING(s) => {
+            let chars = s.value
+                .chars()
+                .map(|c| Object::STRING(object::Str::new(c.to_string(), &meter)))
+                .collect();
+            object::Array::new(chars, &meter)
         }
-        Expression::CALL(ref call) => {
-            if let Some(func) = eval(&NodeType::Expression(*call.function.clone()), env) {
-                if let Some(args) = eval_expression(call.clone().arguments, env) {
-                    return apply_function(func, args);
+        Object::ERROR(_) => return Some(iterable),
+        other => return new_error(format!("{:?} is not iterable", other.obj_type())),
+    };
+
+    let binding = Binding::from(for_stmt.kind);
+    let runtime = env.borrow().runtime();
+    let mut index = 0;
+    while let Some(item) = items.get(index) {
+        index += 1;
+        if let Err(err) = runtime.step().and_then(|_| runtime.check_interrupt()) {
+            return Some(Object::ERROR(Box::new(err)));
+        }
+        // Every iteration gets a fresh scope for its let/const bindings.
+        let iter_env = Environment::new_enclosed(env, ScopeKind::Block);
+        if let Err(err) = bind_pattern(for_stmt.pattern, item, prog, &iter_env, binding) {
+            return Some(Object::ERROR(Box::new(err)));
+        }
+        if let Some(res) = eval_statement(for_stmt.body, prog, &iter_env) {
+            if res.obj_type() == ObjectType::RETURN_VAL || res.obj_type() == ObjectType::ERROR {
+                return Some(res);
+            }
+        }
+    }
+    Some(Object::NULL)
+}
+
+#[inline(never)]
+fn eval_try_statement(try_stmt: &types::TryStatement,
+                      prog: &Rc<Program>,
+                      env: &Env)
+                      -> Option<Object> {
+    let mut result = eval_statement(try_stmt.block, prog, env);
+
+    let caught = match result {
+        Some(Object::ERROR(ref err)) if err.is_catchable() => Some(err.clone()),
+        _ => None,
+    };
+    if let (Some(err), Some(handler)) = (caught, try_stmt.handler) {
+        let catch_env = Environment::new_enclosed(env, ScopeKind::Block);
+        result = match try_stmt.param {
+            Some(param) => {
+                let err = err.to_object(&meter(env));
+                match bind_pattern(param, err, prog, &catch_env, Binding::Let) {
+                    Ok(()) => eval_statement(handler, prog, &catch_env),
+                    Err(err) => Some(Object::ERROR(Box::new(err))),
                 }
             }
-            None
+            None => eval_statement(handler, prog, &catch_env),
+        };
+    }
+
+    // The finally block always runs, and overrides the outcome of the try
+    // and catch blocks when it returns or throws itself.
+    if let Some(finalizer) = try_stmt.finalizer {
+        if let Some(res) = eval_statement(finalizer, prog, env) {
+            if res.obj_type() == ObjectType::RETURN_VAL || res.obj_type() == ObjectType::ERROR {
+                return Some(res);
+            }
         }
-        _ => {
-            println!("{:?}", exp);
-            return Some(Object::NULL);
+    }
+    result
+}
+
+// Whether the `var` named by `pattern` is declared in its scope already.
+fn is_declared(pattern: PatternId, prog: &Program, env: &Env) -> bool {
+    match prog.ast[pattern] {
+        Pattern::IDENT(ident) => {
+            match prog.slots.get(ident) {
+                Some(slot) => env.borrow().get_local(slot.depth, slot.index).is_some(),
+                None => Environment::function_scope(env).borrow().contains(prog.ast.name(ident)),
+            }
         }
+        _ => false,
     }
 }
 
-fn eval_statement_type(stmt: &Statement, env: &mut Environment) -> Option<Object> {
-    match *stmt {
-        Statement::VAR(ref var_stmt) => {
-            if let Some(val) = eval(&NodeType::Expression(*var_stmt.clone().value.unwrap()), env) {
-                if is_error(&val) {
-                    return Some(val);
+// How the names of a pattern are introduced in the environment.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Binding {
+    Var,
+    Let,
+    Const,
+}
+
+impl From<TokenType> for Binding {
+    fn from(tt: TokenType) -> Binding {
+        match tt {
+            TokenType::LET => Binding::Let,
+            TokenType::CONST => Binding::Const,
+            _ => Binding::Var,
+        }
+    }
+}
+
+// Variables with a slot are declared in it, the resolver pointing `var`
+// ones to their function scope, globals by their name.
+fn bind_identifier(ident: IdentId,
+                   value: Object,
+                   prog: &Program,
+                   env: &Env,
+                   binding: Binding)
+                   -> Result<(), object::Error> {
+    let constant = binding == Binding::Const;
+    match (prog.slots.get(ident), binding) {
+        (Some(slot), _) => {
+            env.borrow_mut()
+                .set_local(slot.depth, slot.index, value, constant)
+                .map_err(object::Error::new)
+        }
+        (None, Binding::Var) => {
+            Environment::function_scope(env).borrow_mut().set(prog.ast.name(ident), value);
+            Ok(())
+        }
+        (None, Binding::Let) | (None, Binding::Const) => {
+            env.borrow_mut()
+                .declare(prog.ast.name(ident), value, constant)
+                .map_err(object::Error::new)
+        }
+    }
+}
+
+// Destructures `value` against `pattern`, shared by declarations, function
+// parameters and for...of heads.
+fn bind_pattern(pattern: PatternId,
+                value: Object,
+                prog: &Program,
+                env: &Env,
+                binding: Binding)
+                -> Result<(), object::Error> {
+    match prog.ast[pattern] {
+        Pattern::IDENT(ident) => bind_identifier(ident, value, prog, env, binding),
+        Pattern::ARRAY(ref arr_pattern) => {
+            let elements = match value {
+                Object::ARRAY(arr) => arr.to_vec(),
+                other => {
+                    return Err(object::Error::new(format!("cannot destructure {:?} as an array",
+                                                          other.obj_type())))
                 }
-                return env.set(var_stmt.name.value.as_str(), val);
+            };
+            if elements.len() < arr_pattern.elements.len() {
+                return Err(object::Error::new(format!("cannot destructure {} elements into {}",
+                                                      elements.len(),
+                                                      arr_pattern.to_string(&prog.ast))));
+            }
+
+            let mut elements = elements.into_iter();
+            for (&pattern, element) in arr_pattern.elements.iter().zip(elements.by_ref()) {
+                bind_pattern(pattern, element, prog, env, binding)?;
+            }
+            if let Some(rest) = arr_pattern.rest {
+                let rest_value = Object::ARRAY(object::Array::new(elements.collect(), &meter(env)));
+                bind_pattern(rest, rest_value, prog, env, binding)?;
             }
-            None
+            Ok(())
         }
-        Statement::EXPR_STMT(ref exp_stmt) => {
-            if let Some(ref expr) = exp_stmt.expression {
-                return eval(&NodeType::Expression(*expr.clone()), env);
+        Pattern::OBJECT(ref obj_pattern) => {
+            match value {
+                Object::HASH(_) | Object::INSTANCE(_) => {}
+                other => {
+                    return Err(object::Error::new(format!("cannot destructure {:?} as an object",
+                                                          other.obj_type())))
+                }
             };
-            None
-        }
-        Statement::BLOCK_STMT(ref blk_stmt) => return eval_block(blk_stmt.clone(), env),
-        Statement::RETURN(ref rtn) => {
-            if let Some(rtn_val) = rtn.return_value.clone() {
-                if let Some(value) = eval(&NodeType::Expression(*rtn_val), env) {
-                    let exp_val = Object::from(value);
-                    return Some(Object::RETURN_VAL(object::Return { value: Box::new(exp_val) }));
+            for &(key, pattern) in &obj_pattern.properties {
+                let key = prog.ast.symbols.name(key);
+                match lookup_property(&value, key) {
+                    Some(property) => bind_pattern(pattern, property, prog, env, binding)?,
+                    None => {
+                        let msg = format!("cannot destructure missing property '{}'", key);
+                        return Err(object::Error::new(msg));
+                    }
                 }
             }
-            None
+            Ok(())
         }
     }
 }
 
-fn eval_expression(exps: Vec<Expression>, env: &mut Environment) -> Option<Vec<Object>> {
+// Evaluates call arguments and array elements, expanding spread elements
+// in place.
+fn eval_arguments(exps: &[ExprId],
+                  prog: &Rc<Program>,
+                  env: &Env)
+                  -> Result<Vec<Object>, object::Error> {
     let mut result: Vec<Object> = Vec::new();
 
-    for exp in exps {
-        if let Some(evaluated) = eval(&NodeType::Expression(exp), env) {
-            result.push(evaluated)
+    for &exp in exps {
+        let (exp, spread) = match prog.ast[exp] {
+            Expression::SPREAD(ref spread) => (spread.value, true),
+            _ => (exp, false),
+        };
+        match eval_expression(exp, prog, env) {
+            Some(Object::ERROR(err)) => return Err(*err),
+            Some(Object::ARRAY(arr)) if spread => result.extend(arr.to_vec()),
+            Some(evaluated) => {
+                if spread {
+                    let msg = format!("cannot spread {:?}", evaluated.obj_type());
+                    return Err(object::Error::new(msg));
+                }
+                result.push(evaluated)
+            }
+            None => result.push(Object::NULL),
         }
     }
-    Some(result)
+    Ok(result)
 }
 
-fn eval_prefix_expression(op: &str, right: Object) -> Option<Object> {
+pub fn eval_prefix_expression(op: &str, right: Object) -> Option<Object> {
     match op {
         "!" => eval_bang_operator(right),
         "-" => eval_minus_prefix_operator(right),
-        _ => None,
+        _ => new_error(format!("unknown operator: {}{:?}", op, right.obj_type())),
     }
 }
 
-fn eval_infix_expression(op: &str, left: Object, right: Object) -> Option<Object> {
+pub fn eval_infix_expression(op: &str,
+                             left: Object,
+                             right: Object,
+                             runtime: &Runtime)
+                             -> Option<Object> {
     if left.obj_type() == ObjectType::INTEGER && right.obj_type() == ObjectType::INTEGER {
         return eval_integer_infix(op, left, right);
     } else if left.obj_type() == ObjectType::STRING && right.obj_type() == ObjectType::STRING {
-        return eval_string_infix(op, left, right);
+        return eval_string_infix(op, left, right, runtime);
     } else if op == "==" {
         return native_boolean_object(left == right);
     } else if op == "!=" {
         return native_boolean_object(left != right);
     } else if left.obj_type() != right.obj_type() {
-        return eval_string_infix(op, left, right);
+        return eval_string_infix(op, left, right, runtime);
     }
-    return unimplemented!();
+    new_error(format!("unknown operator: {:?} {} {:?}",
+                      left.obj_type(),
+                      op,
+                      right.obj_type()))
 }
 
-fn eval_if_expression(if_exp: types::IfExpression, env: &mut Environment) -> Option<Object> {
-    if let Some(condition) = eval(&NodeType::Expression(*if_exp.condition), env) {
-        if is_truthy(condition) {
-            return eval(&NodeType::Statement(*if_exp.consequence), env);
-        } else if let Some(alt) = if_exp.alternative {
-            return eval(&NodeType::Statement(alt), env);
-        }
+// An `if` whose condition is falsy and that has no `else` evaluates to null.
+#[inline(never)]
+fn eval_if_expression(if_exp: &types::IfExpression,
+                      prog: &Rc<Program>,
+                      env: &Env)
+                      -> Option<Object> {
+    let condition = eval_expression(if_exp.condition, prog, env)?;
+    if is_error(&condition) {
+        return Some(condition);
+    }
+    if is_truthy(condition) {
+        eval_statement(if_exp.consequence, prog, env)
+    } else if let Some(alt) = if_exp.alternative {
+        eval_statement(alt, prog, env)
+    } else {
+        Some(Object::NULL)
     }
-    None
 }
 
-fn eval_block(block: types::BlockStatement, env: &mut Environment) -> Option<Object> {
+#[inline(never)]
+fn eval_block(block: &types::BlockStatement, prog: &Rc<Program>, env: &Env) -> Option<Object> {
     let mut result = Object::NULL;
+    let block_env = Environment::new_enclosed(env, ScopeKind::Block);
 
-    for stmt in block.statements {
-        if let Some(res) = eval(&NodeType::Statement(stmt), env) {
+    for &stmt in &block.statements {
+        if let Some(res) = eval_statement(stmt, prog, &block_env) {
             result = res;
             if result.obj_type() == ObjectType::RETURN_VAL ||
                result.obj_type() == ObjectType::ERROR {
@@ -172,50 +828,90 @@ fn eval_block(block: types::BlockStatement, env: &mut Environment) -> Option<Obj
     Some(result)
 }
 
-fn eval_identifier(node: &types::Identifier, env: &mut Environment) -> Option<Object> {
-    if let Some(v) = env.get(&node.value) {
+#[inline(never)]
+fn eval_identifier(ident: IdentId, prog: &Program, env: &Env) -> Option<Object> {
+    let value = match prog.slots.get(ident) {
+        Some(slot) => env.borrow().get_local(slot.depth, slot.index),
+        None => env.borrow().get(prog.ast.name(ident)),
+    };
+    if let Some(v) = value {
         return Some(v);
     }
-    None
+    let message = format!("identifier not found: {}", prog.ast.name(ident));
+    let position = prog.ast.identifiers.position(ident);
+    Some(Object::ERROR(Box::new(object::Error::at(message, position))))
 }
 
 fn eval_integer_infix(op: &str, left: Object, right: Object) -> Option<Object> {
-    let left_value = match left {
-        Object::INTEGER(v) => v.value,
-        _ => return None,
+    let (left_value, right_value) = match (left, right) {
+        (Object::INTEGER(l), Object::INTEGER(r)) => (l.value, r.value),
+        (l, r) => {
+            return new_error(format!("type mismatch: {:?} {} {:?}",
+                                     l.obj_type(),
+                                     op,
+                                     r.obj_type()))
+        }
     };
-    let right_value = match right {
-        Object::INTEGER(v) => v.value,
-        _ => return None,
+
+    // Arithmetic is checked, overflowing an integer is an error rather than
+    // a wrap around.
+    let value = match op {
+        "+" => left_value.checked_add(right_value),
+        "-" => left_value.checked_sub(right_value),
+        "*" => left_value.checked_mul(right_value),
+        "/" if right_value == 0 => return new_error("division by zero".to_owned()),
+        "/" => left_value.checked_div(right_value),
+        "^" if right_value < 0 => return new_error("negative exponent".to_owned()),
+        "^" => {
+            u32::try_from(right_value)
+                .ok()
+                .and_then(|exp| left_value.checked_pow(exp))
+        }
+        _ => return eval_integer_comparison(op, left_value, right_value),
     };
+    match value {
+        Some(value) => Some(Object::INTEGER(object::Integer { value })),
+        None => new_error(format!("integer overflow: {} {} {}", left_value, op, right_value)),
+    }
+}
 
+fn eval_integer_comparison(op: &str, left_value: i64, right_value: i64) -> Option<Object> {
     match op {
-        "+" => return Some(Object::INTEGER(object::Integer { value: left_value + right_value })),
-        "-" => return Some(Object::INTEGER(object::Integer { value: left_value - right_value })),
-        "*" => return Some(Object::INTEGER(object::Integer { value: left_value * right_value })),
-        "/" => return Some(Object::INTEGER(object::Integer { value: left_value / right_value })),
-        "^" => return Some(Object::INTEGER(object::Integer { value: left_value.pow(right_value as u32) })),
-        "<" => return native_boolean_object(left_value < right_value),
-        ">" => return native_boolean_object(left_value > right_value),
-        "==" => return native_boolean_object(left_value == right_value),
-        "!=" => return native_boolean_object(left_value != right_value),
-        _ => return None,
-    }
-}
-
-fn eval_string_infix(op: &str, left: Object, right: Object) -> Option<Object> {
-    if op != "+" {
-        return None;
-    }
-    let left_value = match left {
-        Object::STRING(v) => v.value,
-        _ => left.inspect(),
-    };
-    let right_value = match right {
-        Object::STRING(v) => v.value,
-        _ => right.inspect(),
-    };
-    return Some(Object::STRING(object::Str { value: left_value + &right_value }));
+        "<" => native_boolean_object(left_value < right_value),
+        ">" => native_boolean_object(left_value > right_value),
+        "==" => native_boolean_object(left_value == right_value),
+        "!=" => native_boolean_object(left_value != right_value),
+        _ => new_error(format!("unknown operator: INTEGER {} INTEGER", op)),
+    }
+}
+
+// Strings compare with each other, and `+` joins a string with the
+// printed form of any other value.
+fn eval_string_infix(op: &str, left: Object, right: Object, runtime: &Runtime) -> Option<Object> {
+    match op {
+        "+" => {}
+        "==" | "!=" if left.obj_type() == right.obj_type() => {
+            return native_boolean_object((left == right) == (op == "=="));
+        }
+        _ if left.obj_type() == right.obj_type() => {
+            return new_error(format!("unknown operator: STRING {} STRING", op));
+        }
+        _ => {
+            return new_error(format!("type mismatch: {:?} {} {:?}",
+                                     left.obj_type(),
+                                     op,
+                                     right.obj_type()))
+        }
+    }
+    let joined = runtime.stringify(&left).and_then(|left| {
+        let right = runtime.stringify(&right)?;
+        runtime.meter().reserve(left.len() + right.len())?;
+        Ok(left + &right)
+    });
+    match joined {
+        Ok(joined) => Some(Object::STRING(object::Str::new(joined, &runtime.meter()))),
+        Err(err) => Some(Object::ERROR(Box::new(err))),
+    }
 }
 
 fn eval_bang_operator(right: Object) -> Option<Object> {
@@ -224,25 +920,26 @@ fn eval_bang_operator(right: Object) -> Option<Object> {
             object::Boolean::True => Object::BOOL(object::Boolean::False),
             object::Boolean::False => Object::BOOL(object::Boolean::True),
         };
-        return Some(v);
+        Some(v)
     } else {
         let v = match right {
             Object::NULL => Object::BOOL(object::Boolean::True),
             _ => Object::BOOL(object::Boolean::False),
         };
-        return Some(v);
+        Some(v)
     }
 }
 
 fn eval_minus_prefix_operator(right: Object) -> Option<Object> {
-    if right.obj_type() != ObjectType::INTEGER {
-        return None;
+    match right {
+        Object::INTEGER(v) => {
+            match v.value.checked_neg() {
+                Some(value) => Some(Object::INTEGER(object::Integer { value })),
+                None => new_error(format!("integer overflow: -{}", v.value)),
+            }
+        }
+        other => new_error(format!("unknown operator: -{:?}", other.obj_type())),
     }
-    let value = match right {
-        Object::INTEGER(v) => v.value,
-        _ => return None,
-    };
-    return Some(Object::INTEGER(object::Integer { value: -value }));
 }
 
 fn native_boolean_object(input: bool) -> Option<Object> {
@@ -252,28 +949,127 @@ fn native_boolean_object(input: bool) -> Option<Object> {
     Some(Object::BOOL(object::Boolean::False))
 }
 
-fn apply_function(func: Object, args: Vec<Object>) -> Option<Object> {
-    match func {
-        Object::FUNCTION(fun) => {
-            if let Some(mut ext_env) = extend_function_env(fun.clone(), &args) {
-                if let Some(evaluated) = eval(&NodeType::Statement(fun.body), &mut ext_env) {
-                    return unwrap_return_value(evaluated);
+// Calls `func`, then the calls it ends with in turn, each after the one
+// before has returned.
+pub fn apply_function(mut func: Object,
+                      mut args: Vec<Object>,
+                      mut this: Option<Object>)
+                      -> Option<Object> {
+    // The frame of the last caller that made a tail call.
+    let mut tail_caller = None;
+    loop {
+        let result = match func {
+            Object::FUNCTION(ref fun) => {
+                let runtime = fun.env.borrow().runtime();
+                if let Err(err) = runtime.check_stack() {
+                    return Some(Object::ERROR(Box::new(err)));
                 }
+                let (program, function) = match fun.body {
+                    object::Body::Ast(ref program, function) => (program, &program.ast[function]),
+                    object::Body::Bytecode(ref function) => {
+                        return vm::call(fun, function, args, this)
+                    }
+                };
+                let parameters = &function.parameters;
+                let ext_env = match extend_function_env(fun, parameters, program, args, this) {
+                    Ok(env) => env,
+                    Err(err) => return Some(Object::ERROR(Box::new(err))),
+                };
+                if let Err(err) = runtime.check_interrupt()
+                    .and_then(|_| runtime.push_frame(fun.name.clone(), tail_caller.take())) {
+                    return Some(Object::ERROR(Box::new(err)));
+                }
+                let result = match eval_statement(function.body, program, &ext_env) {
+                    Some(evaluated) => unwrap_return_value(evaluated),
+                    None => Some(Object::NULL),
+                };
+                match result {
+                    Some(Object::TAIL_CALL(_)) => tail_caller = runtime.tail_call(),
+                    _ => runtime.pop_frame(),
+                }
+                result
+            }
+            Object::CLASS(ref class) => {
+                new_error(format!("class {} cannot be invoked without 'new'", class.name))
             }
-            None
+            ref other => new_error(format!("{:?} is not a function", other.obj_type())),
+        };
+        match result {
+            Some(Object::TAIL_CALL(call)) => {
+                let call = *call;
+                func = call.function;
+                args = call.args;
+                this = call.this;
+            }
+            result => return result,
         }
-        Object::BUILTIN(ref blt_in) => unimplemented!(),
-        _ => return Some(Object::ERROR(object::Error { message: "Invalid Type".to_owned() })),
     }
 }
 
-fn extend_function_env(func: object::Func, args: &[Object]) -> Option<Environment> {
-    let mut new_env = func.env.new_enclosed();
-    for (id, param) in func.parameters.iter().enumerate() {
-        // params.to_string() is not the correct key, should be param.value
-        new_env.set(&param.to_string(), args[id].clone());
+// Out of line, so apply_function takes less stack for every call.
+#[inline(never)]
+fn extend_function_env(func: &object::Func,
+                       parameters: &[types::Parameter],
+                       prog: &Rc<Program>,
+                       args: Vec<Object>,
+                       this: Option<Object>)
+                       -> Result<Env, object::Error> {
+    let required = required_arguments(parameters);
+    if args.len() < required {
+        return Err(arity_error(required, parameters.len(), args.len()));
+    }
+
+    let new_env = function_env(func, this);
+    let mut args = args.into_iter();
+    for param in parameters {
+        let value = if param.rest {
+            Object::ARRAY(object::Array::new(args.by_ref().collect(), &meter(&new_env)))
+        } else {
+            match (args.next(), param.default) {
+                (Some(arg), _) => arg,
+                // Defaults are evaluated in the new scope so they can refer
+                // to the parameters before them.
+                (None, Some(default)) => {
+                    match eval_expression(default, prog, &new_env) {
+                        Some(Object::ERROR(err)) => return Err(*err),
+                        Some(val) => val,
+                        None => Object::NULL,
+                    }
+                }
+                (None, None) => Object::NULL,
+            }
+        };
+        bind_pattern(param.name, value, prog, &new_env, Binding::Var)?;
+    }
+    Ok(new_env)
+}
+
+// The scope a call of `func` runs in. Arrow functions see the `this` of the
+// scope they were created in.
+pub fn function_env(func: &object::Func, this: Option<Object>) -> Env {
+    let env = Environment::new_enclosed(&func.env, ScopeKind::Function);
+    if !func.arrow {
+        env.borrow_mut().set("this", this.unwrap_or(Object::NULL));
     }
-    Some(new_env)
+    env
+}
+
+// The arguments a call needs at least, those of the parameters up to the last
+// one with neither a default nor a rest.
+pub fn required_arguments(parameters: &[types::Parameter]) -> usize {
+    parameters
+        .iter()
+        .rposition(|p| p.default.is_none() && !p.rest)
+        .map_or(0, |pos| pos + 1)
+}
+
+pub fn arity_error(required: usize, parameters: usize, got: usize) -> object::Error {
+    let expected = if required < parameters {
+        format!("at least {}", required)
+    } else {
+        required.to_string()
+    };
+    object::Error::new(format!("wrong number of arguments: expected {}, got {}", expected, got))
 }
 
 fn unwrap_return_value(obj: Object) -> Option<Object> {
@@ -283,7 +1079,7 @@ fn unwrap_return_value(obj: Object) -> Option<Object> {
     Some(obj)
 }
 
-fn is_truthy(obj: Object) -> bool {
+pub fn is_truthy(obj: Object) -> bool {
     match obj {
         Object::NULL => false,
         Object::BOOL(b) => {
@@ -296,6 +1092,14 @@ fn is_truthy(obj: Object) -> bool {
     }
 }
 
+fn meter(env: &Env) -> Rc<Meter> {
+    env.borrow().runtime().meter()
+}
+
 fn is_error(obj: &Object) -> bool {
     obj.obj_type() == ObjectType::ERROR
 }
+
+fn new_error(message: String) -> Option<Object> {
+    Some(Object::ERROR(Box::new(object::Error::new(message))))
+}
@@ -1,29 +1,39 @@
-use token::{Token, TokenType};
+use token::{Position, Token, TokenType};
 use std::fmt::{self, Display, Formatter};
+use std::rc::Rc;
 
+// Cloning a lexer to look ahead shares its input.
 #[derive(Debug, Clone)]
 pub struct Lexer {
-    pub input: String,
+    input: Rc<[char]>,
     position: usize,
     read_position: usize,
     ch: char,
+    line: usize,
+    column: usize,
 }
 
 impl Lexer {
     pub fn new(input: &str) -> Lexer {
         let mut lex = Lexer {
-            input: input.to_owned(),
+            input: input.chars().collect(),
             position: 0,
             read_position: 0,
-            ch: '0',
+            ch: '\0',
+            line: 1,
+            column: 0,
         };
         lex.read_char();
         lex
     }
 
     pub fn next_token(&mut self) -> Token {
-        let tok: Token;
+        let mut tok: Token;
         self.skip_whitespace();
+        let position = Position {
+            line: self.line,
+            column: self.column,
+        };
 
         match self.ch {
             '=' => {
@@ -33,6 +43,15 @@ impl Lexer {
                     tok = Token {
                         token: TokenType::EQ,
                         literal: ch.to_string() + &self.ch.to_string(),
+                        position,
+                    };
+                } else if self.peek_char() == '>' {
+                    let ch = self.ch;
+                    self.read_char();
+                    tok = Token {
+                        token: TokenType::ARROW,
+                        literal: ch.to_string() + &self.ch.to_string(),
+                        position,
                     };
                 } else {
                     tok = Token::new(TokenType::ASSIGN, self.ch)
@@ -48,15 +67,41 @@ impl Lexer {
                     tok = Token {
                         token: TokenType::NOT_EQ,
                         literal: ch.to_string() + &self.ch.to_string(),
+                        position,
                     };
                 } else {
                     tok = Token::new(TokenType::BANG, self.ch)
                 }
             }
             '"' => {
+                let literal = self.read_string();
+                // An unterminated string runs into the end of the input.
+                let token = if self.ch == '"' {
+                    TokenType::STRING
+                } else {
+                    TokenType::ILLEGAL
+                };
                 tok = Token {
-                    token: TokenType::STRING,
-                    literal: self.read_string(),
+                    token,
+                    literal,
+                    position,
+                }
+            }
+            '.' => {
+                if self.peek_char() == '.' {
+                    self.read_char();
+                    if self.peek_char() == '.' {
+                        self.read_char();
+                        tok = Token {
+                            token: TokenType::ELLIPSIS,
+                            literal: "...".to_owned(),
+                            position,
+                        };
+                    } else {
+                        tok = Token::new(TokenType::ILLEGAL, self.ch)
+                    }
+                } else {
+                    tok = Token::new(TokenType::DOT, self.ch)
                 }
             }
             '/' => tok = Token::new(TokenType::SLASH, self.ch),
@@ -69,12 +114,15 @@ impl Lexer {
             '[' => tok = Token::new(TokenType::LBRACKET, self.ch),
             ']' => tok = Token::new(TokenType::RBRACKET, self.ch),
             ',' => tok = Token::new(TokenType::COMMA, self.ch),
+            '?' => tok = Token::new(TokenType::QUESTION, self.ch),
+            ':' => tok = Token::new(TokenType::COLON, self.ch),
             '{' => tok = Token::new(TokenType::LBRACE, self.ch),
             '}' => tok = Token::new(TokenType::RBRACE, self.ch),
-            '0' => {
+            '\0' => {
                 tok = Token {
                     token: TokenType::EOF,
                     literal: "".to_owned(),
+                    position,
                 }
             }
             _ => {
@@ -84,6 +132,7 @@ impl Lexer {
                     tok = Token {
                         token: tok_type,
                         literal: tok_lit,
+                        position,
                     };
                     return tok;
                 } else if self.ch.is_numeric() {
@@ -92,21 +141,29 @@ impl Lexer {
                     tok = Token {
                         token: tok_type,
                         literal: tok_lit,
+                        position,
                     };
                     return tok;
                 }
                 tok = Token::new(TokenType::ILLEGAL, '0')
             }
         }
+        tok.position = position;
         self.read_char();
         tok
     }
 
     fn read_char(&mut self) {
+        if self.ch == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
         if self.read_position >= self.input.len() {
-            self.ch = '0';
+            self.ch = '\0';
         } else {
-            self.ch = self.input.chars().nth(self.read_position).unwrap();
+            self.ch = self.input[self.read_position];
         }
 
         self.position = self.read_position;
@@ -118,18 +175,18 @@ impl Lexer {
         while self.ch.is_alphabetic() {
             self.read_char()
         }
-        self.input[position..self.position].to_owned()
+        self.input[position..self.position].iter().collect()
     }
 
     fn read_string(&mut self) -> String {
         let position = self.position + 1;
         loop {
             self.read_char();
-            if self.ch == '"' {
+            if self.ch == '"' || self.ch == '\0' {
                 break;
             }
         }
-        self.input[position..self.position].to_owned()
+        self.input[position..self.position].iter().collect()
     }
 
     fn read_number(&mut self) -> String {
@@ -137,14 +194,14 @@ impl Lexer {
         while self.ch.is_numeric() {
             self.read_char();
         }
-        self.input[position..self.position].to_owned()
+        self.input[position..self.position].iter().collect()
     }
 
     fn peek_char(&self) -> char {
         if self.read_position >= self.input.len() {
-            return '0';
+            return '\0';
         }
-        self.input.chars().nth(self.read_position).unwrap()
+        self.input[self.read_position]
     }
 
     fn skip_whitespace(&mut self) {
@@ -156,6 +213,6 @@ impl Lexer {
 
 impl Display for Lexer {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "{}", self.input)
+        write!(f, "{}", self.input.iter().collect::<String>())
     }
 }
@@ -1,42 +1,287 @@
+#![allow(clippy::upper_case_acronyms)]
+
 #[macro_use]
 extern crate lazy_static;
 
+#[macro_use]
+mod arena;
 mod token;
 mod types;
 mod object;
 mod environment;
+mod runtime;
+mod memory;
 mod evaluator;
+mod compiler;
+mod vm;
+mod disassembler;
+mod bytecode;
+mod builtins;
 mod ast;
 mod lexer;
 mod parser;
+mod resolver;
+mod optimizer;
 
 pub mod interpreter {
+    use std::fmt::{self, Display, Formatter};
+    use std::rc::Rc;
+    use std::time::Duration;
+
     use lexer::Lexer;
     use parser::Parser;
-    use environment::Environment;
-    use evaluator::eval;
-    use object::Objecter;
-    use ast::NodeType;
+    use resolver::Resolver;
+    use optimizer;
+    use environment::Env;
+    use evaluator::eval_program;
+    use compiler;
+    use vm;
+    use disassembler;
+    use bytecode;
+    use memory;
+    use types::Program;
+    use object::{self, Object, Objecter};
+    use runtime::{self, Runtime};
+
+    pub use runtime::InterruptHandle;
+
+    // Why a script did not run to completion, each holding the message to
+    // show for it.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum ExecutionError {
+        Syntax(String),
+        Runtime(String),
+        // The script used up its fuel or its time.
+        ResourceExhausted(String),
+        // The host stopped the script through an InterruptHandle.
+        Cancelled(String),
+        // A compiled program was damaged or of another format version.
+        InvalidBytecode(String),
+    }
+
+    impl Display for ExecutionError {
+        fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+            match *self {
+                ExecutionError::Syntax(ref msg) |
+                ExecutionError::Runtime(ref msg) |
+                ExecutionError::ResourceExhausted(ref msg) |
+                ExecutionError::Cancelled(ref msg) |
+                ExecutionError::InvalidBytecode(ref msg) => write!(f, "{}", msg),
+            }
+        }
+    }
+
+    impl From<object::Error> for ExecutionError {
+        fn from(err: object::Error) -> ExecutionError {
+            match err.kind {
+                object::ErrorKind::Error => ExecutionError::Runtime(err.inspect()),
+                object::ErrorKind::ResourceExhausted => {
+                    ExecutionError::ResourceExhausted(err.inspect())
+                }
+                object::ErrorKind::Cancelled => ExecutionError::Cancelled(err.inspect()),
+            }
+        }
+    }
+
+    // How scripts are run: by walking their syntax tree, or by compiling
+    // them to bytecode for the vm. Both give the same results.
+    #[derive(Debug, Copy, Clone, PartialEq, Default)]
+    pub enum Engine {
+        #[default]
+        TreeWalker,
+        Vm,
+    }
+
+    fn parse(code: &str) -> Result<Program, ExecutionError> {
+        let lex = Lexer::new(code);
+        let mut parser = Parser::new(lex);
+        let mut prog = parser.parse_program();
+        if !parser.errors.is_empty() {
+            let msg = format!("parse error: {}", parser.errors.join("\nparse error: "));
+            return Err(ExecutionError::Syntax(msg));
+        }
+
+        let (slots, tail_calls) = {
+            let mut resolver = Resolver::new(&prog.ast);
+            let slots = resolver.resolve_program(&prog.statements);
+            if !resolver.errors.is_empty() {
+                let msg = format!("resolve error: {}", resolver.errors.join("\nresolve error: "));
+                return Err(ExecutionError::Syntax(msg));
+            }
+            (slots, resolver.tail_calls)
+        };
+        prog.slots = slots;
+        prog.tail_calls = tail_calls;
+        Ok(prog)
+    }
+
+    // Programs ending in a declaration have no value and print null. Printing
+    // the value takes steps and memory like the run did.
+    fn finish(result: Option<Object>, runtime: &Runtime) -> Result<String, ExecutionError> {
+        match result {
+            Some(Object::ERROR(err)) => Err(ExecutionError::from(*err)),
+            Some(res) => runtime.stringify(&res).map_err(ExecutionError::from),
+            None => Ok(Object::NULL.inspect()),
+        }
+    }
+
+    // Parses `code` without running it, failing with the syntax errors
+    // Executor::execute would.
+    pub fn check(code: &str) -> Result<(), ExecutionError> {
+        parse(code).map(|_| ())
+    }
+
+    // The listing of the bytecode the vm runs `code` as, one instruction a
+    // line with its offset, source position and operands.
+    pub fn disassemble(code: &str) -> Result<String, ExecutionError> {
+        Ok(disassembler::disassemble(&compiler::compile(&parse(code)?)))
+    }
+
+    // Compiles `code` to a program Executor::execute_compiled can run
+    // without parsing it again, in a versioned binary format.
+    pub fn compile(code: &str) -> Result<Vec<u8>, ExecutionError> {
+        compile_named(code, runtime::DEFAULT_SOURCE)
+    }
+
+    // Like compile, with `name` for the source in the traces of the runtime
+    // errors of the program, whatever Executor runs it.
+    pub fn compile_named(code: &str, name: &str) -> Result<Vec<u8>, ExecutionError> {
+        Ok(bytecode::encode(name, &compiler::compile(&parse(code)?)))
+    }
 
     #[derive(Default)]
     pub struct Executor {
-        variables: Environment,
+        variables: Env,
+        engine: Engine,
+        optimize: bool,
     }
 
     impl Executor {
         pub fn new() -> Executor {
-            Executor { variables: Environment::default() }
+            Executor::default()
         }
 
-        pub fn execute(&mut self, code: &str) -> Result<String, String> {
-            let lex = Lexer::new(code);
-            let mut parser = Parser::new(lex);
-            let prog = parser.parse_program();
+        pub fn execute(&mut self, code: &str) -> Result<String, ExecutionError> {
+            let output = self.run(code);
+            self.variables.borrow().runtime().end();
+            output
+        }
 
-            match eval(&NodeType::Program(prog), &mut self.variables) {
-                Some(res) => Ok(res.inspect()),
-                None => Err("empty result".to_owned()),
+        // Runs a program made by `compile` on the vm, whatever the engine.
+        // Damaged programs and those of another format version are rejected
+        // without running any of them.
+        pub fn execute_compiled(&mut self, program: &[u8]) -> Result<String, ExecutionError> {
+            let output = self.run_compiled(program);
+            self.variables.borrow().runtime().end();
+            output
+        }
+
+        fn run(&mut self, code: &str) -> Result<String, ExecutionError> {
+            let mut prog = parse(code)?;
+            if self.optimize {
+                optimizer::optimize(&mut prog);
             }
+            let prog = Rc::new(prog);
+            self.variables.borrow().runtime().start();
+            let result = match self.engine {
+                Engine::TreeWalker => eval_program(&prog, &self.variables),
+                Engine::Vm => vm::run(&compiler::compile(&prog), &self.variables),
+            };
+            let output = finish(result, &self.variables.borrow().runtime());
+            memory::collect(&self.variables);
+            output
+        }
+
+        // Traces name the source the program was compiled from for the run.
+        fn run_compiled(&mut self, program: &[u8]) -> Result<String, ExecutionError> {
+            let (source, code) = match bytecode::decode(program) {
+                Ok(program) => program,
+                Err(msg) => {
+                    let msg = format!("invalid bytecode: {}", msg);
+                    return Err(ExecutionError::InvalidBytecode(msg));
+                }
+            };
+            let runtime = self.variables.borrow().runtime();
+            let name = runtime.source();
+            runtime.set_source(&source);
+            runtime.start();
+            let result = vm::run(&code, &self.variables);
+            let output = finish(result, &runtime);
+            runtime.set_source(&name);
+            memory::collect(&self.variables);
+            output
+        }
+
+        // Runs scripts with `engine`, the tree-walker by default. Functions
+        // made by one engine can be called from scripts run by the other.
+        pub fn with_engine(mut self, engine: Engine) -> Executor {
+            self.engine = engine;
+            self
+        }
+
+        // Folds the constant parts of scripts before running them, which
+        // gives the same results with fewer steps. Functions then show their
+        // folded source.
+        pub fn with_optimizer(mut self, enabled: bool) -> Executor {
+            self.optimize = enabled;
+            self
+        }
+
+        // Limits how deeply calls may nest, running deeper raises a catchable
+        // "maximum call stack size exceeded" error. The default allows 1000
+        // calls, those the tree-walker makes are limited by with_max_stack too.
+        pub fn with_max_call_depth(self, depth: usize) -> Executor {
+            self.variables.borrow().runtime().set_max_call_depth(depth);
+            self
+        }
+
+        // Limits the native stack the calls of a run may take, the calls
+        // the tree-walker makes and those builtins like `map` make back into
+        // scripts, running deeper raises the same error as nesting calls too
+        // deeply. The default of 1MB fits the 2MB stack of a spawned thread,
+        // threads with a larger stack can allow more.
+        pub fn with_max_stack(self, bytes: usize) -> Executor {
+            self.variables.borrow().runtime().set_max_stack(bytes);
+            self
+        }
+
+        // Gives every execution a budget of `fuel` steps, each evaluated node
+        // and loop iteration taking one.
+        pub fn with_fuel(self, fuel: u64) -> Executor {
+            self.variables.borrow().runtime().set_fuel(fuel);
+            self
+        }
+
+        // Stops executions running for longer than `timeout`.
+        pub fn with_timeout(self, timeout: Duration) -> Executor {
+            self.variables.borrow().runtime().set_timeout(timeout);
+            self
+        }
+
+        // Caps the memory held by the values of scripts at about `bytes`,
+        // going over raises a catchable "out of memory" error.
+        pub fn with_memory_limit(self, bytes: usize) -> Executor {
+            self.variables.borrow().runtime().meter().set_limit(bytes);
+            self
+        }
+
+        // The approximate number of bytes the values of scripts hold now.
+        pub fn memory_usage(&self) -> usize {
+            self.variables.borrow().runtime().meter().used()
+        }
+
+        // A handle other threads can stop the executions of this Executor
+        // with, they then fail with ExecutionError::Cancelled and the
+        // Executor can run further scripts.
+        pub fn interrupt_handle(&self) -> InterruptHandle {
+            self.variables.borrow().runtime().interrupt_handle()
+        }
+
+        // Names the source in the traces of runtime errors, like the file a
+        // script was read from.
+        pub fn with_source_name(self, name: &str) -> Executor {
+            self.variables.borrow().runtime().set_source(name);
+            self
         }
     }
 }
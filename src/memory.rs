@@ -0,0 +1,153 @@
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::fmt::{self, Debug, Formatter};
+use std::rc::Rc;
+
+use environment::Env;
+use object::{self, Object};
+
+// Approximate accounting of the heap held by the values of an Executor:
+// strings count their bytes, arrays and hashes the slots they hold.
+#[derive(Debug, Default)]
+pub struct Meter {
+    used: Cell<usize>,
+    limit: Cell<Option<usize>>,
+}
+
+impl Meter {
+    pub fn used(&self) -> usize {
+        self.used.get()
+    }
+
+    pub fn set_limit(&self, limit: usize) {
+        self.limit.set(Some(limit));
+    }
+
+    // Fails once the values alive take more than the limit.
+    pub fn check(&self) -> Result<(), object::Error> {
+        self.reserve(0)
+    }
+
+    // Fails if `bytes` more would go over the limit, for callers to check
+    // before building a large value.
+    pub fn reserve(&self, bytes: usize) -> Result<(), object::Error> {
+        match self.limit.get() {
+            Some(limit) if self.used.get().saturating_add(bytes) > limit => {
+                Err(object::Error::new("out of memory".to_owned()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// The share of a Meter a value holds. It is taken again when the value is
+// cloned and given back when it is dropped.
+pub struct Charge {
+    meter: Option<Rc<Meter>>,
+    bytes: Cell<usize>,
+}
+
+impl Charge {
+    pub fn new(meter: &Rc<Meter>, bytes: usize) -> Charge {
+        meter.used.set(meter.used.get() + bytes);
+        Charge {
+            meter: Some(meter.clone()),
+            bytes: Cell::new(bytes),
+        }
+    }
+
+    // A charge on no meter, for values made outside of an Executor.
+    pub fn none() -> Charge {
+        Charge {
+            meter: None,
+            bytes: Cell::new(0),
+        }
+    }
+
+    // Accounts for the value growing by `bytes`.
+    pub fn grow(&self, bytes: usize) {
+        if let Some(ref meter) = self.meter {
+            meter.used.set(meter.used.get() + bytes);
+            self.bytes.set(self.bytes.get() + bytes);
+        }
+    }
+}
+
+impl Clone for Charge {
+    fn clone(&self) -> Charge {
+        match self.meter {
+            Some(ref meter) => Charge::new(meter, self.bytes.get()),
+            None => Charge::none(),
+        }
+    }
+}
+
+impl Drop for Charge {
+    fn drop(&mut self) {
+        if let Some(ref meter) = self.meter {
+            meter.used.set(meter.used.get() - self.bytes.get());
+        }
+    }
+}
+
+// Charges never make two values differ.
+impl PartialEq for Charge {
+    fn eq(&self, _: &Charge) -> bool {
+        true
+    }
+}
+
+impl Debug for Charge {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Charge({})", self.bytes.get())
+    }
+}
+
+// Values in a cycle keep each other alive, like a function kept in a
+// variable of the scope it closes over, and dropping them gives none of
+// their memory back. Once a run is over nothing holds the values of
+// scripts but the globals, so the scopes closed over that they do not
+// reach are emptied, which breaks the cycles through them. Cycles made
+// during a run last until it ends, those of arrays and hashes holding
+// themselves for good.
+pub fn collect(globals: &Env) {
+    let mut reached = HashSet::new();
+    let mut scopes = vec![globals.clone()];
+    let mut values = Vec::new();
+    while !scopes.is_empty() || !values.is_empty() {
+        if let Some(scope) = scopes.pop() {
+            if reached.insert(Rc::as_ptr(&scope) as usize) {
+                let (held, out) = scope.borrow().values();
+                values.extend(held);
+                scopes.extend(out);
+            }
+            continue;
+        }
+        match values.pop() {
+            Some(Object::ARRAY(arr)) if reached.insert(Rc::as_ptr(&arr.elements) as usize) => {
+                values.extend(arr.to_vec());
+            }
+            Some(Object::HASH(hash)) if reached.insert(Rc::as_ptr(&hash.pairs) as usize) => {
+                values.extend(hash.pairs.borrow().iter().map(|(_, value)| value.clone()));
+            }
+            Some(Object::CLASS(class)) if reached.insert(Rc::as_ptr(&class) as usize) => {
+                values.extend(class.superclass.clone().map(Object::CLASS));
+                scopes.extend(class.methods.values().map(|method| method.env.clone()));
+            }
+            Some(Object::INSTANCE(instance)) => {
+                values.push(Object::CLASS(instance.class.clone()));
+                values.push(Object::HASH(instance.fields.clone()));
+            }
+            Some(Object::FUNCTION(func)) => scopes.push(func.env.clone()),
+            _ => {}
+        }
+    }
+
+    let runtime = globals.borrow().runtime();
+    for scope in runtime.scopes() {
+        if !reached.contains(&(Rc::as_ptr(&scope) as usize)) {
+            scope.borrow_mut().clear();
+        }
+    }
+    runtime.scopes();
+}
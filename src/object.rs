@@ -1,43 +1,60 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Debug, Display, Formatter};
+use std::mem;
+use std::rc::Rc;
+
 use ast::*;
+use compiler;
+use token::Position;
 use environment::*;
+use memory::{Charge, Meter};
+use types::{FunctionLiteral, Program};
 
 pub trait Objecter {
     fn obj_type(&self) -> ObjectType;
     fn inspect(&self) -> String;
 }
 
+#[allow(non_camel_case_types)]
 #[derive(Debug, PartialEq)]
 pub enum ObjectType {
     INTEGER,
     STRING,
     BOOL,
+    ARRAY,
+    HASH,
+    CLASS,
+    INSTANCE,
     FUNCTION,
     BUILTIN,
     NULL,
     RETURN_VAL,
+    TAIL_CALL,
     ERROR,
 }
 
+// Values are cheap to copy: integers and booleans are held inline, other
+// values by reference. Strings never change, so sharing them cannot be
+// seen, but arrays, hashes and instances are shared like in JS, a change
+// made through one copy is seen through every other.
+#[allow(non_camel_case_types)]
 #[derive(Debug, Clone, PartialEq)]
 pub enum Object {
     INTEGER(Integer),
     BOOL(Boolean),
     STRING(Str),
-    FUNCTION(Func),
+    ARRAY(Array),
+    HASH(Hash),
+    CLASS(Rc<Class>),
+    INSTANCE(Instance),
+    FUNCTION(Rc<Func>),
+    #[allow(dead_code)]
     BUILTIN(BuiltIn),
     RETURN_VAL(Return),
+    TAIL_CALL(Box<TailCall>),
     NULL,
-    ERROR(Error),
-}
-
-impl From<Expression> for Object {
-    fn from(exp: Expression) -> Object {
-        match exp {
-            Expression::INTEGER(v) => Object::INTEGER(Integer { value: v.value }),
-            Expression::STRING(s) => Object::STRING(Str { value: s.value }),
-            _ => Object::NULL,
-        }
-    }
+    ERROR(Box<Error>),
 }
 
 impl Objecter for Object {
@@ -46,9 +63,14 @@ impl Objecter for Object {
             Object::INTEGER(ref int) => int.obj_type(),
             Object::BOOL(ref b) => b.obj_type(),
             Object::STRING(ref s) => s.obj_type(),
+            Object::ARRAY(ref arr) => arr.obj_type(),
+            Object::HASH(ref hash) => hash.obj_type(),
+            Object::CLASS(ref class) => class.obj_type(),
+            Object::INSTANCE(ref instance) => instance.obj_type(),
             Object::FUNCTION(ref f) => f.obj_type(),
             Object::BUILTIN(ref b) => b.obj_type(),
             Object::RETURN_VAL(ref val) => val.obj_type(),
+            Object::TAIL_CALL(ref call) => call.obj_type(),
             Object::NULL => ObjectType::NULL,
             Object::ERROR(ref e) => e.obj_type(),
         }
@@ -59,9 +81,14 @@ impl Objecter for Object {
             Object::INTEGER(ref int) => int.inspect(),
             Object::BOOL(ref b) => b.inspect(),
             Object::STRING(ref s) => s.inspect(),
+            Object::ARRAY(ref arr) => arr.inspect(),
+            Object::HASH(ref hash) => hash.inspect(),
+            Object::CLASS(ref class) => class.inspect(),
+            Object::INSTANCE(ref instance) => instance.inspect(),
             Object::FUNCTION(ref f) => f.inspect(),
             Object::BUILTIN(ref b) => b.inspect(),
             Object::RETURN_VAL(ref val) => val.inspect(),
+            Object::TAIL_CALL(ref call) => call.inspect(),
             Object::NULL => "null".to_owned(),
             Object::ERROR(ref e) => e.inspect(),
         }
@@ -97,9 +124,31 @@ impl Objecter for Boolean {
     }
 }
 
+// Strings never change, copies of a Str share the text and the charge
+// for it.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Str {
-    pub value: String,
+    pub value: Rc<str>,
+    charge: Rc<Charge>,
+}
+
+impl Str {
+    pub fn new(value: String, meter: &Rc<Meter>) -> Str {
+        let charge = Rc::new(Charge::new(meter, value.len()));
+        Str {
+            value: Rc::from(value),
+            charge,
+        }
+    }
+
+    // A string of the code itself, which no run pays for as it lives as long
+    // as the code does.
+    pub fn constant(value: &str) -> Str {
+        Str {
+            value: Rc::from(value),
+            charge: Rc::new(Charge::none()),
+        }
+    }
 }
 
 impl Objecter for Str {
@@ -107,15 +156,316 @@ impl Objecter for Str {
         ObjectType::STRING
     }
     fn inspect(&self) -> String {
-        self.value.clone()
+        self.value.to_string()
+    }
+}
+
+// Arrays are shared by reference like a Hash, an element pushed through
+// one copy is seen through all of them, and two arrays are only equal when
+// they are the same array.
+#[derive(Debug, Clone)]
+pub struct Array {
+    pub elements: Rc<RefCell<Vec<Object>>>,
+    charge: Rc<Charge>,
+}
+
+impl Array {
+    pub fn new(elements: Vec<Object>, meter: &Rc<Meter>) -> Array {
+        let bytes = mem::size_of::<RefCell<Vec<Object>>>() +
+                    elements.len() * mem::size_of::<Object>();
+        Array {
+            elements: Rc::new(RefCell::new(elements)),
+            charge: Rc::new(Charge::new(meter, bytes)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.borrow().len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<Object> {
+        self.elements.borrow().get(index).cloned()
+    }
+
+    // The elements as they are now, later changes to the array are not
+    // seen in the copy.
+    pub fn to_vec(&self) -> Vec<Object> {
+        self.elements.borrow().clone()
+    }
+
+    // The elements, moved out of the array when no other copy holds it.
+    pub fn into_vec(self) -> Vec<Object> {
+        match Rc::try_unwrap(self.elements) {
+            Ok(elements) => elements.into_inner(),
+            Err(elements) => elements.borrow().clone(),
+        }
+    }
+
+    pub fn push(&self, value: Object) {
+        self.charge.grow(mem::size_of::<Object>());
+        self.elements.borrow_mut().push(value);
+    }
+}
+
+impl PartialEq for Array {
+    fn eq(&self, other: &Array) -> bool {
+        Rc::ptr_eq(&self.elements, &other.elements)
+    }
+}
+
+impl Objecter for Array {
+    fn obj_type(&self) -> ObjectType {
+        ObjectType::ARRAY
+    }
+    fn inspect(&self) -> String {
+        let mut out = String::new();
+        let _ = write(&Object::ARRAY(self.clone()), &mut out, |_| Ok(()));
+        out
+    }
+}
+
+// Object literals are shared by reference, copies of a Hash see the
+// properties set through any of them. Properties keep insertion order.
+#[derive(Debug, Clone)]
+pub struct Hash {
+    pub pairs: Rc<RefCell<Vec<(String, Object)>>>,
+    // Shared like the pairs, copies of a Hash hold no memory of their own.
+    charge: Rc<Charge>,
+}
+
+impl Hash {
+    pub fn new(pairs: Vec<(String, Object)>, meter: &Rc<Meter>) -> Hash {
+        let bytes = pairs.iter().map(|(key, _)| Hash::pair_size(key)).sum();
+        Hash {
+            pairs: Rc::new(RefCell::new(pairs)),
+            charge: Rc::new(Charge::new(meter, bytes)),
+        }
+    }
+
+    fn pair_size(key: &str) -> usize {
+        mem::size_of::<(String, Object)>() + key.len()
+    }
+
+    pub fn get(&self, key: &str) -> Option<Object> {
+        self.pairs
+            .borrow()
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.clone())
+    }
+
+    pub fn set(&self, key: &str, value: Object) {
+        let mut pairs = self.pairs.borrow_mut();
+        match pairs.iter_mut().find(|(k, _)| k == key) {
+            Some(pair) => pair.1 = value,
+            None => {
+                self.charge.grow(Hash::pair_size(key));
+                pairs.push((key.to_owned(), value))
+            }
+        }
+    }
+}
+
+impl PartialEq for Hash {
+    fn eq(&self, other: &Hash) -> bool {
+        Rc::ptr_eq(&self.pairs, &other.pairs)
+    }
+}
+
+impl Objecter for Hash {
+    fn obj_type(&self) -> ObjectType {
+        ObjectType::HASH
+    }
+    fn inspect(&self) -> String {
+        let mut out = String::new();
+        let _ = write(&Object::HASH(self.clone()), &mut out, |_| Ok(()));
+        out
+    }
+}
+
+// What is left to write of a value: the value itself, or the rest of an
+// array or hash from the element or pair `next` on.
+enum Part {
+    Value(Object),
+    Elements(Array, usize),
+    Pairs(Hash, usize),
+}
+
+// Writes `value` into `out` like inspect does. Arrays and hashes can hold
+// themselves, where they come up again inside themselves they are written
+// as [...] and {...}. Nested values are kept on a stack of their own, so
+// deep nesting does not overflow the native one. Sharing can still make
+// the text grow exponentially with the values, `check` is called with the
+// length written so far before each value inside an array or hash, and
+// stops the writing by failing.
+pub fn write<F>(value: &Object, out: &mut String, mut check: F) -> Result<(), Error>
+    where F: FnMut(usize) -> Result<(), Error>
+{
+    let mut open = HashSet::new();
+    let mut parts = vec![Part::Value(value.clone())];
+    while let Some(part) = parts.pop() {
+        if let Part::Value(_) = part {
+            if !open.is_empty() {
+                check(out.len())?;
+            }
+        }
+        match part {
+            Part::Value(Object::ARRAY(arr)) => {
+                if open.insert(Rc::as_ptr(&arr.elements) as usize) {
+                    out.push('[');
+                    parts.push(Part::Elements(arr, 0));
+                } else {
+                    out.push_str("[...]");
+                }
+            }
+            Part::Value(Object::HASH(hash)) => {
+                if open.insert(Rc::as_ptr(&hash.pairs) as usize) {
+                    out.push('{');
+                    parts.push(Part::Pairs(hash, 0));
+                } else {
+                    out.push_str("{...}");
+                }
+            }
+            Part::Value(Object::INSTANCE(instance)) => {
+                out.push_str(&instance.class.name);
+                out.push(' ');
+                parts.push(Part::Value(Object::HASH(instance.fields)));
+            }
+            Part::Value(other) => out.push_str(&other.inspect()),
+            Part::Elements(arr, next) => {
+                match arr.get(next) {
+                    Some(element) => {
+                        if next > 0 {
+                            out.push_str(", ");
+                        }
+                        parts.push(Part::Elements(arr, next + 1));
+                        parts.push(Part::Value(element));
+                    }
+                    None => {
+                        open.remove(&(Rc::as_ptr(&arr.elements) as usize));
+                        out.push(']');
+                    }
+                }
+            }
+            Part::Pairs(hash, next) => {
+                let pair = hash.pairs.borrow().get(next).cloned();
+                match pair {
+                    Some((key, value)) => {
+                        if next > 0 {
+                            out.push_str(", ");
+                        }
+                        out.push_str(&key);
+                        out.push_str(": ");
+                        parts.push(Part::Pairs(hash, next + 1));
+                        parts.push(Part::Value(value));
+                    }
+                    None => {
+                        open.remove(&(Rc::as_ptr(&hash.pairs) as usize));
+                        out.push('}');
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct Class {
+    pub name: String,
+    pub superclass: Option<Rc<Class>>,
+    pub methods: HashMap<String, Rc<Func>>,
+}
+
+impl Class {
+    // Looks a method up through the superclass chain.
+    pub fn find_method(&self, name: &str) -> Option<Rc<Func>> {
+        match self.methods.get(name) {
+            Some(method) => Some(method.clone()),
+            None => self.superclass.as_ref().and_then(|sup| sup.find_method(name)),
+        }
+    }
+}
+
+// Classes are only ever equal to themselves.
+impl PartialEq for Class {
+    fn eq(&self, other: &Class) -> bool {
+        ::std::ptr::eq(self, other)
+    }
+}
+
+impl Objecter for Class {
+    fn obj_type(&self) -> ObjectType {
+        ObjectType::CLASS
+    }
+    fn inspect(&self) -> String {
+        format!("class {}", self.name)
     }
 }
 
+// An object created by `new`, its own fields are shared by reference like
+// a Hash and methods are looked up on its class.
 #[derive(Debug, Clone, PartialEq)]
+pub struct Instance {
+    pub class: Rc<Class>,
+    pub fields: Hash,
+}
+
+impl Objecter for Instance {
+    fn obj_type(&self) -> ObjectType {
+        ObjectType::INSTANCE
+    }
+    fn inspect(&self) -> String {
+        let mut out = String::new();
+        let _ = write(&Object::INSTANCE(self.clone()), &mut out, |_| Ok(()));
+        out
+    }
+}
+
+#[derive(Clone)]
 pub struct Func {
-    pub parameters: Vec<Expression>,
-    pub body: Statement,
-    pub env: Environment,
+    // Named after the variable or method it was defined as, if any.
+    pub name: Option<String>,
+    pub body: Body,
+    pub env: Env,
+    pub arrow: bool,
+}
+
+// What a function runs: its syntax tree when the tree-walker made it, its
+// bytecode when the vm did.
+#[derive(Debug, Clone)]
+pub enum Body {
+    Ast(Rc<Program>, FuncId),
+    Bytecode(Rc<compiler::Function>),
+}
+
+impl PartialEq for Body {
+    fn eq(&self, other: &Body) -> bool {
+        match (self, other) {
+            (Body::Ast(program, function), Body::Ast(other, id)) => {
+                Rc::ptr_eq(program, other) && function == id
+            }
+            (Body::Bytecode(function), Body::Bytecode(other)) => Rc::ptr_eq(function, other),
+            _ => false,
+        }
+    }
+}
+
+// Two function values are the same when they share code and closure.
+impl PartialEq for Func {
+    fn eq(&self, other: &Func) -> bool {
+        self.body == other.body && self.arrow == other.arrow && Rc::ptr_eq(&self.env, &other.env)
+    }
+}
+
+impl Debug for Func {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Func")
+            .field("name", &self.name)
+            .field("body", &self.body)
+            .field("arrow", &self.arrow)
+            .finish()
+    }
 }
 
 impl Objecter for Func {
@@ -123,26 +473,43 @@ impl Objecter for Func {
         ObjectType::FUNCTION
     }
     fn inspect(&self) -> String {
-        let mut buff = String::new();
-
-        buff.push_str("function");
-        buff.push('(');
-        for p in self.parameters.clone() {
-            buff.push_str(format!("{}, ", p.to_string()).as_str());
+        match self.body {
+            Body::Ast(ref program, function) => {
+                render_function(&program.ast, &program.ast[function])
+            }
+            Body::Bytecode(ref function) => function.text.clone(),
         }
-        buff.push_str(") {\n");
-        buff.push_str(self.body.to_string().as_str());
-        buff.push_str("\n}");
+    }
+}
 
-        buff
+// How functions print, which compiled functions work out ahead of time.
+pub fn render_function(ast: &Ast, function: &FunctionLiteral) -> String {
+    let mut buff = String::new();
+
+    buff.push_str("function");
+    buff.push('(');
+    for p in &function.parameters {
+        buff.push_str(&p.to_string(ast));
+        buff.push_str(", ");
     }
+    buff.push_str(") {\n");
+    buff.push_str(&function.body.to_string(ast));
+    buff.push_str("\n}");
+
+    buff
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct BuiltIn {
     func: fn(Vec<String>) -> Object,
 }
 
+impl PartialEq for BuiltIn {
+    fn eq(&self, other: &BuiltIn) -> bool {
+        ::std::ptr::fn_addr_eq(self.func, other.func)
+    }
+}
+
 impl Objecter for BuiltIn {
     fn obj_type(&self) -> ObjectType {
         ObjectType::BUILTIN
@@ -166,9 +533,125 @@ impl Objecter for Return {
     }
 }
 
+// The call a function returns the result of, left for its caller to make
+// once the function has returned, so recursing this way takes no stack.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TailCall {
+    pub function: Object,
+    pub args: Vec<Object>,
+    pub this: Option<Object>,
+}
+
+impl Objecter for TailCall {
+    fn obj_type(&self) -> ObjectType {
+        ObjectType::TAIL_CALL
+    }
+    fn inspect(&self) -> String {
+        self.function.inspect()
+    }
+}
+
+// Scripts catch errors of kind Error, running out of a resource or being
+// cancelled by the host stops them whatever they do.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ErrorKind {
+    Error,
+    ResourceExhausted,
+    Cancelled,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Error {
+    pub kind: ErrorKind,
     pub message: String,
+    // What a script passed to `throw`, runtime errors carry none.
+    pub value: Option<Box<Object>>,
+    // Where in the source the error was raised, when it is known.
+    pub position: Option<Position>,
+    // The calls that were running when the error was raised, innermost
+    // first, filled in by the evaluator as the error leaves the node that
+    // raised it.
+    pub trace: Option<Vec<Frame>>,
+}
+
+impl Error {
+    pub fn new(message: String) -> Error {
+        Error {
+            kind: ErrorKind::Error,
+            message,
+            value: None,
+            position: None,
+            trace: None,
+        }
+    }
+
+    pub fn at(message: String, position: Position) -> Error {
+        Error {
+            position: Some(position),
+            ..Error::new(message)
+        }
+    }
+
+    pub fn exhausted(message: String) -> Error {
+        Error {
+            kind: ErrorKind::ResourceExhausted,
+            ..Error::new(message)
+        }
+    }
+
+    pub fn cancelled() -> Error {
+        Error {
+            kind: ErrorKind::Cancelled,
+            ..Error::new("execution cancelled".to_owned())
+        }
+    }
+
+    pub fn is_catchable(&self) -> bool {
+        self.kind == ErrorKind::Error
+    }
+
+    // The message of a thrown object is its `message` property when it has
+    // one, like for errors caught and rethrown.
+    pub fn thrown(value: Object) -> Error {
+        let message = match value {
+            Object::HASH(ref hash) => hash.get("message"),
+            Object::INSTANCE(ref instance) => instance.fields.get("message"),
+            _ => None,
+        };
+        Error {
+            value: Some(Box::new(value.clone())),
+            ..Error::new(message.unwrap_or(value).inspect())
+        }
+    }
+
+    pub fn stack(&self) -> String {
+        format!("Error: {}{}", self.message, self.backtrace())
+    }
+
+    // An `at` line per frame of the trace, or for the position alone when
+    // the error was never traced.
+    fn backtrace(&self) -> String {
+        match (&self.trace, self.position) {
+            (Some(frames), _) => frames.iter().map(|frame| frame.backtrace()).collect(),
+            (None, Some(position)) => format!("\n    at {}", position),
+            (None, None) => String::new(),
+        }
+    }
+
+    // The value a `catch` clause binds, runtime errors are turned into an
+    // object with their `message` and `stack`.
+    pub fn to_object(&self, meter: &Rc<Meter>) -> Object {
+        match self.value {
+            Some(ref value) => *value.clone(),
+            None => {
+                let message = Str::new(self.message.clone(), meter);
+                let stack = Str::new(self.stack(), meter);
+                Object::HASH(Hash::new(vec![("message".to_owned(), Object::STRING(message)),
+                                            ("stack".to_owned(), Object::STRING(stack))],
+                                       meter))
+            }
+        }
+    }
 }
 
 impl Objecter for Error {
@@ -176,6 +659,46 @@ impl Objecter for Error {
         ObjectType::ERROR
     }
     fn inspect(&self) -> String {
-        format!("ERROR: {}", self.message)
+        format!("ERROR: {}{}", self.message, self.backtrace())
+    }
+}
+
+// A call on the stack, `name` is None for the top level of a script.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub name: Option<String>,
+    pub source: String,
+    pub position: Position,
+    // How many tail calls were made before this frame's, whose frames are
+    // gone from the stack.
+    pub elided: usize,
+}
+
+impl Frame {
+    pub fn new(name: Option<String>, source: String) -> Frame {
+        Frame {
+            name,
+            source,
+            position: Position::default(),
+            elided: 0,
+        }
+    }
+
+    // Its line in a trace, and one for the tail calls elided before it.
+    fn backtrace(&self) -> String {
+        match self.elided {
+            0 => format!("\n    at {}", self),
+            1 => format!("\n    at {}\n    ... 1 tail call elided", self),
+            elided => format!("\n    at {}\n    ... {} tail calls elided", self, elided),
+        }
+    }
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.name {
+            Some(ref name) => write!(f, "{} ({}:{})", name, self.source, self.position),
+            None => write!(f, "{}:{}", self.source, self.position),
+        }
     }
 }
@@ -0,0 +1,127 @@
+
+use arena::Id;
+use ast::*;
+use evaluator::{eval_infix_expression, eval_prefix_expression, is_truthy};
+use object::{self, Object};
+use runtime::Runtime;
+use token::Position;
+use types::{self, Program};
+
+// Rewrites `program` to do less work when run: operators on literals are
+// replaced by their result, the branches of an `if` on a literal that can
+// never run are dropped, and `!!x` is read as `x` where only its truthiness
+// counts. Operations that would fail, like an overflow, are left for the
+// program to fail at when run.
+//
+// Nodes are allocated after their children, so a single pass over the
+// arena sees the operands of an expression folded before the expression.
+pub fn optimize(program: &mut Program) {
+    let runtime = Runtime::default();
+    let ast = &mut program.ast;
+    for index in 0..ast.expressions.len() {
+        let exp = ExprId::new(index);
+        if let Some(folded) = fold(exp, ast, &runtime) {
+            ast[exp] = folded;
+            continue;
+        }
+        let mut node = match ast[exp] {
+            Expression::PREFIX(_) | Expression::TERNARY(_) | Expression::IF(_) => ast[exp].clone(),
+            _ => continue,
+        };
+        match node {
+            Expression::PREFIX(ref mut prefix) if prefix.operator == "!" => {
+                prefix.right = truthiness(prefix.right, ast);
+            }
+            Expression::TERNARY(ref mut ternary) => {
+                ternary.condition = truthiness(ternary.condition, ast);
+            }
+            Expression::IF(ref mut if_exp) => {
+                if_exp.condition = truthiness(if_exp.condition, ast);
+                let position = ast.expressions.position(exp);
+                drop_dead_branch(if_exp, position, ast, &runtime);
+            }
+            _ => {}
+        }
+        ast[exp] = node;
+    }
+}
+
+// The result of an operator whose operands are literals, unless it fails.
+fn fold(exp: ExprId, ast: &Ast, runtime: &Runtime) -> Option<Expression> {
+    let result = match ast[exp] {
+        Expression::PREFIX(ref prefix) => {
+            eval_prefix_expression(prefix.operator, literal(prefix.right, ast, runtime)?)?
+        }
+        Expression::INFIX(ref infix) => {
+            let left = literal(infix.left, ast, runtime)?;
+            let right = literal(infix.right, ast, runtime)?;
+            eval_infix_expression(infix.operator, left, right, runtime)?
+        }
+        _ => return None,
+    };
+    match result {
+        Object::INTEGER(int) => {
+            Some(Expression::INTEGER(types::IntegerLiteral { value: int.value }))
+        }
+        Object::STRING(s) => {
+            Some(Expression::STRING(types::StringLiteral { value: s.value.to_string() }))
+        }
+        Object::BOOL(b) => {
+            Some(Expression::BOOL(types::Boolean { value: b == object::Boolean::True }))
+        }
+        _ => None,
+    }
+}
+
+fn literal(exp: ExprId, ast: &Ast, runtime: &Runtime) -> Option<Object> {
+    match ast[exp] {
+        Expression::INTEGER(ref int) => Some(Object::INTEGER(object::Integer { value: int.value })),
+        Expression::STRING(ref s) => {
+            Some(Object::STRING(object::Str::new(s.value.clone(), &runtime.meter())))
+        }
+        Expression::BOOL(ref b) => {
+            Some(Object::BOOL(if b.value {
+                                  object::Boolean::True
+                              } else {
+                                  object::Boolean::False
+                              }))
+        }
+        _ => None,
+    }
+}
+
+// `!!x` is as truthy as `x`, and `!` never fails.
+fn truthiness(mut exp: ExprId, ast: &Ast) -> ExprId {
+    while let Expression::PREFIX(ref outer) = ast[exp] {
+        match ast[outer.right] {
+            Expression::PREFIX(ref inner) if outer.operator == "!" && inner.operator == "!" => {
+                exp = inner.right;
+            }
+            _ => break,
+        }
+    }
+    exp
+}
+
+// An `if` on a literal keeps only the branch it takes, under a condition
+// that is always true, an empty block standing for a missing `else`.
+fn drop_dead_branch(if_exp: &mut types::IfExpression,
+                    position: Position,
+                    ast: &mut Ast,
+                    runtime: &Runtime) {
+    match literal(if_exp.condition, ast, runtime).map(is_truthy) {
+        Some(true) => {}
+        Some(false) => {
+            ast[if_exp.condition] = Expression::BOOL(types::Boolean { value: true });
+            if_exp.consequence = match if_exp.alternative {
+                Some(alt) => alt,
+                None => {
+                    let block = types::BlockStatement { statements: Vec::new() };
+                    ast.statements.alloc(Statement::BLOCK_STMT(block), position)
+                }
+            };
+        }
+        None => return,
+    }
+    if_exp.alternative = None;
+}
@@ -1,8 +1,10 @@
 use std::collections::HashMap;
 use std::fmt::{self, Formatter, Display};
+use std::mem;
 
+use arena::Symbol;
 use lexer::Lexer;
-use token::{Token, TokenType};
+use token::{Position, Token, TokenType};
 use types::*;
 use ast::*;
 
@@ -10,6 +12,8 @@ use ast::*;
 #[allow(non_camel_case_types)]
 enum PrecedenceType {
     LOWEST,
+    ASSIGN,
+    TERNARY,
     EQUALS,
     LESSGREATER,
     SUM,
@@ -18,10 +22,17 @@ enum PrecedenceType {
     CALL,
 }
 
+// How deep statements, expressions and patterns nest at most. Deeper input
+// would overflow the stack of the parser, or of the engines walking what it
+// parsed.
+pub const MAX_DEPTH: usize = 256;
+
 
 lazy_static! {
     static ref PRECEDENCES: HashMap<TokenType, PrecedenceType> = {
         let mut hm = HashMap::new();
+        hm.insert(TokenType::ASSIGN, PrecedenceType::ASSIGN);
+        hm.insert(TokenType::QUESTION, PrecedenceType::TERNARY);
         hm.insert(TokenType::EQ, PrecedenceType::EQUALS);
         hm.insert(TokenType::NOT_EQ, PrecedenceType::EQUALS);
         hm.insert(TokenType::LT, PrecedenceType::LESSGREATER);
@@ -32,30 +43,51 @@ lazy_static! {
         hm.insert(TokenType::ASTERISK, PrecedenceType::PRODUCT);
         hm.insert(TokenType::POW, PrecedenceType::PRODUCT);
         hm.insert(TokenType::LPAREN, PrecedenceType::CALL);
+        hm.insert(TokenType::DOT, PrecedenceType::CALL);
         hm
     };
 }
 
+// The operator a prefix or infix token stands for.
+fn operator(tt: TokenType) -> &'static str {
+    match tt {
+        TokenType::BANG => "!",
+        TokenType::PLUS => "+",
+        TokenType::MINUS => "-",
+        TokenType::ASTERISK => "*",
+        TokenType::SLASH => "/",
+        TokenType::POW => "^",
+        TokenType::EQ => "==",
+        TokenType::NOT_EQ => "!=",
+        TokenType::LT => "<",
+        TokenType::GT => ">",
+        _ => "",
+    }
+}
+
 pub struct Parser {
     lex: Lexer,
     pub cur_token: Token,
     pub peek_token: Token,
     pub errors: Vec<String>,
+    ast: Ast,
+    // How many statements, expressions and patterns are being parsed.
+    depth: usize,
+    // How many errors there were once the input nested too deeply, after
+    // which parsing stops and the errors of unwinding are dropped.
+    too_deep: Option<usize>,
 }
 
 impl Parser {
     pub fn new(l: Lexer) -> Parser {
         let mut parser = Parser {
             lex: l,
-            cur_token: Token {
-                token: TokenType::EOF,
-                literal: String::new(),
-            },
-            peek_token: Token {
-                token: TokenType::EOF,
-                literal: String::new(),
-            },
+            cur_token: Token::new(TokenType::EOF, '\0'),
+            peek_token: Token::new(TokenType::EOF, '\0'),
             errors: Vec::new(),
+            ast: Ast::default(),
+            depth: 0,
+            too_deep: None,
         };
         parser.next_token();
         parser.next_token();
@@ -63,8 +95,8 @@ impl Parser {
         parser
     }
 
-    fn prefix_parse_fns(&mut self, tok: Token) -> Option<Expression> {
-        match tok.token {
+    fn prefix_parse_fns(&mut self, tt: TokenType) -> Option<ExprId> {
+        match tt {
             TokenType::IDENT => self.parse_identifier(),
             TokenType::BANG => self.parse_prefix_expression(),
             TokenType::MINUS => self.parse_prefix_expression(),
@@ -76,13 +108,18 @@ impl Parser {
             TokenType::FUNCTION => self.parse_function(),
             TokenType::STRING => self.parse_string(),
             TokenType::LBRACKET => self.parse_array(),
+            TokenType::LBRACE => self.parse_hash(),
+            TokenType::THIS => self.parse_identifier(),
+            TokenType::SUPER => self.parse_identifier(),
+            TokenType::NEW => self.parse_new_expression(),
             _ => None,
         }
     }
 
-    fn infix_parse_fns(&mut self, tok: Token, exp: Expression) -> Option<Expression> {
-        match tok.token {
+    fn infix_parse_fns(&mut self, tt: TokenType, exp: ExprId) -> Option<ExprId> {
+        match tt {
             TokenType::LPAREN => self.parse_call_expression(exp),
+            TokenType::DOT => self.parse_member_expression(exp),
             TokenType::PLUS => self.parse_infix_expression(exp),
             TokenType::MINUS => self.parse_infix_expression(exp),
             TokenType::SLASH => self.parse_infix_expression(exp),
@@ -92,6 +129,8 @@ impl Parser {
             TokenType::NOT_EQ => self.parse_infix_expression(exp),
             TokenType::LT => self.parse_infix_expression(exp),
             TokenType::GT => self.parse_infix_expression(exp),
+            TokenType::ASSIGN => self.parse_assign_expression(exp),
+            TokenType::QUESTION => self.parse_ternary_expression(exp),
             _ => None,
         }
     }
@@ -111,49 +150,119 @@ impl Parser {
     }
 
     pub fn next_token(&mut self) {
-        self.cur_token = self.peek_token.to_owned();
-        self.peek_token = self.lex.next_token();
+        self.cur_token = mem::replace(&mut self.peek_token, self.lex.next_token());
     }
 
     pub fn parse_program(&mut self) -> Program {
-        let mut program = Program { statements: Vec::new() };
+        let mut statements = Vec::new();
 
-        while !self.current_token_is(TokenType::EOF) {
+        while !self.current_token_is(TokenType::EOF) && self.too_deep.is_none() {
             if let Some(stmt) = self.parse_statement() {
-                program.statements.push(Box::new(stmt));
+                statements.push(stmt);
             }
             self.next_token();
         }
-        program
+        if let Some(count) = self.too_deep {
+            self.errors.truncate(count);
+        }
+        Program {
+            ast: mem::take(&mut self.ast),
+            statements,
+            ..Program::default()
+        }
+    }
+
+    fn expression(&mut self, exp: Expression, position: Position) -> ExprId {
+        self.ast.expressions.alloc(exp, position)
+    }
+
+    fn statement(&mut self, stmt: Statement, position: Position) -> StmtId {
+        self.ast.statements.alloc(stmt, position)
+    }
+
+    fn pattern(&mut self, pattern: Pattern, position: Position) -> PatternId {
+        self.ast.patterns.alloc(pattern, position)
+    }
+
+    // The name the current token spells.
+    fn symbol(&mut self) -> Symbol {
+        self.ast.symbols.intern(&self.cur_token.literal)
     }
 
-    fn parse_statement(&mut self) -> Option<Statement> {
+    fn identifier(&mut self) -> IdentId {
+        let name = self.symbol();
+        self.ast.identifiers.alloc(name, self.cur_token.position)
+    }
+
+    // Runs `parse` a level deeper, giving up with an error past MAX_DEPTH.
+    fn nested<T, F>(&mut self, parse: F) -> Option<T>
+        where F: FnOnce(&mut Parser) -> Option<T>
+    {
+        if !self.deepen() {
+            return None;
+        }
+        let parsed = parse(self);
+        self.depth -= 1;
+        parsed
+    }
+
+    // Goes a level deeper, unless that is past MAX_DEPTH, which stops the
+    // parsing with an error.
+    fn deepen(&mut self) -> bool {
+        if self.too_deep.is_some() {
+            return false;
+        }
+        if self.depth == MAX_DEPTH {
+            let msg = format!("nesting deeper than {} levels at {}",
+                              MAX_DEPTH,
+                              self.cur_token.position);
+            self.errors.push(msg);
+            self.too_deep = Some(self.errors.len());
+            return false;
+        }
+        self.depth += 1;
+        true
+    }
+
+    fn parse_statement(&mut self) -> Option<StmtId> {
+        self.nested(Parser::parse_any_statement)
+    }
+
+    fn parse_any_statement(&mut self) -> Option<StmtId> {
         match self.cur_token.token {
-            TokenType::VAR => self.parse_let_statement(),
+            TokenType::VAR | TokenType::LET | TokenType::CONST => self.parse_let_statement(),
             TokenType::RETURN => self.parse_return_statement(),
+            TokenType::FOR => self.parse_for_statement(),
+            TokenType::CLASS => self.parse_class_statement(),
+            TokenType::THROW => self.parse_throw_statement(),
+            TokenType::TRY => self.parse_try_statement(),
+            // Like in JS, a brace starting a statement opens a block, an
+            // object literal there has to be put in parentheses.
+            TokenType::LBRACE => self.parse_block_statement(),
             _ => self.parse_expression_statement(),
         }
     }
 
-    fn parse_expression_statement(&mut self) -> Option<Statement> {
-        let cur_tok = self.cur_token.clone();
+    fn parse_expression_statement(&mut self) -> Option<StmtId> {
+        let position = self.cur_token.position;
         if let Some(exp) = self.parse_expression(PrecedenceType::LOWEST) {
             if self.peek_token_is(TokenType::SEMICOLON) {
                 self.next_token();
             }
 
-            let exp_stmt = Statement::EXPR_STMT(ExpressionStatement {
-                                                    token: cur_tok,
-                                                    expression: Some(Box::new(exp)),
-                                                });
-            return Some(exp_stmt);
+            let exp_stmt = Statement::EXPR_STMT(ExpressionStatement { expression: Some(exp) });
+            return Some(self.statement(exp_stmt, position));
         }
         None
     }
 
-    fn parse_expression(&mut self, preced: PrecedenceType) -> Option<Expression> {
-        let cur_tok = self.cur_token.clone();
-        let mut left_exp = match self.prefix_parse_fns(cur_tok) {
+    fn parse_expression(&mut self, preced: PrecedenceType) -> Option<ExprId> {
+        self.nested(|parser| parser.parse_operators(preced))
+    }
+
+    // An operand and the operators binding tighter than `preced` after it.
+    fn parse_operators(&mut self, preced: PrecedenceType) -> Option<ExprId> {
+        let mut left_exp = match self.prefix_parse_fns(self.cur_token.token) {
             Some(exp) => exp,
             None => {
                 self.no_prefix_parse_fn_error();
@@ -161,133 +270,477 @@ impl Parser {
             }
         };
 
+        // Each operator folded in nests the operands before it a level
+        // deeper, like `1 + 2 + 3` is `(1 + 2) + 3`.
+        let depth = self.depth;
         while !self.peek_token_is(TokenType::SEMICOLON) && preced < self.peek_precedence() {
-            let peek_tok = self.peek_token.clone();
+            if !self.deepen() {
+                self.depth = depth;
+                return None;
+            }
+            let peek_tok = self.peek_token.token;
             self.next_token();
-            if let Some(infix) = self.infix_parse_fns(peek_tok, left_exp.clone()) {
-                left_exp = infix;
-            } else {
-                return Some(left_exp);
+            match self.infix_parse_fns(peek_tok, left_exp) {
+                Some(infix) => left_exp = infix,
+                None => break,
             }
         }
+        self.depth = depth;
         Some(left_exp)
     }
 
-    fn parse_prefix_expression(&mut self) -> Option<Expression> {
-        let cur_tok = self.cur_token.clone();
-        let cur_op = cur_tok.literal.clone();
+    fn parse_prefix_expression(&mut self) -> Option<ExprId> {
+        let position = self.cur_token.position;
+        let cur_op = operator(self.cur_token.token);
 
         self.next_token();
         if let Some(right) = self.parse_expression(PrecedenceType::PREFIX) {
             let exp = Expression::PREFIX(PrefixExpression {
-                                             token: cur_tok,
                                              operator: cur_op,
-                                             right: Box::new(right),
+                                             right,
                                          });
-            return Some(exp);
+            return Some(self.expression(exp, position));
         }
         None
     }
 
-    fn parse_infix_expression(&mut self, left: Expression) -> Option<Expression> {
+    // Operators, calls and the like start where their leftmost operand does.
+    fn parse_infix_expression(&mut self, left: ExprId) -> Option<ExprId> {
         let preced = self.cur_precedence();
-        let cur_tok = self.cur_token.clone();
-        let cur_op = cur_tok.literal.clone();
+        let cur_op = operator(self.cur_token.token);
 
         self.next_token();
         if let Some(right) = self.parse_expression(preced) {
             let exp = Expression::INFIX(InfixExpression {
-                                            token: cur_tok,
                                             operator: cur_op,
-                                            left: Box::new(left),
-                                            right: Box::new(right),
+                                            left,
+                                            right,
                                         });
-            return Some(exp);
+            let position = self.ast.expressions.position(left);
+            return Some(self.expression(exp, position));
         }
         None
     }
 
-    fn parse_let_statement(&mut self) -> Option<Statement> {
-        let cur_tok = self.cur_token.clone();
-        if !self.expect_peek(TokenType::IDENT) {
+    fn parse_assign_expression(&mut self, left: ExprId) -> Option<ExprId> {
+        let assignable = match self.ast[left] {
+            Expression::IDENT(ident) => {
+                let name = self.ast.name(ident);
+                name != "this" && name != "super"
+            }
+            Expression::MEMBER(_) => true,
+            _ => false,
+        };
+        if !assignable {
+            let msg = format!("invalid assignment target {}", left.to_string(&self.ast));
+            self.errors.push(msg);
             return None;
         }
-        let iden = Identifier {
-            token: self.cur_token.clone(),
-            value: self.cur_token.clone().literal,
-        };
+
+        self.next_token();
+        // Parsed at the lowest precedence so that `a = b = c` groups to the right.
+        if let Some(value) = self.parse_expression(PrecedenceType::LOWEST) {
+            let exp = Expression::ASSIGN(AssignExpression {
+                                             target: left,
+                                             value,
+                                         });
+            let position = self.ast.expressions.position(left);
+            return Some(self.expression(exp, position));
+        }
+        None
+    }
+
+    fn parse_ternary_expression(&mut self, condition: ExprId) -> Option<ExprId> {
+        self.next_token();
+        let consequence = self.parse_expression(PrecedenceType::LOWEST)?;
+        if !self.expect_peek(TokenType::COLON) {
+            return None;
+        }
+        self.next_token();
+        // Like assignments, the alternative extends as far right as possible so
+        // that `a ? b : c ? d : e` nests in the alternative.
+        let alternative = self.parse_expression(PrecedenceType::LOWEST)?;
+
+        let exp = Expression::TERNARY(TernaryExpression {
+                                          condition,
+                                          consequence,
+                                          alternative,
+                                      });
+        let position = self.ast.expressions.position(condition);
+        Some(self.expression(exp, position))
+    }
+
+    fn parse_let_statement(&mut self) -> Option<StmtId> {
+        let position = self.cur_token.position;
+        let kind = self.cur_token.token;
+        self.next_token();
+        let iden = self.parse_pattern()?;
+        let is_ident = matches!(self.ast[iden], Pattern::IDENT(_));
+        if is_ident && kind != TokenType::CONST && self.peek_token_is(TokenType::SEMICOLON) {
+            self.next_token();
+            let stmt = Statement::VAR(VarStatement {
+                                          kind,
+                                          name: iden,
+                                          value: None,
+                                      });
+            return Some(self.statement(stmt, position));
+        }
         if !self.expect_peek(TokenType::ASSIGN) {
             return None;
         }
         self.next_token();
         if let Some(value) = self.parse_expression(PrecedenceType::LOWEST) {
-            while !self.current_token_is(TokenType::SEMICOLON) {
+            if self.peek_token_is(TokenType::SEMICOLON) {
                 self.next_token();
             }
             let stmt = Statement::VAR(VarStatement {
-                                          token: cur_tok.clone(),
+                                          kind,
                                           name: iden,
-                                          value: Some(Box::new(value)),
+                                          value: Some(value),
                                       });
-            return Some(stmt);
+            return Some(self.statement(stmt, position));
         }
         None
     }
 
-    fn parse_integer(&self) -> Option<Expression> {
-        let cur_tok = self.cur_token.clone();
+    // Parses a binding pattern starting at the current token: an identifier,
+    // `[a, b, ...rest]` or `{key, other: pattern}`.
+    fn parse_pattern(&mut self) -> Option<PatternId> {
+        self.nested(Parser::parse_binding_pattern)
+    }
+
+    fn parse_binding_pattern(&mut self) -> Option<PatternId> {
+        let position = self.cur_token.position;
+        match self.cur_token.token {
+            TokenType::IDENT => {
+                let ident = self.identifier();
+                Some(self.pattern(Pattern::IDENT(ident), position))
+            }
+            TokenType::LBRACKET => {
+                let mut elements = Vec::new();
+                let mut rest = None;
+                while !self.peek_token_is(TokenType::RBRACKET) {
+                    self.next_token();
+                    if self.current_token_is(TokenType::ELLIPSIS) {
+                        self.next_token();
+                        rest = Some(self.parse_pattern()?);
+                        break;
+                    }
+                    elements.push(self.parse_pattern()?);
+                    if !self.peek_token_is(TokenType::RBRACKET) &&
+                       !self.expect_peek(TokenType::COMMA) {
+                        return None;
+                    }
+                }
+                if !self.expect_peek(TokenType::RBRACKET) {
+                    return None;
+                }
+                Some(self.pattern(Pattern::ARRAY(ArrayPattern { elements, rest }), position))
+            }
+            TokenType::LBRACE => {
+                let mut properties = Vec::new();
+                while !self.peek_token_is(TokenType::RBRACE) {
+                    if !self.expect_peek(TokenType::IDENT) {
+                        return None;
+                    }
+                    let key = self.symbol();
+                    let value = if self.peek_token_is(TokenType::COLON) {
+                        self.next_token();
+                        self.next_token();
+                        self.parse_pattern()?
+                    } else {
+                        let key_position = self.cur_token.position;
+                        let ident = self.identifier();
+                        self.pattern(Pattern::IDENT(ident), key_position)
+                    };
+                    properties.push((key, value));
+                    if !self.peek_token_is(TokenType::RBRACE) &&
+                       !self.expect_peek(TokenType::COMMA) {
+                        return None;
+                    }
+                }
+                self.next_token();
+                Some(self.pattern(Pattern::OBJECT(ObjectPattern { properties }), position))
+            }
+            _ => {
+                let msg = format!("expect binding pattern, got {:?} instead",
+                                  self.cur_token.token);
+                self.errors.push(msg);
+                None
+            }
+        }
+    }
+
+    fn parse_for_statement(&mut self) -> Option<StmtId> {
+        let position = self.cur_token.position;
+        if !self.expect_peek(TokenType::LPAREN) {
+            return None;
+        }
+        self.next_token();
+        let kind = self.cur_token.token;
+        match kind {
+            TokenType::VAR | TokenType::LET | TokenType::CONST => {}
+            _ => {
+                let msg = format!("expect declaration in for...of head, got {:?} instead",
+                                  kind);
+                self.errors.push(msg);
+                return None;
+            }
+        }
+        self.next_token();
+        let pattern = self.parse_pattern()?;
+
+        // `of` is only a keyword in this position.
+        if !self.peek_token_is(TokenType::IDENT) || self.peek_token.literal != "of" {
+            let msg = format!("expect next token to be of, got {:?} instead",
+                              self.peek_token.token);
+            self.errors.push(msg);
+            return None;
+        }
+        self.next_token();
+        self.next_token();
+        let iterable = self.parse_expression(PrecedenceType::LOWEST)?;
+        if !self.expect_peek(TokenType::RPAREN) || !self.expect_peek(TokenType::LBRACE) {
+            return None;
+        }
+        let body = self.parse_block_statement()?;
+
+        if self.peek_token_is(TokenType::SEMICOLON) {
+            self.next_token();
+        }
+        let stmt = Statement::FOR_OF(ForOfStatement {
+                                         kind,
+                                         pattern,
+                                         iterable,
+                                         body,
+                                     });
+        Some(self.statement(stmt, position))
+    }
+
+    fn parse_class_statement(&mut self) -> Option<StmtId> {
+        let position = self.cur_token.position;
+        if !self.expect_peek(TokenType::IDENT) {
+            return None;
+        }
+        let name = self.identifier();
+
+        let mut superclass = None;
+        if self.peek_token_is(TokenType::EXTENDS) {
+            self.next_token();
+            self.next_token();
+            superclass = Some(self.parse_expression(PrecedenceType::LOWEST)?);
+        }
+        if !self.expect_peek(TokenType::LBRACE) {
+            return None;
+        }
+
+        let mut methods = Vec::new();
+        while !self.peek_token_is(TokenType::RBRACE) {
+            if !self.expect_peek(TokenType::IDENT) {
+                return None;
+            }
+            let method_name = self.symbol();
+            let method_position = self.cur_token.position;
+            if !self.expect_peek(TokenType::LPAREN) {
+                return None;
+            }
+            let parameters = self.parse_function_parameters()?;
+            if !self.expect_peek(TokenType::LBRACE) {
+                return None;
+            }
+            let body = self.parse_block_statement()?;
+            let method = FunctionLiteral {
+                parameters,
+                body,
+                arrow: false,
+            };
+            methods.push((method_name, self.ast.functions.alloc(method, method_position)));
+        }
+        self.next_token();
+
+        if self.peek_token_is(TokenType::SEMICOLON) {
+            self.next_token();
+        }
+        let stmt = Statement::CLASS(ClassStatement {
+                                        name,
+                                        superclass,
+                                        methods,
+                                    });
+        Some(self.statement(stmt, position))
+    }
+
+    fn parse_throw_statement(&mut self) -> Option<StmtId> {
+        let position = self.cur_token.position;
+        self.next_token();
+        let value = self.parse_expression(PrecedenceType::LOWEST)?;
+        if self.peek_token_is(TokenType::SEMICOLON) {
+            self.next_token();
+        }
+        Some(self.statement(Statement::THROW(ThrowStatement { value }), position))
+    }
+
+    fn parse_try_statement(&mut self) -> Option<StmtId> {
+        let position = self.cur_token.position;
+        if !self.expect_peek(TokenType::LBRACE) {
+            return None;
+        }
+        let block = self.parse_block_statement()?;
+
+        let mut param = None;
+        let mut handler = None;
+        if self.peek_token_is(TokenType::CATCH) {
+            self.next_token();
+            // The binding is optional, `catch { }` ignores the error.
+            if self.peek_token_is(TokenType::LPAREN) {
+                self.next_token();
+                self.next_token();
+                param = Some(self.parse_pattern()?);
+                if !self.expect_peek(TokenType::RPAREN) {
+                    return None;
+                }
+            }
+            if !self.expect_peek(TokenType::LBRACE) {
+                return None;
+            }
+            handler = Some(self.parse_block_statement()?);
+        }
+
+        let mut finalizer = None;
+        if self.peek_token_is(TokenType::FINALLY) {
+            self.next_token();
+            if !self.expect_peek(TokenType::LBRACE) {
+                return None;
+            }
+            finalizer = Some(self.parse_block_statement()?);
+        }
+
+        if handler.is_none() && finalizer.is_none() {
+            self.errors.push("missing catch or finally after try".to_owned());
+            return None;
+        }
+
+        if self.peek_token_is(TokenType::SEMICOLON) {
+            self.next_token();
+        }
+        let stmt = Statement::TRY(TryStatement {
+                                      block,
+                                      param,
+                                      handler,
+                                      finalizer,
+                                  });
+        Some(self.statement(stmt, position))
+    }
+
+    fn parse_new_expression(&mut self) -> Option<ExprId> {
+        let position = self.cur_token.position;
+        self.next_token();
+
+        // The class stops at the argument list, but may be a member chain.
+        let mut class = self.parse_expression(PrecedenceType::CALL)?;
+        while self.peek_token_is(TokenType::DOT) {
+            self.next_token();
+            class = self.parse_member_expression(class)?;
+        }
+        if !self.expect_peek(TokenType::LPAREN) {
+            return None;
+        }
+        let arguments = self.parse_expression_list(TokenType::RPAREN)?;
+
+        Some(self.expression(Expression::NEW(NewExpression { class, arguments }), position))
+    }
+
+    fn parse_integer(&mut self) -> Option<ExprId> {
+        let position = self.cur_token.position;
         if let Ok(integer) = self.cur_token.literal.parse::<i64>() {
-            let exp_int = Expression::INTEGER(IntegerLiteral {
-                                                  token: cur_tok,
-                                                  value: integer,
-                                              });
-            return Some(exp_int);
+            let exp_int = Expression::INTEGER(IntegerLiteral { value: integer });
+            return Some(self.expression(exp_int, position));
         };
         None
     }
 
-    fn parse_boolean(&self) -> Option<Expression> {
-        Some(Expression::BOOL(Boolean {
-                                  token: self.cur_token.clone(),
-                                  value: self.current_token_is(TokenType::TRUE),
-                              }))
+    fn parse_boolean(&mut self) -> Option<ExprId> {
+        let value = self.current_token_is(TokenType::TRUE);
+        let position = self.cur_token.position;
+        Some(self.expression(Expression::BOOL(Boolean { value }), position))
     }
 
-    fn parse_string(&self) -> Option<Expression> {
-        Some(Expression::STRING(StringLiteral {
-                                    token: self.cur_token.clone(),
-                                    value: self.cur_token.clone().literal,
-                                }))
+    fn parse_string(&mut self) -> Option<ExprId> {
+        let value = self.cur_token.literal.clone();
+        let position = self.cur_token.position;
+        Some(self.expression(Expression::STRING(StringLiteral { value }), position))
     }
 
-    fn parse_array(&mut self) -> Option<Expression> {
-        let cur_tok = self.cur_token.clone();
+    fn parse_array(&mut self) -> Option<ExprId> {
+        let position = self.cur_token.position;
         if let Some(elems) = self.parse_expression_list(TokenType::RBRACKET) {
-            let arr_exp = Expression::ARRAY(ArrayLiteral {
-                                                token: cur_tok,
-                                                elements: elems,
-                                            });
-            return Some(arr_exp);
+            let arr_exp = Expression::ARRAY(ArrayLiteral { elements: elems });
+            return Some(self.expression(arr_exp, position));
         }
         None
     }
 
-    fn parse_expression_list(&mut self, tt: TokenType) -> Option<Vec<Expression>> {
-        let mut list: Vec<Expression> = Vec::new();
+    fn parse_hash(&mut self) -> Option<ExprId> {
+        let position = self.cur_token.position;
+        let mut pairs = Vec::new();
+
+        while !self.peek_token_is(TokenType::RBRACE) {
+            self.next_token();
+            let key_type = self.cur_token.token;
+            if key_type != TokenType::IDENT && key_type != TokenType::STRING {
+                let msg = format!("expect property name, got {:?} instead", key_type);
+                self.errors.push(msg);
+                return None;
+            }
+            let key = self.symbol();
+
+            let value = if self.peek_token_is(TokenType::COLON) {
+                self.next_token();
+                self.next_token();
+                self.parse_expression(PrecedenceType::LOWEST)?
+            } else if key_type == TokenType::IDENT {
+                // `{name}` is short for `{name: name}`.
+                self.parse_identifier()?
+            } else {
+                self.peek_error(TokenType::COLON);
+                return None;
+            };
+            pairs.push((key, value));
+
+            if !self.peek_token_is(TokenType::RBRACE) && !self.expect_peek(TokenType::COMMA) {
+                return None;
+            }
+        }
+        self.next_token();
+
+        Some(self.expression(Expression::HASH(HashLiteral { pairs }), position))
+    }
+
+    fn parse_member_expression(&mut self, object: ExprId) -> Option<ExprId> {
+        if !self.expect_peek(TokenType::IDENT) {
+            return None;
+        }
+        let property = self.symbol();
+        let position = self.ast.expressions.position(object);
+        Some(self.expression(Expression::MEMBER(MemberExpression { object, property }),
+                             position))
+    }
+
+    fn parse_expression_list(&mut self, tt: TokenType) -> Option<Vec<ExprId>> {
+        let mut list: Vec<ExprId> = Vec::new();
         if self.peek_token_is(tt) {
             self.next_token();
             return Some(list);
         }
 
         self.next_token();
-        if let Some(exp) = self.parse_expression(PrecedenceType::LOWEST) {
+        if let Some(exp) = self.parse_list_element() {
             list.push(exp);
         };
 
         while self.peek_token_is(TokenType::COMMA) {
             self.next_token();
             self.next_token();
-            if let Some(exp) = self.parse_expression(PrecedenceType::LOWEST) {
+            if let Some(exp) = self.parse_list_element() {
                 list.push(exp);
             };
         }
@@ -297,120 +750,193 @@ impl Parser {
         Some(list)
     }
 
-    fn parse_identifier(&self) -> Option<Expression> {
-        Some(Expression::IDENT(Identifier {
-                                   token: self.cur_token.clone(),
-                                   value: self.cur_token.clone().literal,
-                               }))
+    // Elements of call arguments and array literals may be spread with `...`.
+    fn parse_list_element(&mut self) -> Option<ExprId> {
+        if !self.current_token_is(TokenType::ELLIPSIS) {
+            return self.parse_expression(PrecedenceType::LOWEST);
+        }
+        let position = self.cur_token.position;
+        self.next_token();
+        let value = self.parse_expression(PrecedenceType::LOWEST)?;
+        Some(self.expression(Expression::SPREAD(SpreadElement { value }), position))
+    }
+
+    fn parse_identifier(&mut self) -> Option<ExprId> {
+        if self.peek_token_is(TokenType::ARROW) {
+            return self.parse_arrow_function();
+        }
+        let position = self.cur_token.position;
+        let ident = self.identifier();
+        Some(self.expression(Expression::IDENT(ident), position))
     }
 
-    fn parse_function(&mut self) -> Option<Expression> {
-        let cur_tok = self.cur_token.clone();
+    fn parse_function(&mut self) -> Option<ExprId> {
+        let position = self.cur_token.position;
         if !self.expect_peek(TokenType::LPAREN) {
             return None;
         }
 
-        let params = self.parse_function_parameters().unwrap();
+        let params = self.parse_function_parameters()?;
         if !self.expect_peek(TokenType::LBRACE) {
             return None;
         }
         if let Some(body) = self.parse_block_statement() {
-            let func_exp = Expression::FUNC(FunctionLiteral {
-                                                token: cur_tok,
-                                                parameters: params,
-                                                body: body,
-                                            });
-            return Some(func_exp);
+            let func = self.ast.functions.alloc(FunctionLiteral {
+                                                    parameters: params,
+                                                    body,
+                                                    arrow: false,
+                                                },
+                                                position);
+            return Some(self.expression(Expression::FUNC(func), position));
         }
         None
     }
 
-    fn parse_function_parameters(&mut self) -> Option<Vec<Expression>> {
-        let mut idents: Vec<Expression> = Vec::new();
+    // Parses `(a, b) => body` from its `(` or `x => body` from its identifier.
+    fn parse_arrow_function(&mut self) -> Option<ExprId> {
+        let params = if self.current_token_is(TokenType::LPAREN) {
+            self.parse_function_parameters()?
+        } else {
+            let position = self.cur_token.position;
+            let ident = self.identifier();
+            vec![Parameter {
+                     name: self.pattern(Pattern::IDENT(ident), position),
+                     default: None,
+                     rest: false,
+                 }]
+        };
+        if !self.expect_peek(TokenType::ARROW) {
+            return None;
+        }
+        let position = self.cur_token.position;
+        self.next_token();
+
+        // An expression body is sugar for a block returning it.
+        let body = if self.current_token_is(TokenType::LBRACE) {
+            self.parse_block_statement()?
+        } else {
+            let value = self.parse_expression(PrecedenceType::LOWEST)?;
+            let rtn = Statement::RETURN(ReturnStatement { return_value: Some(value) });
+            let statements = vec![self.statement(rtn, position)];
+            self.statement(Statement::BLOCK_STMT(BlockStatement { statements }), position)
+        };
+
+        let func = self.ast.functions.alloc(FunctionLiteral {
+                                                parameters: params,
+                                                body,
+                                                arrow: true,
+                                            },
+                                            position);
+        Some(self.expression(Expression::FUNC(func), position))
+    }
+
+    // Looks past the parenthesis at the current token to tell an arrow
+    // function parameter list from a grouped expression.
+    fn is_arrow_ahead(&self) -> bool {
+        let mut lex = self.lex.clone();
+        let mut tt = self.peek_token.token;
+        let mut depth = 1;
+
+        loop {
+            match tt {
+                TokenType::LPAREN => depth += 1,
+                TokenType::RPAREN => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return lex.next_token().token == TokenType::ARROW;
+                    }
+                }
+                TokenType::EOF => return false,
+                _ => {}
+            }
+            tt = lex.next_token().token;
+        }
+    }
+
+    fn parse_function_parameters(&mut self) -> Option<Vec<Parameter>> {
+        let mut params: Vec<Parameter> = Vec::new();
         if self.peek_token_is(TokenType::RPAREN) {
             self.next_token();
-            return Some(idents);
+            return Some(params);
         }
         self.next_token();
-        let ident = Expression::IDENT(Identifier {
-                                          token: self.cur_token.clone(),
-                                          value: self.cur_token.clone().literal,
-                                      });
-        idents.push(ident);
+        params.push(self.parse_parameter()?);
 
         while self.peek_token_is(TokenType::COMMA) {
+            if params.last().is_some_and(|p| p.rest) {
+                self.errors.push("rest parameter must be the last parameter".to_owned());
+                return None;
+            }
             self.next_token();
             self.next_token();
-            let ident = Expression::IDENT(Identifier {
-                                              token: self.cur_token.clone(),
-                                              value: self.cur_token.clone().literal,
-                                          });
-            idents.push(ident);
+            params.push(self.parse_parameter()?);
         }
 
         if !self.expect_peek(TokenType::RPAREN) {
             return None;
         }
-        Some(idents)
+        Some(params)
+    }
+
+    fn parse_parameter(&mut self) -> Option<Parameter> {
+        let rest = self.current_token_is(TokenType::ELLIPSIS);
+        if rest {
+            self.next_token();
+        }
+        let name = self.parse_pattern()?;
+
+        let mut default = None;
+        if !rest && self.peek_token_is(TokenType::ASSIGN) {
+            self.next_token();
+            self.next_token();
+            default = Some(self.parse_expression(PrecedenceType::LOWEST)?);
+        }
+
+        Some(Parameter {
+                 name,
+                 default,
+                 rest,
+             })
     }
 
-    fn parse_return_statement(&mut self) -> Option<Statement> {
-        let cur_tok = self.cur_token.clone();
+    fn parse_return_statement(&mut self) -> Option<StmtId> {
+        let position = self.cur_token.position;
+        // A bare `return;` or `return }` has no value.
+        if self.peek_token_is(TokenType::SEMICOLON) || self.peek_token_is(TokenType::RBRACE) {
+            if self.peek_token_is(TokenType::SEMICOLON) {
+                self.next_token();
+            }
+            let rtn_stmt = Statement::RETURN(ReturnStatement { return_value: None });
+            return Some(self.statement(rtn_stmt, position));
+        }
         self.next_token();
 
         if let Some(ret_val) = self.parse_expression(PrecedenceType::LOWEST) {
-            while !self.current_token_is(TokenType::SEMICOLON) {
+            if self.peek_token_is(TokenType::SEMICOLON) {
                 self.next_token();
             }
-            let rtn_stmt = Statement::RETURN(ReturnStatement {
-                                                 token: cur_tok,
-                                                 return_value: Some(Box::new(ret_val)),
-                                             });
-            return Some(rtn_stmt);
+            let rtn_stmt = Statement::RETURN(ReturnStatement { return_value: Some(ret_val) });
+            return Some(self.statement(rtn_stmt, position));
         };
         None
     }
 
-    fn parse_call_expression(&mut self, func: Expression) -> Option<Expression> {
-        let cur_tok = self.cur_token.clone();
+    fn parse_call_expression(&mut self, func: ExprId) -> Option<ExprId> {
         if let Some(arguments) = self.parse_expression_list(TokenType::RPAREN) {
             let call_exp = Expression::CALL(CallExpression {
-                                                token: cur_tok,
-                                                function: Box::new(func),
-                                                arguments: arguments,
+                                                function: func,
+                                                arguments,
                                             });
-            return Some(call_exp);
+            let position = self.ast.expressions.position(func);
+            return Some(self.expression(call_exp, position));
         }
         None
     }
 
-    fn parse_call_arguments(&mut self) -> Option<Vec<Expression>> {
-        let mut args: Vec<Expression> = Vec::new();
-        if self.peek_token_is(TokenType::RPAREN) {
-            self.next_token();
-            return Some(args);
-        }
-        self.next_token();
-
-        if let Some(arg) = self.parse_expression(PrecedenceType::LOWEST) {
-            args.push(arg);
-        }
-
-        while self.peek_token_is(TokenType::COMMA) {
-            self.next_token();
-            self.next_token();
-            if let Some(arg) = self.parse_expression(PrecedenceType::LOWEST) {
-                args.push(arg);
-            }
+    fn parse_group_expression(&mut self) -> Option<ExprId> {
+        if self.is_arrow_ahead() {
+            return self.parse_arrow_function();
         }
-
-        if !self.expect_peek(TokenType::RPAREN) {
-            return None;
-        }
-        Some(args)
-    }
-
-    fn parse_group_expression(&mut self) -> Option<Expression> {
         self.next_token();
         let exp = self.parse_expression(PrecedenceType::LOWEST);
 
@@ -420,51 +946,52 @@ impl Parser {
         exp
     }
 
-    fn parse_if_expression(&mut self) -> Option<Expression> {
-        let cur_tok = self.cur_token.clone();
+    fn parse_if_expression(&mut self) -> Option<ExprId> {
+        let position = self.cur_token.position;
         if !self.expect_peek(TokenType::LPAREN) {
             return None;
         }
         self.next_token();
-        let exp_cond = self.parse_expression(PrecedenceType::LOWEST);
+        let exp_cond = self.parse_expression(PrecedenceType::LOWEST)?;
         if !self.expect_peek(TokenType::RPAREN) || !self.expect_peek(TokenType::LBRACE) {
             return None;
         }
 
-        let exp_cons = self.parse_block_statement();
+        let exp_cons = self.parse_block_statement()?;
         let mut exp_alt = None;
         if self.peek_token_is(TokenType::ELSE) {
             self.next_token();
             if !self.expect_peek(TokenType::LBRACE) {
                 return None;
             }
-            exp_alt = self.parse_block_statement();
+            exp_alt = Some(self.parse_block_statement()?);
         }
 
-        Some(Expression::IF(IfExpression {
-                                token: cur_tok,
-                                condition: Box::new(exp_cond.unwrap()),
-                                consequence: Box::new(exp_cons.unwrap()),
-                                alternative: exp_alt,
-                            }))
+        let exp = Expression::IF(IfExpression {
+                                     condition: exp_cond,
+                                     consequence: exp_cons,
+                                     alternative: exp_alt,
+                                 });
+        Some(self.expression(exp, position))
     }
 
-    fn parse_block_statement(&mut self) -> Option<Statement> {
-        let cur_tok = self.cur_token.clone();
-        let mut statements: Vec<Statement> = Vec::new();
+    fn parse_block_statement(&mut self) -> Option<StmtId> {
+        let position = self.cur_token.position;
+        let mut statements: Vec<StmtId> = Vec::new();
         self.next_token();
 
         while !self.current_token_is(TokenType::RBRACE) {
+            if self.current_token_is(TokenType::EOF) {
+                self.errors.push("expect RBRACE to close block, got EOF instead".to_owned());
+                return None;
+            }
             if let Some(stmt) = self.parse_statement() {
                 statements.push(stmt);
             }
             self.next_token();
         }
-        let block = Statement::BLOCK_STMT(BlockStatement {
-                                              token: cur_tok,
-                                              statements: statements,
-                                          });
-        Some(block)
+        let block = Statement::BLOCK_STMT(BlockStatement { statements });
+        Some(self.statement(block, position))
     }
 
     fn current_token_is(&self, tt: TokenType) -> bool {
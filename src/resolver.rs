@@ -0,0 +1,525 @@
+use std::collections::HashMap;
+use std::mem;
+
+use arena::{NodeMap, Symbol};
+use ast::*;
+use environment::ScopeKind;
+use token::TokenType;
+use types::Slot;
+
+// A name declared in a scope. Lexical ones, from `let`, `const` and
+// classes, cannot be used before their declaration has run.
+struct Declared {
+    index: usize,
+    lexical: bool,
+    initialized: bool,
+}
+
+// Mirrors an environment the evaluator creates, in the same order, so
+// that the distance between two scopes is the same at runtime.
+struct Scope {
+    kind: ScopeKind,
+    names: HashMap<Symbol, Declared>,
+}
+
+impl Scope {
+    fn new(kind: ScopeKind) -> Scope {
+        Scope {
+            kind,
+            names: HashMap::new(),
+        }
+    }
+}
+
+// Finds the slot every variable that is not a global lives in, so it is
+// found without looking its name up in each enclosing scope, and reports
+// the declarations that conflict and the variables used before their
+// declaration. Globals stay looked up by name, as scripts run by an
+// Executor share them. It also finds the calls a function returns the
+// result of, which can be made once the function has returned.
+pub struct Resolver<'a> {
+    ast: &'a Ast,
+    scopes: Vec<Scope>,
+    slots: NodeMap<IdentId, Slot>,
+    // Whether a `return` here ends the call it is in, which it does not in
+    // a try block or in a catch block with a finally block.
+    tail: bool,
+    pub tail_calls: NodeMap<ExprId, ()>,
+    pub errors: Vec<String>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(ast: &'a Ast) -> Resolver<'a> {
+        Resolver {
+            ast,
+            scopes: Vec::new(),
+            slots: NodeMap::default(),
+            tail: false,
+            tail_calls: NodeMap::default(),
+            errors: Vec::new(),
+        }
+    }
+
+    // The slots of the identifiers of the program, those missing from it
+    // being looked up by name.
+    pub fn resolve_program(&mut self, statements: &[StmtId]) -> NodeMap<IdentId, Slot> {
+        self.scopes = vec![Scope::new(ScopeKind::Function)];
+        self.hoist_vars(statements);
+        self.hoist_lexical(statements);
+        for &stmt in statements {
+            self.resolve_statement(stmt);
+        }
+        self.scopes.clear();
+        mem::take(&mut self.slots)
+    }
+
+    fn declare(&mut self, scope: usize, ident: IdentId, lexical: bool) {
+        let ast = self.ast;
+        let names = &mut self.scopes[scope].names;
+        let index = names.len();
+        if let Some(declared) = names.get(&ast[ident]) {
+            if lexical || declared.lexical {
+                self.redeclared(ident);
+            }
+            return;
+        }
+        names.insert(ast[ident],
+                     Declared {
+                         index,
+                         lexical,
+                         initialized: !lexical,
+                     });
+    }
+
+    fn redeclared(&mut self, ident: IdentId) {
+        self.errors.push(format!("identifier '{}' has already been declared at {}",
+                                 self.ast.name(ident),
+                                 self.ast.identifiers.position(ident)));
+    }
+
+    // Gives `ident` the slot of its name in `scope`, if it has one.
+    fn assign_slot(&mut self, ident: IdentId, scope: usize) {
+        if scope == 0 {
+            return;
+        }
+        if let Some(declared) = self.scopes[scope].names.get(&self.ast[ident]) {
+            let slot = Slot {
+                depth: self.scopes.len() - 1 - scope,
+                index: declared.index,
+            };
+            self.slots.insert(ident, slot);
+        }
+    }
+
+    fn function_scope(&self) -> usize {
+        self.scopes.iter().rposition(|scope| scope.kind == ScopeKind::Function).unwrap_or(0)
+    }
+
+    // `var` declarations belong to the whole function they are in, even
+    // when they are nested in blocks.
+    fn hoist_vars(&mut self, statements: &[StmtId]) {
+        let mut names = Vec::new();
+        for &stmt in statements {
+            vars_in_statement(self.ast, stmt, &mut names);
+        }
+        let scope = self.function_scope();
+        for ident in names {
+            self.declare(scope, ident, false);
+        }
+    }
+
+    // `let`, `const` and classes belong to the block they are directly in.
+    fn hoist_lexical(&mut self, statements: &[StmtId]) {
+        let ast = self.ast;
+        let scope = self.scopes.len() - 1;
+        for &stmt in statements {
+            match ast[stmt] {
+                Statement::VAR(ref var_stmt) if var_stmt.kind != TokenType::VAR => {
+                    let mut names = Vec::new();
+                    pattern_names(ast, var_stmt.name, &mut names);
+                    for ident in names {
+                        self.declare(scope, ident, true);
+                    }
+                }
+                Statement::CLASS(ref class_stmt) => self.declare(scope, class_stmt.name, true),
+                _ => {}
+            }
+        }
+    }
+
+    fn lookup(&mut self, ident: IdentId) {
+        let ast = self.ast;
+        let name = ast.name(ident);
+        if name == "this" || name == "super" {
+            return;
+        }
+        let mut in_function = false;
+        for scope in (0..self.scopes.len()).rev() {
+            if let Some(declared) = self.scopes[scope].names.get(&ast[ident]) {
+                // Functions may run once the declaration has, so only uses
+                // from the same function are known to come too early.
+                if declared.lexical && !declared.initialized && !in_function {
+                    self.errors.push(format!("cannot access '{}' before its declaration at {}",
+                                             name,
+                                             ast.identifiers.position(ident)));
+                }
+                self.assign_slot(ident, scope);
+                return;
+            }
+            in_function |= self.scopes[scope].kind == ScopeKind::Function;
+        }
+    }
+
+    // Binds the name of `ident`, declared already in `scope`, which lexical
+    // ones can be used from then on.
+    fn initialize(&mut self, ident: IdentId, scope: usize) {
+        if let Some(declared) = self.scopes[scope].names.get_mut(&self.ast[ident]) {
+            declared.initialized = true;
+        }
+        self.assign_slot(ident, scope);
+    }
+
+    // Binds the names of `pattern` in the scope `kind` declares them in.
+    fn bind(&mut self, pattern: PatternId, kind: TokenType) {
+        let ast = self.ast;
+        match ast[pattern] {
+            Pattern::IDENT(ident) => {
+                let scope = if kind == TokenType::VAR {
+                    self.function_scope()
+                } else {
+                    self.scopes.len() - 1
+                };
+                self.initialize(ident, scope);
+            }
+            Pattern::ARRAY(ref arr) => {
+                for &element in &arr.elements {
+                    self.bind(element, kind);
+                }
+                if let Some(rest) = arr.rest {
+                    self.bind(rest, kind);
+                }
+            }
+            Pattern::OBJECT(ref obj) => {
+                for &(_, pattern) in &obj.properties {
+                    self.bind(pattern, kind);
+                }
+            }
+        }
+    }
+
+    // Declares and binds the names of a pattern in a scope of its own, like
+    // the parameter of a catch block or the head of a for...of loop.
+    fn declare_in_scope(&mut self, pattern: PatternId, kind: TokenType) {
+        if kind != TokenType::VAR {
+            let scope = self.scopes.len() - 1;
+            let mut names = Vec::new();
+            pattern_names(self.ast, pattern, &mut names);
+            for ident in names {
+                self.declare(scope, ident, true);
+            }
+        }
+        self.bind(pattern, kind);
+    }
+
+    fn resolve_statement(&mut self, stmt: StmtId) {
+        let ast = self.ast;
+        match ast[stmt] {
+            Statement::VAR(ref var_stmt) => {
+                if let Some(value) = var_stmt.value {
+                    self.resolve_expression(value);
+                }
+                self.bind(var_stmt.name, var_stmt.kind);
+            }
+            Statement::EXPR_STMT(ref exp_stmt) => {
+                if let Some(exp) = exp_stmt.expression {
+                    self.resolve_expression(exp);
+                }
+            }
+            Statement::BLOCK_STMT(ref block) => {
+                self.scopes.push(Scope::new(ScopeKind::Block));
+                self.hoist_lexical(&block.statements);
+                for &stmt in &block.statements {
+                    self.resolve_statement(stmt);
+                }
+                self.scopes.pop();
+            }
+            Statement::RETURN(ref ret) => {
+                if let Some(value) = ret.return_value {
+                    self.resolve_expression(value);
+                    if self.tail {
+                        self.find_tail_calls(value);
+                    }
+                }
+            }
+            Statement::FOR_OF(ref for_stmt) => {
+                self.resolve_expression(for_stmt.iterable);
+                self.scopes.push(Scope::new(ScopeKind::Block));
+                self.declare_in_scope(for_stmt.pattern, for_stmt.kind);
+                self.resolve_statement(for_stmt.body);
+                self.scopes.pop();
+            }
+            Statement::CLASS(ref class_stmt) => {
+                if let Some(superclass) = class_stmt.superclass {
+                    self.resolve_expression(superclass);
+                }
+                // The scope binding `super` the methods close over.
+                self.scopes.push(Scope::new(ScopeKind::Block));
+                for &(_, method) in &class_stmt.methods {
+                    self.resolve_function(method);
+                }
+                self.scopes.pop();
+                let scope = self.scopes.len() - 1;
+                self.initialize(class_stmt.name, scope);
+            }
+            Statement::THROW(ref throw) => self.resolve_expression(throw.value),
+            Statement::TRY(ref try_stmt) => {
+                let tail = self.tail;
+                self.tail = false;
+                self.resolve_statement(try_stmt.block);
+                if let Some(handler) = try_stmt.handler {
+                    self.tail = tail && try_stmt.finalizer.is_none();
+                    self.scopes.push(Scope::new(ScopeKind::Block));
+                    if let Some(param) = try_stmt.param {
+                        self.declare_in_scope(param, TokenType::LET);
+                    }
+                    self.resolve_statement(handler);
+                    self.scopes.pop();
+                }
+                self.tail = tail;
+                if let Some(finalizer) = try_stmt.finalizer {
+                    self.resolve_statement(finalizer);
+                }
+            }
+        }
+    }
+
+    // Parameters are bound one after the other, defaults seeing those
+    // before them, in the scope of the call the body block is nested in.
+    fn resolve_function(&mut self, func: FuncId) {
+        let ast = self.ast;
+        let func = &ast[func];
+        self.scopes.push(Scope::new(ScopeKind::Function));
+        let tail = mem::replace(&mut self.tail, true);
+        let scope = self.scopes.len() - 1;
+        for param in &func.parameters {
+            if let Some(default) = param.default {
+                self.resolve_expression(default);
+            }
+            let mut names = Vec::new();
+            pattern_names(ast, param.name, &mut names);
+            for ident in names {
+                // Parameters are declared like `var`s, which the body may
+                // redeclare, but no two of them can have the same name.
+                if self.scopes[scope].names.contains_key(&ast[ident]) {
+                    self.redeclared(ident);
+                }
+                self.declare(scope, ident, false);
+            }
+            self.bind(param.name, TokenType::VAR);
+        }
+        if let Statement::BLOCK_STMT(ref block) = ast[func.body] {
+            self.hoist_vars(&block.statements);
+        }
+        self.resolve_statement(func.body);
+        self.tail = tail;
+        self.scopes.pop();
+    }
+
+    // The calls a returned value ends with, but `super(...)`, which returns
+    // the instance being built rather than its result.
+    fn find_tail_calls(&mut self, exp: ExprId) {
+        let ast = self.ast;
+        match ast[exp] {
+            Expression::CALL(ref call) => {
+                let is_super = match ast[call.function] {
+                    Expression::IDENT(ident) => ast.name(ident) == "super",
+                    _ => false,
+                };
+                if !is_super {
+                    self.tail_calls.insert(exp, ());
+                }
+            }
+            Expression::TERNARY(ref ternary) => {
+                self.find_tail_calls(ternary.consequence);
+                self.find_tail_calls(ternary.alternative);
+            }
+            _ => {}
+        }
+    }
+
+    fn resolve_expression(&mut self, exp: ExprId) {
+        let ast = self.ast;
+        match ast[exp] {
+            Expression::IDENT(ident) => self.lookup(ident),
+            Expression::BOOL(_) | Expression::INTEGER(_) | Expression::STRING(_) => {}
+            Expression::ARRAY(ref arr) => self.resolve_expressions(&arr.elements),
+            Expression::PREFIX(ref prefix) => self.resolve_expression(prefix.right),
+            Expression::INFIX(ref infix) => {
+                self.resolve_expression(infix.left);
+                self.resolve_expression(infix.right);
+            }
+            Expression::IF(ref if_exp) => {
+                self.resolve_expression(if_exp.condition);
+                self.resolve_statement(if_exp.consequence);
+                if let Some(alternative) = if_exp.alternative {
+                    self.resolve_statement(alternative);
+                }
+            }
+            Expression::FUNC(func) => self.resolve_function(func),
+            Expression::CALL(ref call) => {
+                self.resolve_expression(call.function);
+                self.resolve_expressions(&call.arguments);
+            }
+            Expression::ASSIGN(ref assign) => {
+                self.resolve_expression(assign.value);
+                self.resolve_expression(assign.target);
+            }
+            Expression::SPREAD(ref spread) => self.resolve_expression(spread.value),
+            Expression::TERNARY(ref ternary) => {
+                self.resolve_expression(ternary.condition);
+                self.resolve_expression(ternary.consequence);
+                self.resolve_expression(ternary.alternative);
+            }
+            Expression::HASH(ref hash) => {
+                for &(_, value) in &hash.pairs {
+                    self.resolve_expression(value);
+                }
+            }
+            Expression::MEMBER(ref member) => self.resolve_expression(member.object),
+            Expression::NEW(ref new) => {
+                self.resolve_expression(new.class);
+                self.resolve_expressions(&new.arguments);
+            }
+        }
+    }
+
+    fn resolve_expressions(&mut self, exps: &[ExprId]) {
+        for &exp in exps {
+            self.resolve_expression(exp);
+        }
+    }
+}
+
+fn pattern_names(ast: &Ast, pattern: PatternId, names: &mut Vec<IdentId>) {
+    match ast[pattern] {
+        Pattern::IDENT(ident) => names.push(ident),
+        Pattern::ARRAY(ref arr) => {
+            for &element in &arr.elements {
+                pattern_names(ast, element, names);
+            }
+            if let Some(rest) = arr.rest {
+                pattern_names(ast, rest, names);
+            }
+        }
+        Pattern::OBJECT(ref obj) => {
+            for &(_, pattern) in &obj.properties {
+                pattern_names(ast, pattern, names);
+            }
+        }
+    }
+}
+
+// The names `var` declarations in `stmt` introduce, leaving out those of
+// the functions it defines.
+fn vars_in_statement(ast: &Ast, stmt: StmtId, names: &mut Vec<IdentId>) {
+    match ast[stmt] {
+        Statement::VAR(ref var_stmt) => {
+            if var_stmt.kind == TokenType::VAR {
+                pattern_names(ast, var_stmt.name, names);
+            }
+            if let Some(value) = var_stmt.value {
+                vars_in_expression(ast, value, names);
+            }
+        }
+        Statement::EXPR_STMT(ref exp_stmt) => {
+            if let Some(exp) = exp_stmt.expression {
+                vars_in_expression(ast, exp, names);
+            }
+        }
+        Statement::BLOCK_STMT(ref block) => {
+            for &stmt in &block.statements {
+                vars_in_statement(ast, stmt, names);
+            }
+        }
+        Statement::RETURN(ref ret) => {
+            if let Some(value) = ret.return_value {
+                vars_in_expression(ast, value, names);
+            }
+        }
+        Statement::FOR_OF(ref for_stmt) => {
+            if for_stmt.kind == TokenType::VAR {
+                pattern_names(ast, for_stmt.pattern, names);
+            }
+            vars_in_expression(ast, for_stmt.iterable, names);
+            vars_in_statement(ast, for_stmt.body, names);
+        }
+        Statement::CLASS(ref class_stmt) => {
+            if let Some(superclass) = class_stmt.superclass {
+                vars_in_expression(ast, superclass, names);
+            }
+        }
+        Statement::THROW(ref throw) => vars_in_expression(ast, throw.value, names),
+        Statement::TRY(ref try_stmt) => {
+            vars_in_statement(ast, try_stmt.block, names);
+            if let Some(handler) = try_stmt.handler {
+                vars_in_statement(ast, handler, names);
+            }
+            if let Some(finalizer) = try_stmt.finalizer {
+                vars_in_statement(ast, finalizer, names);
+            }
+        }
+    }
+}
+
+// Blocks of `if` expressions can declare variables wherever they are.
+fn vars_in_expression(ast: &Ast, exp: ExprId, names: &mut Vec<IdentId>) {
+    match ast[exp] {
+        Expression::IDENT(_) | Expression::BOOL(_) | Expression::INTEGER(_) |
+        Expression::STRING(_) | Expression::FUNC(_) => {}
+        Expression::ARRAY(ref arr) => {
+            for &element in &arr.elements {
+                vars_in_expression(ast, element, names);
+            }
+        }
+        Expression::PREFIX(ref prefix) => vars_in_expression(ast, prefix.right, names),
+        Expression::INFIX(ref infix) => {
+            vars_in_expression(ast, infix.left, names);
+            vars_in_expression(ast, infix.right, names);
+        }
+        Expression::IF(ref if_exp) => {
+            vars_in_expression(ast, if_exp.condition, names);
+            vars_in_statement(ast, if_exp.consequence, names);
+            if let Some(alternative) = if_exp.alternative {
+                vars_in_statement(ast, alternative, names);
+            }
+        }
+        Expression::CALL(ref call) => {
+            vars_in_expression(ast, call.function, names);
+            for &arg in &call.arguments {
+                vars_in_expression(ast, arg, names);
+            }
+        }
+        Expression::ASSIGN(ref assign) => {
+            vars_in_expression(ast, assign.target, names);
+            vars_in_expression(ast, assign.value, names);
+        }
+        Expression::SPREAD(ref spread) => vars_in_expression(ast, spread.value, names),
+        Expression::TERNARY(ref ternary) => {
+            vars_in_expression(ast, ternary.condition, names);
+            vars_in_expression(ast, ternary.consequence, names);
+            vars_in_expression(ast, ternary.alternative, names);
+        }
+        Expression::HASH(ref hash) => {
+            for &(_, value) in &hash.pairs {
+                vars_in_expression(ast, value, names);
+            }
+        }
+        Expression::MEMBER(ref member) => vars_in_expression(ast, member.object, names),
+        Expression::NEW(ref new) => {
+            vars_in_expression(ast, new.class, names);
+            for &arg in &new.arguments {
+                vars_in_expression(ast, arg, names);
+            }
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use std::cell::{Cell, RefCell};
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use environment::{Env, Environment};
+use memory::Meter;
+use object::{self, Frame, Object};
+use token::Position;
+
+// Calls between compiled functions take no native stack, so calls can nest
+// as deeply as scripts are likely to need.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+// Calls the tree-walker makes, and those builtins like `map` make back into
+// scripts on either engine, recurse on the native stack. Each takes up to
+// about 14KB of it in debug builds, 35KB for a callback into the vm. Runs may
+// take this much, which leaves room to spare in the 2MB of a thread spawned
+// with the default stack.
+pub const DEFAULT_MAX_STACK: usize = 1 << 20;
+
+// What traces call sources not given a name.
+pub const DEFAULT_SOURCE: &str = "<script>";
+
+// Traces keep the innermost frames only, like JS engines do.
+const TRACE_LIMIT: usize = 10;
+
+// The weak references of dropped scopes are cleared out once there are
+// this many, or twice as many as the scopes alive when they were last.
+const MIN_SCOPES: usize = 1024;
+
+// Reading the clock on every step would cost more than the step itself, the
+// deadline is checked once every this many steps.
+const CLOCK_INTERVAL: u64 = 1024;
+
+// Asks the execution in progress to stop from another thread. Scripts notice
+// it at their next call or loop iteration.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle {
+    interrupted: Arc<AtomicBool>,
+}
+
+impl InterruptHandle {
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::SeqCst);
+    }
+}
+
+// State shared by every scope of an Executor: the name of the source being
+// run, the stack of calls in progress, starting with the top level, the
+// budget each run may spend, the memory its values hold, the scopes closed
+// over and whether the host asked the run to stop.
+#[derive(Debug)]
+pub struct Runtime {
+    source: RefCell<String>,
+    frames: RefCell<Vec<Call>>,
+    max_call_depth: Cell<usize>,
+    max_stack: Cell<usize>,
+    fuel: Cell<Option<u64>>,
+    timeout: Cell<Option<Duration>>,
+    // What is left of the budget of the current run.
+    remaining_fuel: Cell<Option<u64>>,
+    // Where the native stack was when the current run started.
+    stack_base: Cell<Option<usize>>,
+    deadline: Cell<Option<Instant>>,
+    steps: Cell<u64>,
+    meter: Rc<Meter>,
+    scopes: RefCell<Vec<Weak<RefCell<Environment>>>>,
+    live_scopes: Cell<usize>,
+    interrupt: InterruptHandle,
+}
+
+impl Default for Runtime {
+    fn default() -> Runtime {
+        let source = DEFAULT_SOURCE.to_owned();
+        Runtime {
+            frames: RefCell::new(vec![Call {
+                                          frame: Frame::new(None, source.clone()),
+                                          tail_caller: None,
+                                      }]),
+            source: RefCell::new(source),
+            max_call_depth: Cell::new(DEFAULT_MAX_CALL_DEPTH),
+            max_stack: Cell::new(DEFAULT_MAX_STACK),
+            fuel: Cell::new(None),
+            timeout: Cell::new(None),
+            remaining_fuel: Cell::new(None),
+            stack_base: Cell::new(None),
+            deadline: Cell::new(None),
+            steps: Cell::new(0),
+            meter: Rc::new(Meter::default()),
+            scopes: RefCell::new(Vec::new()),
+            live_scopes: Cell::new(0),
+            interrupt: InterruptHandle::default(),
+        }
+    }
+}
+
+impl Runtime {
+    pub fn source(&self) -> String {
+        self.source.borrow().clone()
+    }
+
+    pub fn set_source(&self, name: &str) {
+        *self.source.borrow_mut() = name.to_owned();
+        if let Some(top_level) = self.frames.borrow_mut().first_mut() {
+            top_level.frame.source = name.to_owned();
+        }
+    }
+
+    pub fn set_max_call_depth(&self, depth: usize) {
+        self.max_call_depth.set(depth);
+    }
+
+    pub fn set_max_stack(&self, bytes: usize) {
+        self.max_stack.set(bytes);
+    }
+
+    pub fn set_fuel(&self, fuel: u64) {
+        self.fuel.set(Some(fuel));
+    }
+
+    pub fn set_timeout(&self, timeout: Duration) {
+        self.timeout.set(Some(timeout));
+    }
+
+    pub fn meter(&self) -> Rc<Meter> {
+        self.meter.clone()
+    }
+
+    // Keeps track of a scope functions close over, for memory::collect.
+    pub fn track(&self, scope: &Env) {
+        let mut scopes = self.scopes.borrow_mut();
+        if scopes.last().is_some_and(|last| last.as_ptr() == Rc::as_ptr(scope)) {
+            return;
+        }
+        if scopes.len() >= MIN_SCOPES.max(2 * self.live_scopes.get()) {
+            scopes.retain(|scope| scope.strong_count() > 0);
+            self.live_scopes.set(scopes.len());
+        }
+        scopes.push(Rc::downgrade(scope));
+    }
+
+    // The scopes still alive, forgetting those that are not.
+    pub fn scopes(&self) -> Vec<Env> {
+        let mut scopes = self.scopes.borrow_mut();
+        scopes.retain(|scope| scope.strong_count() > 0);
+        self.live_scopes.set(scopes.len());
+        scopes.iter().filter_map(Weak::upgrade).collect()
+    }
+
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    // Every run starts with the whole budget.
+    pub fn start(&self) {
+        self.remaining_fuel.set(self.fuel.get());
+        self.stack_base.set(Some(stack_address()));
+        self.deadline.set(self.timeout.get().map(|timeout| Instant::now() + timeout));
+        self.steps.set(0);
+    }
+
+    // Ends an execution, however far it got, so that interrupts meant for it
+    // do not stop the next one. Those raised while it was still being parsed
+    // or compiled stop it once it runs.
+    pub fn end(&self) {
+        self.interrupt.interrupted.store(false, Ordering::SeqCst);
+    }
+
+    // Accounts for one step of execution, an evaluated node or a loop
+    // iteration, failing once the run is out of fuel, time or memory.
+    pub fn step(&self) -> Result<(), object::Error> {
+        self.meter.check()?;
+        if let Some(fuel) = self.remaining_fuel.get() {
+            if fuel == 0 {
+                return Err(object::Error::exhausted("out of fuel".to_owned()));
+            }
+            self.remaining_fuel.set(Some(fuel - 1));
+        }
+        if let Some(deadline) = self.deadline.get() {
+            let steps = self.steps.get() + 1;
+            self.steps.set(steps);
+            if steps.is_multiple_of(CLOCK_INTERVAL) && Instant::now() >= deadline {
+                return Err(object::Error::exhausted("timed out".to_owned()));
+            }
+        }
+        Ok(())
+    }
+
+    // The text of `value` as inspect gives it, each value inside an array
+    // or hash taking a step, failing once the text would not fit in memory.
+    pub fn stringify(&self, value: &Object) -> Result<String, object::Error> {
+        let mut out = String::new();
+        object::write(value, &mut out, |len| {
+            self.step()?;
+            self.meter.reserve(len)
+        })?;
+        Ok(out)
+    }
+
+    // Fails once the host interrupted the run, checked on every call and
+    // loop iteration.
+    pub fn check_interrupt(&self) -> Result<(), object::Error> {
+        if self.interrupt.interrupted.load(Ordering::SeqCst) {
+            return Err(object::Error::cancelled());
+        }
+        Ok(())
+    }
+
+    // Fails once the run took more native stack than allowed, checked on
+    // every call that recurses natively.
+    pub fn check_stack(&self) -> Result<(), object::Error> {
+        if let Some(base) = self.stack_base.get() {
+            if base.abs_diff(stack_address()) > self.max_stack.get() {
+                return Err(object::Error::new("maximum call stack size exceeded".to_owned()));
+            }
+        }
+        Ok(())
+    }
+
+    // Enters a call, unless that would nest calls deeper than allowed. A
+    // call made by a tail call gets the frame of the caller it replaced.
+    pub fn push_frame(&self,
+                      name: Option<String>,
+                      tail_caller: Option<Frame>)
+                      -> Result<(), object::Error> {
+        let mut frames = self.frames.borrow_mut();
+        // The top level is not a call.
+        if frames.len() > self.max_call_depth.get() {
+            return Err(object::Error::new("maximum call stack size exceeded".to_owned()));
+        }
+        let name = name.unwrap_or_else(|| "<anonymous>".to_owned());
+        frames.push(Call {
+                        frame: Frame::new(Some(name), self.source.borrow().clone()),
+                        tail_caller,
+                    });
+        Ok(())
+    }
+
+    pub fn pop_frame(&self) {
+        self.frames.borrow_mut().pop();
+    }
+
+    // Leaves the innermost call for the one it tail calls. Its frame is
+    // given back for the callee to keep, with the tail calls made before it
+    // counted rather than kept, so that recursion stays in constant space.
+    pub fn tail_call(&self) -> Option<Frame> {
+        self.frames.borrow_mut().pop().map(|call| {
+            Frame {
+                elided: call.tail_caller.map_or(0, |caller| caller.elided + 1),
+                ..call.frame
+            }
+        })
+    }
+
+    // Records where the innermost call currently is, which is what its line
+    // in a trace points at.
+    pub fn set_position(&self, position: Position) {
+        if let Some(call) = self.frames.borrow_mut().last_mut() {
+            call.frame.position = position;
+        }
+    }
+
+    // The stack innermost first, with the innermost frame at `position`.
+    // Callers replaced by tail calls follow the call that replaced them.
+    pub fn trace(&self, position: Position) -> Vec<Frame> {
+        let mut frames: Vec<Frame> = self.frames
+            .borrow()
+            .iter()
+            .rev()
+            .flat_map(|call| Some(&call.frame).into_iter().chain(call.tail_caller.as_ref()))
+            .take(TRACE_LIMIT)
+            .cloned()
+            .collect();
+        if let Some(innermost) = frames.first_mut() {
+            innermost.position = position;
+        }
+        frames
+    }
+}
+
+// Roughly where the native stack is now.
+fn stack_address() -> usize {
+    let marker = 0u8;
+    &marker as *const u8 as usize
+}
+
+// A call on the stack, with the last caller that tail called it, if any.
+#[derive(Debug)]
+struct Call {
+    frame: Frame,
+    tail_caller: Option<Frame>,
+}
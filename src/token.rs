@@ -19,8 +19,13 @@ pub enum TokenType {
     POW,       // ^
     EQ,        // ==
     NOT_EQ,    // !=
+    ARROW,     // =>
+    ELLIPSIS,  // ...
 
     COMMA,     // ,
+    DOT,       // .
+    QUESTION,  // ?
+    COLON,     // :
     SEMICOLON, // ;
     LBRACKET,  // [
     RBRACKET,  // ]
@@ -33,11 +38,23 @@ pub enum TokenType {
 
     FUNCTION,
     VAR,
+    LET,
+    CONST,
     TRUE,
     FALSE,
     IF,
     ELSE,
     RETURN,
+    FOR,
+    THIS,
+    CLASS,
+    EXTENDS,
+    NEW,
+    SUPER,
+    THROW,
+    TRY,
+    CATCH,
+    FINALLY,
 }
 
 lazy_static! {
@@ -45,19 +62,45 @@ lazy_static! {
         let mut hm = HashMap::new();
         hm.insert("function", TokenType::FUNCTION);
         hm.insert("var", TokenType::VAR);
+        hm.insert("let", TokenType::LET);
+        hm.insert("const", TokenType::CONST);
         hm.insert("true", TokenType::TRUE);
         hm.insert("false", TokenType::FALSE);
         hm.insert("if", TokenType::IF);
         hm.insert("else", TokenType::ELSE);
         hm.insert("return", TokenType::RETURN);
+        hm.insert("for", TokenType::FOR);
+        hm.insert("this", TokenType::THIS);
+        hm.insert("class", TokenType::CLASS);
+        hm.insert("extends", TokenType::EXTENDS);
+        hm.insert("new", TokenType::NEW);
+        hm.insert("super", TokenType::SUPER);
+        hm.insert("throw", TokenType::THROW);
+        hm.insert("try", TokenType::TRY);
+        hm.insert("catch", TokenType::CATCH);
+        hm.insert("finally", TokenType::FINALLY);
         hm
     };
 }
 
+// Where a token starts in the source, lines and columns count from 1.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub token: TokenType,
     pub literal: String,
+    pub position: Position,
 }
 
 impl Token {
@@ -65,6 +108,7 @@ impl Token {
         Token {
             token: token_type,
             literal: literal.to_string(),
+            position: Position::default(),
         }
     }
 
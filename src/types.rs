@@ -1,418 +1,505 @@
-use token::Token;
+use arena::{NodeMap, Symbol};
+use token::TokenType;
 use ast::*;
 
-#[allow(dead_code)]
-#[derive(Debug, Clone, PartialEq)]
+// A parsed script: its nodes, its top-level statements, the slots the
+// resolver found its variables in and the calls it found in tail position.
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Program {
-    pub statements: Vec<Box<Statement>>,
+    pub ast: Ast,
+    pub statements: Vec<StmtId>,
+    pub slots: NodeMap<IdentId, Slot>,
+    pub tail_calls: NodeMap<ExprId, ()>,
 }
 
-impl Node for Program {
-    fn token_literal(&self) -> String {
-        if !self.statements.is_empty() {
-            let head_stmt = self.statements[0].token_literal();
-            return head_stmt;
-        }
-        "".to_owned()
-    }
-
-    fn to_string(&self) -> String {
-        let mut out = String::new();
-        for s in &self.statements {
-            out.push_str(s.to_string().as_str());
-        }
-        out
-    }
-
-    fn node_type(&self) -> NodeType {
-        NodeType::Program(self.clone())
-    }
+// A variable `depth` scopes out of the one it is used in, at `index` among
+// the locals of that scope.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Slot {
+    pub depth: usize,
+    pub index: usize,
 }
 
-#[allow(dead_code)]
-#[derive(Debug, Clone, PartialEq)]
-pub struct Identifier {
-    pub token: Token,
-    pub value: String,
-}
-
-impl Node for Identifier {
-    fn token_literal(&self) -> String {
-        self.token.literal.to_owned()
-    }
-
-    fn to_string(&self) -> String {
-        self.value.to_owned()
-    }
-
-    fn node_type(&self) -> NodeType {
-        NodeType::Expression(Expression::IDENT(self.clone()))
+// The keyword of a `var`, `let` or `const` declaration.
+fn declaration_keyword(kind: TokenType) -> &'static str {
+    match kind {
+        TokenType::LET => "let",
+        TokenType::CONST => "const",
+        _ => "var",
     }
 }
 
-#[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq)]
 pub struct VarStatement {
-    pub token: Token,
-    pub name: Identifier,
-    pub value: Option<Box<Expression>>,
+    pub kind: TokenType,
+    pub name: PatternId,
+    pub value: Option<ExprId>,
 }
 
 impl Node for VarStatement {
-    fn token_literal(&self) -> String {
-        self.token.literal.to_owned()
-    }
-    fn to_string(&self) -> String {
+    fn to_string(&self, ast: &Ast) -> String {
         let mut out = String::new();
-        out.push_str(&(self.token_literal() + " "));
-        out.push_str(&self.name.to_string());
+        out.push_str(declaration_keyword(self.kind));
+        out.push(' ');
+        out.push_str(&self.name.to_string(ast));
         out.push_str(" = ");
-        if let Some(v) = self.value.as_ref() {
-            out.push_str(v.to_string().as_str());
+        if let Some(v) = self.value {
+            out.push_str(v.to_string(ast).as_str());
         }
-        out.push_str(";");
-        out.to_owned()
-    }
-
-    fn node_type(&self) -> NodeType {
-        NodeType::Statement(Statement::VAR(self.clone()))
+        out.push(';');
+        out
     }
 }
 
-#[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq)]
 pub struct ReturnStatement {
-    pub token: Token,
-    pub return_value: Option<Box<Expression>>,
+    pub return_value: Option<ExprId>,
 }
 
 impl Node for ReturnStatement {
-    fn token_literal(&self) -> String {
-        self.token.literal.to_owned()
-    }
-
-    fn to_string(&self) -> String {
+    fn to_string(&self, ast: &Ast) -> String {
         let mut out = String::new();
 
-        out.push_str(&(self.token_literal() + " "));
-        if let Some(v) = self.return_value.as_ref() {
-            out.push_str(v.to_string().as_str());
+        out.push_str("return ");
+        if let Some(v) = self.return_value {
+            out.push_str(v.to_string(ast).as_str());
         }
 
-        out.push_str(";");
-        out.to_owned()
-    }
-
-    fn node_type(&self) -> NodeType {
-        NodeType::Statement(Statement::RETURN(self.clone()))
+        out.push(';');
+        out
     }
 }
 
-#[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq)]
 pub struct ExpressionStatement {
-    pub token: Token,
-    pub expression: Option<Box<Expression>>,
+    pub expression: Option<ExprId>,
 }
 
 impl Node for ExpressionStatement {
-    fn token_literal(&self) -> String {
-        self.token.literal.to_owned()
-    }
-
-    fn to_string(&self) -> String {
-        match self.expression.as_ref() {
-            Some(v) => v.to_string(),
+    fn to_string(&self, ast: &Ast) -> String {
+        match self.expression {
+            Some(v) => v.to_string(ast),
             None => "".to_owned(),
         }
     }
-
-    fn node_type(&self) -> NodeType {
-        NodeType::Statement(Statement::EXPR_STMT(self.clone()))
-    }
 }
 
-#[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq)]
 pub struct IntegerLiteral {
-    pub token: Token,
     pub value: i64,
 }
 
 impl Node for IntegerLiteral {
-    fn token_literal(&self) -> String {
-        self.token.literal.to_owned()
-    }
-
-    fn to_string(&self) -> String {
-        self.token.literal.to_owned()
-    }
-
-    fn node_type(&self) -> NodeType {
-        NodeType::Expression(Expression::INTEGER(self.clone()))
+    fn to_string(&self, _: &Ast) -> String {
+        self.value.to_string()
     }
 }
 
-#[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq)]
 pub struct PrefixExpression {
-    pub token: Token,
-    pub operator: String,
-    pub right: Box<Expression>,
+    pub operator: &'static str,
+    pub right: ExprId,
 }
 
 impl Node for PrefixExpression {
-    fn token_literal(&self) -> String {
-        self.token.literal.to_owned()
-    }
-
-    fn to_string(&self) -> String {
+    fn to_string(&self, ast: &Ast) -> String {
         let mut out = String::new();
 
-        out.push_str("(");
-        out.push_str(self.operator.as_str());
-        out.push_str(self.right.to_string().as_str());
-        out.push_str(")");
+        out.push('(');
+        out.push_str(self.operator);
+        out.push_str(self.right.to_string(ast).as_str());
+        out.push(')');
 
-        out.to_owned()
-    }
-
-    fn node_type(&self) -> NodeType {
-        NodeType::Expression(Expression::PREFIX(self.clone()))
+        out
     }
 }
 
-#[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq)]
 pub struct InfixExpression {
-    pub token: Token,
-    pub operator: String,
-    pub left: Box<Expression>,
-    pub right: Box<Expression>,
+    pub operator: &'static str,
+    pub left: ExprId,
+    pub right: ExprId,
 }
 
 impl Node for InfixExpression {
-    fn token_literal(&self) -> String {
-        self.token.literal.to_owned()
-    }
-
-    fn to_string(&self) -> String {
+    fn to_string(&self, ast: &Ast) -> String {
         let mut out = String::new();
 
-        out.push_str("(");
-        out.push_str(self.left.to_string().as_str());
+        out.push('(');
+        out.push_str(self.left.to_string(ast).as_str());
         out.push_str(&format!(" {} ", self.operator));
-        out.push_str(self.right.to_string().as_str());
-        out.push_str(")");
-
-        out.to_owned()
-    }
+        out.push_str(self.right.to_string(ast).as_str());
+        out.push(')');
 
-    fn node_type(&self) -> NodeType {
-        NodeType::Expression(Expression::INFIX(self.clone()))
+        out
     }
 }
 
-#[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq)]
 pub struct Boolean {
-    pub token: Token,
     pub value: bool,
 }
 
 impl Node for Boolean {
-    fn token_literal(&self) -> String {
-        self.token.literal.to_owned()
-    }
-
-    fn to_string(&self) -> String {
-        self.token.literal.to_owned()
-    }
-
-    fn node_type(&self) -> NodeType {
-        NodeType::Expression(Expression::BOOL(self.clone()))
+    fn to_string(&self, _: &Ast) -> String {
+        self.value.to_string()
     }
 }
 
-#[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq)]
 pub struct BlockStatement {
-    pub token: Token,
-    pub statements: Vec<Statement>,
+    pub statements: Vec<StmtId>,
 }
 
 impl Node for BlockStatement {
-    fn token_literal(&self) -> String {
-        self.token.literal.to_owned()
-    }
-
-    fn to_string(&self) -> String {
+    fn to_string(&self, ast: &Ast) -> String {
         let mut out = String::new();
 
         for s in &self.statements {
-            out.push_str(s.to_string().as_str())
+            out.push_str(s.to_string(ast).as_str())
         }
 
-        out.to_owned()
-    }
-
-    fn node_type(&self) -> NodeType {
-        NodeType::Statement(Statement::BLOCK_STMT(self.clone()))
+        out
     }
 }
 
-#[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq)]
 pub struct IfExpression {
-    pub token: Token,
-    pub condition: Box<Expression>,
-    pub consequence: Box<Statement>,
-    pub alternative: Option<Statement>,
+    pub condition: ExprId,
+    pub consequence: StmtId,
+    pub alternative: Option<StmtId>,
 }
 
 impl Node for IfExpression {
-    fn token_literal(&self) -> String {
-        self.token.literal.to_owned()
-    }
-
-    fn to_string(&self) -> String {
+    fn to_string(&self, ast: &Ast) -> String {
         let mut out = String::new();
 
         out.push_str("if");
-        out.push_str(self.condition.to_string().as_str());
-        out.push_str(" ");
-        out.push_str(self.consequence.to_string().as_str());
+        out.push_str(self.condition.to_string(ast).as_str());
+        out.push(' ');
+        out.push_str(self.consequence.to_string(ast).as_str());
 
-        if let Some(v) = self.alternative.as_ref() {
+        if let Some(v) = self.alternative {
             out.push_str("else ");
-            out.push_str(v.to_string().as_str());
+            out.push_str(v.to_string(ast).as_str());
         }
 
-        out.to_owned()
-    }
-
-    fn node_type(&self) -> NodeType {
-        NodeType::Expression(Expression::IF(self.clone()))
+        out
     }
 }
 
-#[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq)]
 pub struct FunctionLiteral {
-    pub token: Token,
-    pub parameters: Vec<Expression>,
-    pub body: Statement,
+    pub parameters: Vec<Parameter>,
+    pub body: StmtId,
+    pub arrow: bool,
 }
 
-impl Node for FunctionLiteral {
-    fn token_literal(&self) -> String {
-        self.token.literal.to_owned()
-    }
-
-    fn to_string(&self) -> String {
+impl FunctionLiteral {
+    // The function without its `function` keyword or the name it has as a
+    // method.
+    pub fn signature(&self, ast: &Ast) -> String {
         let mut out = String::new();
-        let mut params = Vec::new();
+        let params: Vec<String> = self.parameters.iter().map(|p| p.to_string(ast)).collect();
 
-        for p in &self.parameters {
-            params.push(p.to_string());
-        }
-        out.push_str(self.token_literal().as_str());
-        out.push_str("(");
+        out.push('(');
         out.push_str(params.join(", ").as_str());
         out.push_str(") ");
-        out.push_str(self.body.to_string().as_str());
+        if self.arrow {
+            out.push_str("=> ");
+        }
+        out.push_str(self.body.to_string(ast).as_str());
 
-        out.to_owned()
+        out
     }
+}
 
-    fn node_type(&self) -> NodeType {
-        NodeType::Expression(Expression::FUNC(self.clone()))
+impl Node for FunctionLiteral {
+    fn to_string(&self, ast: &Ast) -> String {
+        if self.arrow {
+            self.signature(ast)
+        } else {
+            format!("function{}", self.signature(ast))
+        }
     }
 }
 
-#[allow(dead_code)]
+// A function parameter, `name`, `name = default` or `...name`, where the
+// name can be any destructuring pattern.
 #[derive(Debug, Clone, PartialEq)]
-pub struct CallExpression {
-    pub token: Token,
-    pub function: Box<Expression>,
-    pub arguments: Vec<Expression>,
+pub struct Parameter {
+    pub name: PatternId,
+    pub default: Option<ExprId>,
+    pub rest: bool,
 }
 
-impl Node for CallExpression {
-    fn token_literal(&self) -> String {
-        self.token.literal.to_owned()
+impl Node for Parameter {
+    fn to_string(&self, ast: &Ast) -> String {
+        let mut out = String::new();
+        if self.rest {
+            out.push_str("...");
+        }
+        out.push_str(&self.name.to_string(ast));
+        if let Some(default) = self.default {
+            out.push_str(&format!(" = {}", default.to_string(ast)));
+        }
+        out
     }
+}
 
-    fn to_string(&self) -> String {
-        let mut out = String::new();
-        let mut params = Vec::new();
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallExpression {
+    pub function: ExprId,
+    pub arguments: Vec<ExprId>,
+}
 
-        for p in &self.arguments {
-            params.push(p.to_string());
-        }
+impl Node for CallExpression {
+    fn to_string(&self, ast: &Ast) -> String {
+        let mut out = String::new();
+        let params: Vec<String> = self.arguments.iter().map(|a| a.to_string(ast)).collect();
 
-        out.push_str(self.function.to_string().as_str());
-        out.push_str("(");
+        out.push_str(self.function.to_string(ast).as_str());
+        out.push('(');
         out.push_str(params.join(", ").as_str());
-        out.push_str(")");
-
-        out.to_owned()
-    }
+        out.push(')');
 
-    fn node_type(&self) -> NodeType {
-        NodeType::Expression(Expression::CALL(self.clone()))
+        out
     }
 }
 
-#[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq)]
 pub struct StringLiteral {
-    pub token: Token,
     pub value: String,
 }
 
 impl Node for StringLiteral {
-    fn token_literal(&self) -> String {
-        self.token.literal.to_owned()
+    fn to_string(&self, _: &Ast) -> String {
+        self.value.to_owned()
     }
+}
 
-    fn to_string(&self) -> String {
-        self.token.literal.to_owned()
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArrayLiteral {
+    pub elements: Vec<ExprId>,
+}
+
+impl Node for ArrayLiteral {
+    fn to_string(&self, ast: &Ast) -> String {
+        let elements: Vec<String> = self.elements.iter().map(|e| e.to_string(ast)).collect();
+        format!("[{}]", elements.join(", "))
     }
+}
 
-    fn node_type(&self) -> NodeType {
-        NodeType::Expression(Expression::STRING(self.clone()))
+// `target = value`, where target is an identifier or a member expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssignExpression {
+    pub target: ExprId,
+    pub value: ExprId,
+}
+
+impl Node for AssignExpression {
+    fn to_string(&self, ast: &Ast) -> String {
+        format!("({} = {})", self.target.to_string(ast), self.value.to_string(ast))
     }
 }
 
-#[allow(dead_code)]
+// `...value` inside a call argument list or an array literal.
 #[derive(Debug, Clone, PartialEq)]
-pub struct ArrayLiteral {
-    pub token: Token,
-    pub elements: Vec<Expression>,
+pub struct SpreadElement {
+    pub value: ExprId,
 }
 
-impl Node for ArrayLiteral {
-    fn token_literal(&self) -> String {
-        self.token.literal.to_owned()
+impl Node for SpreadElement {
+    fn to_string(&self, ast: &Ast) -> String {
+        format!("...{}", self.value.to_string(ast))
+    }
+}
+
+// `[a, b, ...rest]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArrayPattern {
+    pub elements: Vec<PatternId>,
+    pub rest: Option<PatternId>,
+}
+
+impl Node for ArrayPattern {
+    fn to_string(&self, ast: &Ast) -> String {
+        let mut elements: Vec<String> = self.elements.iter().map(|e| e.to_string(ast)).collect();
+        if let Some(rest) = self.rest {
+            elements.push(format!("...{}", rest.to_string(ast)));
+        }
+        format!("[{}]", elements.join(", "))
+    }
+}
+
+// `{name, age: years}`, each property is bound to a pattern of its own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectPattern {
+    pub properties: Vec<(Symbol, PatternId)>,
+}
+
+impl Node for ObjectPattern {
+    fn to_string(&self, ast: &Ast) -> String {
+        let mut properties = Vec::new();
+        for &(key, value) in &self.properties {
+            match ast[value] {
+                Pattern::IDENT(ident) if ast[ident] == key => properties.push(key.to_string(ast)),
+                _ => properties.push(format!("{}: {}", key.to_string(ast), value.to_string(ast))),
+            }
+        }
+        format!("{{{}}}", properties.join(", "))
+    }
+}
+
+// `for (let pattern of iterable) { ... }`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForOfStatement {
+    pub kind: TokenType,
+    pub pattern: PatternId,
+    pub iterable: ExprId,
+    pub body: StmtId,
+}
+
+impl Node for ForOfStatement {
+    fn to_string(&self, ast: &Ast) -> String {
+        format!("for ({} {} of {}) {}",
+                declaration_keyword(self.kind),
+                self.pattern.to_string(ast),
+                self.iterable.to_string(ast),
+                self.body.to_string(ast))
+    }
+}
+
+// `condition ? consequence : alternative`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TernaryExpression {
+    pub condition: ExprId,
+    pub consequence: ExprId,
+    pub alternative: ExprId,
+}
+
+impl Node for TernaryExpression {
+    fn to_string(&self, ast: &Ast) -> String {
+        format!("({} ? {} : {})",
+                self.condition.to_string(ast),
+                self.consequence.to_string(ast),
+                self.alternative.to_string(ast))
     }
+}
+
+// `{key: value, other}`
+#[derive(Debug, Clone, PartialEq)]
+pub struct HashLiteral {
+    pub pairs: Vec<(Symbol, ExprId)>,
+}
 
-    fn to_string(&self) -> String {
+impl Node for HashLiteral {
+    fn to_string(&self, ast: &Ast) -> String {
+        let pairs: Vec<String> = self.pairs
+            .iter()
+            .map(|(key, value)| format!("{}: {}", key.to_string(ast), value.to_string(ast)))
+            .collect();
+        format!("{{{}}}", pairs.join(", "))
+    }
+}
+
+// `object.property`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberExpression {
+    pub object: ExprId,
+    pub property: Symbol,
+}
+
+impl Node for MemberExpression {
+    fn to_string(&self, ast: &Ast) -> String {
+        format!("{}.{}", self.object.to_string(ast), self.property.to_string(ast))
+    }
+}
+
+// `class Name extends Base { constructor(...) { ... } method(...) { ... } }`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassStatement {
+    pub name: IdentId,
+    pub superclass: Option<ExprId>,
+    pub methods: Vec<(Symbol, FuncId)>,
+}
+
+impl Node for ClassStatement {
+    fn to_string(&self, ast: &Ast) -> String {
         let mut out = String::new();
-        let mut elements = Vec::new();
 
-        for el in &self.elements {
-            elements.push(el.to_string());
+        out.push_str("class ");
+        out.push_str(self.name.to_string(ast).as_str());
+        if let Some(superclass) = self.superclass {
+            out.push_str(" extends ");
+            out.push_str(superclass.to_string(ast).as_str());
+        }
+        out.push_str(" { ");
+        for &(name, method) in &self.methods {
+            out.push_str(name.to_string(ast).as_str());
+            out.push_str(ast[method].signature(ast).as_str());
+            out.push(' ');
         }
+        out.push('}');
 
-        out.push_str("[");
-        out.push_str(elements.join(", ").as_str());
-        out.push_str("]");
+        out
+    }
+}
+
+// `new Class(arguments)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewExpression {
+    pub class: ExprId,
+    pub arguments: Vec<ExprId>,
+}
+
+impl Node for NewExpression {
+    fn to_string(&self, ast: &Ast) -> String {
+        let arguments: Vec<String> = self.arguments.iter().map(|a| a.to_string(ast)).collect();
+        format!("new {}({})", self.class.to_string(ast), arguments.join(", "))
+    }
+}
+
+// `throw value;`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThrowStatement {
+    pub value: ExprId,
+}
 
-        out.to_owned()
+impl Node for ThrowStatement {
+    fn to_string(&self, ast: &Ast) -> String {
+        format!("throw {};", self.value.to_string(ast))
     }
+}
+
+// `try { } catch (param) { } finally { }`, with a catch clause, a finally
+// clause or both.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TryStatement {
+    pub block: StmtId,
+    pub param: Option<PatternId>,
+    pub handler: Option<StmtId>,
+    pub finalizer: Option<StmtId>,
+}
+
+impl Node for TryStatement {
+    fn to_string(&self, ast: &Ast) -> String {
+        let mut out = String::new();
 
-    fn node_type(&self) -> NodeType {
-        NodeType::Expression(Expression::ARRAY(self.clone()))
+        out.push_str("try ");
+        out.push_str(self.block.to_string(ast).as_str());
+        if let Some(handler) = self.handler {
+            out.push_str(" catch ");
+            if let Some(param) = self.param {
+                out.push_str(format!("({}) ", param.to_string(ast)).as_str());
+            }
+            out.push_str(handler.to_string(ast).as_str());
+        }
+        if let Some(finalizer) = self.finalizer {
+            out.push_str(" finally ");
+            out.push_str(finalizer.to_string(ast).as_str());
+        }
+
+        out
     }
 }
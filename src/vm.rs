@@ -0,0 +1,979 @@
+use std::mem;
+use std::rc::Rc;
+use std::vec;
+
+use builtins;
+use compiler::{Args, ClassTemplate, Code, Constant, Function, Op};
+use environment::{Env, Environment, ScopeKind};
+use evaluator::{apply_function, arity_error, eval_infix_expression, eval_prefix_expression,
+                function_env, get_property, is_truthy, lookup_property, method_name, new_array};
+use memory::Meter;
+use object::{self, Object, Objecter};
+use runtime::Runtime;
+use token::Position;
+
+// Why a frame stopped running its instructions in order.
+enum Unwind {
+    Return(Box<Object>),
+    // The function ends with a call, which takes its place.
+    TailCall(Box<object::TailCall>),
+    Error(object::Error),
+}
+
+impl From<object::Error> for Unwind {
+    fn from(err: object::Error) -> Unwind {
+        Unwind::Error(err)
+    }
+}
+
+// What the vm does after an instruction.
+enum Flow<'a> {
+    Next,
+    // Runs the frame of a call, the current one waiting for it to return.
+    Call(Frame<'a>),
+}
+
+// A call the vm started, in a frame of its own when the function is
+// compiled, or made already otherwise.
+enum Call<'a> {
+    Frame(Frame<'a>),
+    Value(Object),
+}
+
+// The code a frame runs, kept alive by the frame when it is a function.
+#[derive(Clone)]
+enum Source<'a> {
+    Program(&'a Code),
+    Function(Rc<Function>),
+}
+
+impl<'a> Source<'a> {
+    fn code(&self) -> &Code {
+        match *self {
+            Source::Program(code) => code,
+            Source::Function(ref function) => &function.code,
+        }
+    }
+}
+
+// The block of a try statement being run, with the outcome of the try or
+// catch block once the finally block runs.
+enum Stage {
+    Try,
+    Catch,
+    Finally(Result<Object, Unwind>),
+}
+
+// A try statement in progress, with the height of the stack and the scope
+// each of its blocks starts from.
+struct Try {
+    handler: usize,
+    finalizer: usize,
+    end: usize,
+    height: usize,
+    env: Env,
+    stage: Stage,
+}
+
+impl Try {
+    // Where the block being run ends.
+    fn boundary(&self) -> usize {
+        match self.stage {
+            Stage::Try => self.handler,
+            Stage::Catch => self.finalizer,
+            Stage::Finally(_) => self.end,
+        }
+    }
+}
+
+// A call in progress, or the program itself.
+struct Frame<'a> {
+    source: Source<'a>,
+    ip: usize,
+    // The innermost scope, which blocks enter and leave.
+    env: Env,
+    // The arguments left for the parameters to bind.
+    args: vec::IntoIter<Object>,
+    name: Option<String>,
+    // The frame of the caller this call replaced by a tail call.
+    tail_caller: Option<Box<object::Frame>>,
+    // Whether the call is on the stack of the runtime yet.
+    entered: bool,
+    // The height of the stack the call started at, which it leaves.
+    base: usize,
+    tries: Vec<Try>,
+    // What the call gives its caller in place of the value it returns,
+    // for constructors.
+    result: Option<Object>,
+}
+
+impl<'a> Frame<'a> {
+    fn new(source: Source<'a>,
+           env: Env,
+           args: Vec<Object>,
+           name: Option<String>,
+           base: usize)
+           -> Frame<'a> {
+        Frame {
+            source,
+            ip: 0,
+            env,
+            args: args.into_iter(),
+            name,
+            tail_caller: None,
+            entered: false,
+            base,
+            tries: Vec::new(),
+            result: None,
+        }
+    }
+
+    // Where the last instruction run comes from, the call a caller is in.
+    fn position(&self) -> Option<Position> {
+        let ip = self.ip.checked_sub(1)?;
+        self.source.code().positions.get(ip).and_then(|position| *position)
+    }
+}
+
+// Runs bytecode on an operand stack. Calls between compiled functions push
+// a frame on the vm rather than recursing, others go through the
+// tree-walker, which calls back into a new vm for compiled callbacks.
+struct Vm<'a> {
+    stack: Vec<Object>,
+    // The frames waiting for the calls they made, the innermost last.
+    frames: Vec<Frame<'a>>,
+    runtime: Rc<Runtime>,
+    meter: Rc<Meter>,
+}
+
+// Runs a compiled program in `env`, to the same value `eval` gives.
+pub fn run(code: &Code, env: &Env) -> Option<Object> {
+    let mut vm = Vm::new(env);
+    let frame = Frame::new(Source::Program(code), env.clone(), Vec::new(), None, 0);
+    Some(vm.run(frame).unwrap_or_else(|err| Object::ERROR(Box::new(err))))
+}
+
+// Calls a compiled function from outside of the vm, like the tree-walker
+// does for callbacks.
+pub fn call(func: &object::Func,
+            function: &Rc<Function>,
+            args: Vec<Object>,
+            this: Option<Object>)
+            -> Option<Object> {
+    let mut vm = Vm::new(&func.env);
+    let result = vm.enter(func, function, args, this).and_then(|frame| vm.run(frame));
+    Some(result.unwrap_or_else(|err| Object::ERROR(Box::new(err))))
+}
+
+fn string(code: &Code, index: u32) -> &str {
+    match code.constants.get(index as usize) {
+        Some(Constant::String(value)) => &value.value,
+        _ => "",
+    }
+}
+
+fn to_result(obj: Option<Object>) -> Result<Object, object::Error> {
+    match obj {
+        Some(Object::ERROR(err)) => Err(*err),
+        Some(obj) => Ok(obj),
+        None => Ok(Object::NULL),
+    }
+}
+
+fn new_bool(value: bool) -> Object {
+    if value {
+        Object::BOOL(object::Boolean::True)
+    } else {
+        Object::BOOL(object::Boolean::False)
+    }
+}
+
+impl<'a> Vm<'a> {
+    fn new(env: &Env) -> Vm<'a> {
+        let runtime = env.borrow().runtime();
+        Vm {
+            stack: Vec::new(),
+            frames: Vec::new(),
+            meter: runtime.meter(),
+            runtime,
+        }
+    }
+
+    fn pop(&mut self) -> Object {
+        self.stack.pop().unwrap_or(Object::NULL)
+    }
+
+    fn peek(&self) -> &Object {
+        self.stack.last().unwrap_or(&Object::NULL)
+    }
+
+    fn take(&mut self, count: usize) -> Vec<Object> {
+        let at = self.stack.len().saturating_sub(count);
+        self.stack.split_off(at)
+    }
+
+    fn take_args(&mut self, args: Args) -> Vec<Object> {
+        match args {
+            Args::Values(count) => self.take(count as usize),
+            Args::Spread => {
+                match self.pop() {
+                    Object::ARRAY(arr) => arr.into_vec(),
+                    _ => Vec::new(),
+                }
+            }
+        }
+    }
+
+    // Runs `frame` and the calls it makes, to the value it returns. Calls
+    // suspend the frame making them on `frames` until they return, so the
+    // native stack stays the same however deep the script calls.
+    fn run(&mut self, mut frame: Frame<'a>) -> Result<Object, object::Error> {
+        'frames: loop {
+            let source = frame.source.clone();
+            let code = source.code();
+            let mut unwind = loop {
+                if frame.tries.last().is_some_and(|statement| statement.boundary() == frame.ip) {
+                    if let Err(unwind) = self.complete(&mut frame) {
+                        break unwind;
+                    }
+                }
+                let op = match code.instructions.get(frame.ip) {
+                    Some(&op) => op,
+                    // Code that runs off its end returns the value on top.
+                    None => break Unwind::Return(Box::new(self.pop())),
+                };
+                let position = code.positions.get(frame.ip).and_then(|position| *position);
+                frame.ip += 1;
+                let result = match self.runtime.step() {
+                    Ok(()) => self.execute(op, position, code, &mut frame),
+                    Err(err) => Err(Unwind::Error(err)),
+                };
+                match result {
+                    Ok(Flow::Next) => {}
+                    Ok(Flow::Call(callee)) => {
+                        self.frames.push(mem::replace(&mut frame, callee));
+                        continue 'frames;
+                    }
+                    Err(Unwind::Error(err)) => break Unwind::Error(self.trace(err, position)),
+                    Err(unwind) => break unwind,
+                }
+            };
+
+            loop {
+                unwind = match self.catch(&mut frame, unwind) {
+                    Some(unwind) => unwind,
+                    None => continue 'frames,
+                };
+                let tail_caller = match unwind {
+                    Unwind::TailCall(_) if frame.entered => self.runtime.tail_call(),
+                    _ if frame.entered => {
+                        self.runtime.pop_frame();
+                        None
+                    }
+                    _ => None,
+                };
+                self.stack.truncate(frame.base);
+                let returned = match unwind {
+                    Unwind::Return(value) => Ok(*value),
+                    Unwind::Error(err) => Err(err),
+                    Unwind::TailCall(call) => {
+                        let call = *call;
+                        match self.call_value(call.function, call.args, call.this) {
+                            Ok(Call::Frame(mut callee)) => {
+                                callee.tail_caller = tail_caller.map(Box::new);
+                                callee.result = frame.result.take();
+                                frame = callee;
+                                continue 'frames;
+                            }
+                            Ok(Call::Value(value)) => Ok(value),
+                            Err(err) => Err(err),
+                        }
+                    }
+                };
+                let result = frame.result.take();
+                frame = match self.frames.pop() {
+                    Some(caller) => caller,
+                    None => return returned.map(|value| result.unwrap_or(value)),
+                };
+                // Errors the call did not trace are traced at the caller.
+                match returned {
+                    Ok(value) => {
+                        self.stack.push(result.unwrap_or(value));
+                        continue 'frames;
+                    }
+                    Err(err) => {
+                        let position = frame.position();
+                        unwind = Unwind::Error(self.trace(err, position));
+                    }
+                }
+            }
+        }
+    }
+
+    // Errors are traced by the instruction that raised them, or left to
+    // the caller when it has no position.
+    fn trace(&self, mut err: object::Error, position: Option<Position>) -> object::Error {
+        if err.trace.is_none() {
+            if let Some(position) = err.position.or(position) {
+                err.position = Some(position);
+                err.trace = Some(self.runtime.trace(position));
+            }
+        }
+        err
+    }
+
+    fn execute(&mut self,
+               op: Op,
+               position: Option<Position>,
+               code: &Code,
+               frame: &mut Frame<'a>)
+               -> Result<Flow<'a>, Unwind> {
+        match op {
+            Op::Constant(index) => {
+                let value = match code.constants.get(index as usize) {
+                    Some(&Constant::Integer(value)) => Object::INTEGER(object::Integer { value }),
+                    Some(Constant::String(value)) => Object::STRING(value.clone()),
+                    _ => Object::NULL,
+                };
+                self.stack.push(value);
+            }
+            Op::True => self.stack.push(new_bool(true)),
+            Op::False => self.stack.push(new_bool(false)),
+            Op::Null => self.stack.push(Object::NULL),
+            Op::Pop => {
+                self.stack.pop();
+            }
+            Op::Dup => {
+                let top = self.peek().clone();
+                self.stack.push(top);
+            }
+            Op::Error(index) => {
+                return Err(object::Error::new(string(code, index).to_owned()).into());
+            }
+
+            Op::GetVar(index) => {
+                let name = string(code, index);
+                let value = frame.env.borrow().get(name);
+                match value {
+                    Some(value) => self.stack.push(value),
+                    None => {
+                        let msg = format!("identifier not found: {}", name);
+                        return Err(object::Error::new(msg).into());
+                    }
+                }
+            }
+            Op::SetVar(index) => {
+                let value = self.pop();
+                let assigned = frame.env.borrow_mut().assign(string(code, index), value);
+                self.stack.push(assigned.map_err(object::Error::new)?);
+            }
+            Op::DeclareVar(index) => {
+                let value = self.pop();
+                let scope = Environment::function_scope(&frame.env);
+                scope.borrow_mut().set(string(code, index), value);
+            }
+            Op::DeclareVarOnce(index) => {
+                let scope = Environment::function_scope(&frame.env);
+                let name = string(code, index);
+                if !scope.borrow().contains(name) {
+                    scope.borrow_mut().set(name, Object::NULL);
+                }
+            }
+            Op::DeclareLet(index) | Op::DeclareConst(index) => {
+                let value = self.pop();
+                frame.env
+                    .borrow_mut()
+                    .declare(string(code, index), value, op == Op::DeclareConst(index))
+                    .map_err(object::Error::new)?;
+            }
+            Op::GetLocal { name, depth, slot } => {
+                let value = frame.env.borrow().get_local(depth as usize, slot as usize);
+                match value {
+                    Some(value) => self.stack.push(value),
+                    None => {
+                        let msg = format!("identifier not found: {}", string(code, name));
+                        return Err(object::Error::new(msg).into());
+                    }
+                }
+            }
+            Op::SetLocal { name, depth, slot } => {
+                let value = self.pop();
+                let assigned = frame.env.borrow_mut().assign_local(depth as usize,
+                                                                   slot as usize,
+                                                                   string(code, name),
+                                                                   value);
+                self.stack.push(assigned.map_err(object::Error::new)?);
+            }
+            Op::DeclareLocal { depth, slot, constant } => {
+                let value = self.pop();
+                frame.env
+                    .borrow_mut()
+                    .set_local(depth as usize, slot as usize, value, constant)
+                    .map_err(object::Error::new)?;
+            }
+            Op::DeclareLocalOnce { depth, slot } => {
+                let (depth, slot) = (depth as usize, slot as usize);
+                if frame.env.borrow().get_local(depth, slot).is_none() {
+                    frame.env
+                        .borrow_mut()
+                        .set_local(depth, slot, Object::NULL, false)
+                        .map_err(object::Error::new)?;
+                }
+            }
+            Op::UnpackArray { count, rest, pattern } => {
+                self.unpack_array(count as usize, rest, string(code, pattern))?
+            }
+            Op::CheckObject => {
+                match *self.peek() {
+                    Object::HASH(_) | Object::INSTANCE(_) => {}
+                    ref other => {
+                        let msg = format!("cannot destructure {:?} as an object", other.obj_type());
+                        return Err(object::Error::new(msg).into());
+                    }
+                }
+            }
+            Op::Destructure(index) => {
+                let obj = self.pop();
+                let key = string(code, index);
+                match lookup_property(&obj, key) {
+                    Some(property) => self.stack.push(property),
+                    None => {
+                        let msg = format!("cannot destructure missing property '{}'", key);
+                        return Err(object::Error::new(msg).into());
+                    }
+                }
+            }
+            Op::GetProperty(index) => {
+                let obj = self.pop();
+                let value = to_result(get_property(&obj, string(code, index)))?;
+                self.stack.push(value);
+            }
+            Op::SetProperty(index) => {
+                let value = self.pop();
+                let name = string(code, index);
+                match self.pop() {
+                    Object::HASH(hash) |
+                    Object::INSTANCE(object::Instance { fields: hash, .. }) => {
+                        hash.set(name, value.clone());
+                        self.stack.push(value);
+                    }
+                    other => {
+                        return Err(object::Error::new(format!("cannot set property '{}' of {:?}",
+                                                              name,
+                                                              other.obj_type()))
+                                       .into());
+                    }
+                }
+            }
+
+            Op::Not => self.prefix("!")?,
+            Op::Negate => self.prefix("-")?,
+            Op::Add => self.infix("+")?,
+            Op::Sub => self.infix("-")?,
+            Op::Mul => self.infix("*")?,
+            Op::Div => self.infix("/")?,
+            Op::Pow => self.infix("^")?,
+            Op::Less => self.infix("<")?,
+            Op::Greater => self.infix(">")?,
+            Op::Equal => self.infix("==")?,
+            Op::NotEqual => self.infix("!=")?,
+
+            Op::Jump(target) => frame.ip = target as usize,
+            Op::JumpIfFalse(target) => {
+                if !is_truthy(self.pop()) {
+                    frame.ip = target as usize;
+                }
+            }
+
+            Op::Array(count) => {
+                let elements = self.take(count as usize);
+                self.stack.push(new_array(elements, &self.meter)?);
+            }
+            Op::Spread => {
+                if let Object::ARRAY(_) = *self.peek() {
+                } else {
+                    let msg = format!("cannot spread {:?}", self.peek().obj_type());
+                    return Err(object::Error::new(msg).into());
+                }
+            }
+            Op::Concat(count) => {
+                let mut elements = Vec::new();
+                for part in self.take(count as usize) {
+                    if let Object::ARRAY(arr) = part {
+                        elements.extend(arr.to_vec());
+                    }
+                }
+                self.stack.push(new_array(elements, &self.meter)?);
+            }
+            Op::Hash(count) => {
+                let mut items = self.take(count as usize * 2).into_iter();
+                let mut pairs = Vec::new();
+                while let (Some(key), Some(value)) = (items.next(), items.next()) {
+                    pairs.push((key.inspect(), value));
+                }
+                self.stack.push(Object::HASH(object::Hash::new(pairs, &self.meter)));
+            }
+            Op::Closure(index) => {
+                if let Some(Constant::Function(function)) = code.constants.get(index as usize) {
+                    let body = object::Body::Bytecode(function.clone());
+                    let env = Environment::capture(&frame.env);
+                    self.stack.push(Object::FUNCTION(Rc::new(object::Func {
+                                                                 name: None,
+                                                                 body,
+                                                                 env,
+                                                                 arrow: function.arrow,
+                                                             })));
+                }
+            }
+            Op::NameFunction(index) => {
+                if let Some(Object::FUNCTION(func)) = self.stack.last_mut() {
+                    if func.name.is_none() {
+                        Rc::make_mut(func).name = Some(string(code, index).to_owned());
+                    }
+                }
+            }
+
+            Op::Call(args) => {
+                let args = self.take_args(args);
+                let callee = self.pop();
+                self.enter_call(position);
+                let call = self.call_value(callee, args, None)?;
+                return Ok(self.flow(call));
+            }
+            Op::CallMethod { name, args } | Op::CallSuperMethod { name, args } => {
+                let args = self.take_args(args);
+                let obj = self.pop();
+                let name = string(code, name);
+                self.enter_call(position);
+                let call = match lookup_property(&obj, name) {
+                    Some(func) => {
+                        let this = match op {
+                            Op::CallSuperMethod { .. } => frame.env.borrow().get("this"),
+                            _ => Some(obj),
+                        };
+                        self.call_value(func, args, this)?
+                    }
+                    // Values without the property are called through the
+                    // builtins, like `arr.push`.
+                    None => {
+                        let value = builtins::call_method(&obj, name, args, &self.runtime);
+                        Call::Value(to_result(value)?)
+                    }
+                };
+                return Ok(self.flow(call));
+            }
+            Op::TailCall(args) => {
+                self.enter_call(position);
+                return Err(self.tail_call(args));
+            }
+            Op::TailCallMethod { name, args } => {
+                self.tail_call_method(string(code, name), args, position)?
+            }
+            Op::SuperCall(args) => {
+                let args = self.take_args(args);
+                let (sup, this) = {
+                    let scope = frame.env.borrow();
+                    (scope.get("super"), scope.get("this"))
+                };
+                return match (sup, this) {
+                           (Some(Object::CLASS(sup)), Some(this @ Object::INSTANCE(_))) => {
+                               self.enter_call(position);
+                               Ok(self.construct(&sup, args, this, Object::NULL)?)
+                           }
+                           _ => {
+                               let msg = "'super' call outside of a subclass constructor";
+                               Err(object::Error::new(msg.to_owned()).into())
+                           }
+                       };
+            }
+            Op::New(args) => {
+                let args = self.take_args(args);
+                let class = match self.pop() {
+                    Object::CLASS(class) => class,
+                    other => {
+                        let msg = format!("{} is not a class", other.inspect());
+                        return Err(object::Error::new(msg).into());
+                    }
+                };
+                let instance = Object::INSTANCE(object::Instance {
+                                                    class: class.clone(),
+                                                    fields: object::Hash::new(Vec::new(),
+                                                                              &self.meter),
+                                                });
+                self.enter_call(position);
+                return Ok(self.construct(&class, args, instance.clone(), instance)?);
+            }
+            Op::Return => return Err(Unwind::Return(Box::new(self.pop()))),
+            Op::Throw => {
+                let value = self.pop();
+                return Err(object::Error::thrown(value).into());
+            }
+
+            Op::EnterBlock => {
+                frame.env = Environment::new_enclosed(&frame.env, ScopeKind::Block);
+                frame.env.borrow_mut().set_slots(code.slots as usize);
+            }
+            Op::ExitBlock => {
+                let outer = frame.env.borrow().outer();
+                if let Some(outer) = outer {
+                    frame.env = outer;
+                }
+            }
+            Op::IntoIter => {
+                let items = match self.pop() {
+                    Object::ARRAY(arr) => Object::ARRAY(arr),
+                    Object::STRING(s) => {
+                        let chars = s.value
+                            .chars()
+                            .map(|c| Object::STRING(object::Str::new(c.to_string(), &self.meter)))
+                            .collect();
+                        Object::ARRAY(object::Array::new(chars, &self.meter))
+                    }
+                    other => {
+                        let msg = format!("{:?} is not iterable", other.obj_type());
+                        return Err(object::Error::new(msg).into());
+                    }
+                };
+                self.stack.push(items);
+                self.stack.push(Object::INTEGER(object::Integer { value: 0 }));
+            }
+            Op::Next(target) => self.next(target as usize, frame)?,
+            Op::Class { template, superclass } => {
+                if let Some(Constant::Class(template)) = code.constants.get(template as usize) {
+                    let class = self.new_class(template, superclass, &frame.env)?;
+                    self.stack.push(class);
+                }
+            }
+            Op::Try { handler, finalizer, end } => {
+                frame.tries.push(Try {
+                                     handler: handler as usize,
+                                     finalizer: finalizer as usize,
+                                     end: end as usize,
+                                     height: self.stack.len(),
+                                     env: frame.env.clone(),
+                                     stage: Stage::Try,
+                                 });
+            }
+
+            Op::NextArg(target) => {
+                if let Some(arg) = frame.args.next() {
+                    self.stack.push(arg);
+                    frame.ip = target as usize;
+                }
+            }
+            Op::RestArgs => {
+                let rest = frame.args.by_ref().collect();
+                self.stack.push(Object::ARRAY(object::Array::new(rest, &self.meter)));
+            }
+            Op::Enter => {
+                self.runtime.check_interrupt()?;
+                let tail_caller = frame.tail_caller.take().map(|caller| *caller);
+                self.runtime.push_frame(frame.name.take(), tail_caller)?;
+                frame.entered = true;
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn prefix(&mut self, op: &str) -> Result<(), object::Error> {
+        let right = self.pop();
+        let value = to_result(eval_prefix_expression(op, right))?;
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn infix(&mut self, op: &str) -> Result<(), object::Error> {
+        let right = self.pop();
+        let left = self.pop();
+        let value = to_result(eval_infix_expression(op, left, right, &self.runtime))?;
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn unpack_array(&mut self,
+                    count: usize,
+                    rest: bool,
+                    pattern: &str)
+                    -> Result<(), object::Error> {
+        let mut elements = match self.pop() {
+            Object::ARRAY(arr) => arr.to_vec(),
+            other => {
+                let msg = format!("cannot destructure {:?} as an array", other.obj_type());
+                return Err(object::Error::new(msg));
+            }
+        };
+        if elements.len() < count {
+            let msg = format!("cannot destructure {} elements into {}", elements.len(), pattern);
+            return Err(object::Error::new(msg));
+        }
+        let others = elements.split_off(count);
+        if rest {
+            self.stack.push(Object::ARRAY(object::Array::new(others, &self.meter)));
+        }
+        self.stack.extend(elements.into_iter().rev());
+        Ok(())
+    }
+
+    fn next(&mut self, done: usize, frame: &mut Frame) -> Result<(), object::Error> {
+        self.runtime.check_interrupt()?;
+        let len = self.stack.len();
+        let index = match self.stack.get(len.wrapping_sub(1)) {
+            Some(Object::INTEGER(index)) => index.value,
+            _ => 0,
+        };
+        let item = match self.stack.get(len.wrapping_sub(2)) {
+            Some(Object::ARRAY(items)) => items.get(index as usize),
+            _ => None,
+        };
+        match item {
+            Some(item) => {
+                self.stack[len - 1] = Object::INTEGER(object::Integer { value: index + 1 });
+                self.stack.push(item);
+            }
+            None => {
+                self.stack.truncate(len.saturating_sub(2));
+                frame.ip = done;
+            }
+        }
+        Ok(())
+    }
+
+    fn tail_call(&mut self, args: Args) -> Unwind {
+        let args = self.take_args(args);
+        let function = self.pop();
+        Unwind::TailCall(Box::new(object::TailCall {
+                                      function,
+                                      args,
+                                      this: None,
+                                  }))
+    }
+
+    // Methods of builtin types are called right away, they do not recurse.
+    fn tail_call_method(&mut self,
+                        name: &str,
+                        args: Args,
+                        position: Option<Position>)
+                        -> Result<(), Unwind> {
+        let args = self.take_args(args);
+        let obj = self.pop();
+        self.enter_call(position);
+        match lookup_property(&obj, name) {
+            Some(function) => {
+                Err(Unwind::TailCall(Box::new(object::TailCall {
+                                                  function,
+                                                  args,
+                                                  this: Some(obj),
+                                              })))
+            }
+            None => {
+                let value = builtins::call_method(&obj, name, args, &self.runtime);
+                self.stack.push(to_result(value)?);
+                Ok(())
+            }
+        }
+    }
+
+    // Records where the current frame is calling from.
+    fn enter_call(&self, position: Option<Position>) {
+        if let Some(position) = position {
+            self.runtime.set_position(position);
+        }
+    }
+
+    fn flow(&mut self, call: Call<'a>) -> Flow<'a> {
+        match call {
+            Call::Frame(frame) => Flow::Call(frame),
+            Call::Value(value) => {
+                self.stack.push(value);
+                Flow::Next
+            }
+        }
+    }
+
+    // Starts a call of `callee`. Compiled functions get a frame for the vm
+    // to run, others are called through the tree-walker.
+    fn call_value(&mut self,
+                  callee: Object,
+                  args: Vec<Object>,
+                  this: Option<Object>)
+                  -> Result<Call<'a>, object::Error> {
+        if let Object::FUNCTION(ref func) = callee {
+            if let object::Body::Bytecode(ref function) = func.body {
+                return self.enter(func, function, args, this).map(Call::Frame);
+            }
+        }
+        to_result(apply_function(callee, args, this)).map(Call::Value)
+    }
+
+    // The frame of a call of a compiled function, which binds its
+    // parameters first thing.
+    fn enter(&self,
+             func: &object::Func,
+             function: &Rc<Function>,
+             args: Vec<Object>,
+             this: Option<Object>)
+             -> Result<Frame<'a>, object::Error> {
+        if args.len() < function.required {
+            return Err(arity_error(function.required, function.parameters, args.len()));
+        }
+        let env = function_env(func, this);
+        env.borrow_mut().set_slots(function.code.slots as usize);
+        Ok(Frame::new(Source::Function(function.clone()),
+                      env,
+                      args,
+                      func.name.clone(),
+                      self.stack.len()))
+    }
+
+    // Runs the constructor `class` has or inherits on `this`, the call
+    // giving `result` once it is done.
+    fn construct(&mut self,
+                 class: &Rc<object::Class>,
+                 args: Vec<Object>,
+                 this: Object,
+                 result: Object)
+                 -> Result<Flow<'a>, object::Error> {
+        let call = match class.find_method("constructor") {
+            Some(ctor) => self.call_value(Object::FUNCTION(ctor), args, Some(this))?,
+            None => Call::Value(Object::NULL),
+        };
+        Ok(match call {
+               Call::Frame(mut frame) => {
+                   frame.result = Some(result);
+                   Flow::Call(frame)
+               }
+               Call::Value(_) => {
+                   self.stack.push(result);
+                   Flow::Next
+               }
+           })
+    }
+
+    fn new_class(&mut self,
+                 template: &ClassTemplate,
+                 superclass: bool,
+                 env: &Env)
+                 -> Result<Object, object::Error> {
+        let superclass = if superclass {
+            match self.pop() {
+                Object::CLASS(class) => Some(class),
+                other => {
+                    return Err(object::Error::new(format!("class {} cannot extend {}",
+                                                          template.name,
+                                                          other.inspect())))
+                }
+            }
+        } else {
+            None
+        };
+
+        // Methods close over a scope binding `super` to the parent class.
+        let class_env = Environment::new_enclosed(env, ScopeKind::Block);
+        if let Some(ref sup) = superclass {
+            class_env.borrow_mut().set("super", Object::CLASS(sup.clone()));
+        }
+        let methods = template.methods
+            .iter()
+            .map(|(name, function)| {
+                (name.clone(),
+                 Rc::new(object::Func {
+                             name: Some(method_name(&template.name, name)),
+                             body: object::Body::Bytecode(function.clone()),
+                             env: Environment::capture(&class_env),
+                             arrow: false,
+                         }))
+            })
+            .collect();
+
+        Ok(Object::CLASS(Rc::new(object::Class {
+                                     name: template.name.clone(),
+                                     superclass,
+                                     methods,
+                                 })))
+    }
+
+    // Moves on from the blocks of try statements that end where the frame
+    // is. A try or catch block leaves its value for the finally block to
+    // give once it is done, when the statement has one.
+    fn complete(&mut self, frame: &mut Frame) -> Result<(), Unwind> {
+        while let Some(statement) = frame.tries.pop() {
+            if statement.boundary() != frame.ip {
+                frame.tries.push(statement);
+                break;
+            }
+            match statement.stage {
+                Stage::Try | Stage::Catch => {
+                    let value = self.pop();
+                    self.finish(frame, statement, Ok(value))?;
+                }
+                Stage::Finally(outcome) => {
+                    self.restore(frame, statement.height, &statement.env);
+                    self.stack.push(outcome?);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Gives the way out of a frame to its try statements, which catch
+    // errors or run their finally blocks first. What is left leaves the
+    // frame, unless a block resumes it.
+    fn catch(&mut self, frame: &mut Frame, mut unwind: Unwind) -> Option<Unwind> {
+        while let Some(mut statement) = frame.tries.pop() {
+            let caught = match (&statement.stage, &unwind) {
+                (Stage::Try, Unwind::Error(err)) if err.is_catchable() &&
+                                                    statement.handler < statement.finalizer => {
+                    Some(err.to_object(&self.meter))
+                }
+                _ => None,
+            };
+            if let Some(caught) = caught {
+                self.restore(frame, statement.height, &statement.env);
+                self.stack.push(caught);
+                frame.ip = statement.handler;
+                statement.stage = Stage::Catch;
+                frame.tries.push(statement);
+                return None;
+            }
+            unwind = match statement.stage {
+                // What the finally block does overrides what it was resuming.
+                Stage::Finally(_) => {
+                    self.restore(frame, statement.height, &statement.env);
+                    unwind
+                }
+                _ => {
+                    match self.finish(frame, statement, Err(unwind)) {
+                        Ok(()) => return None,
+                        Err(unwind) => unwind,
+                    }
+                }
+            };
+        }
+        Some(unwind)
+    }
+
+    // Ends the try or catch block of `statement` with `outcome`, which
+    // waits for the finally block when there is one.
+    fn finish(&mut self,
+              frame: &mut Frame,
+              mut statement: Try,
+              outcome: Result<Object, Unwind>)
+              -> Result<(), Unwind> {
+        self.restore(frame, statement.height, &statement.env);
+        if statement.finalizer < statement.end {
+            frame.ip = statement.finalizer;
+            statement.stage = Stage::Finally(outcome);
+            frame.tries.push(statement);
+            return Ok(());
+        }
+        self.stack.push(outcome?);
+        frame.ip = statement.end;
+        Ok(())
+    }
+
+    // Unwinds the stack and scopes to where they were before a block.
+    fn restore(&mut self, frame: &mut Frame, height: usize, env: &Env) {
+        self.stack.truncate(height);
+        frame.env = env.clone();
+    }
+}
@@ -0,0 +1,212 @@
+extern crate plasma;
+
+use plasma::interpreter::{compile, ExecutionError, Executor};
+
+const PROGRAMS: &[&str] = &[
+    "1 + 2 * 3",
+    "var xs = [1, \"two\", {three: 3}]; [...xs, xs.len()]",
+    "var fib = function(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(10)",
+    "class A { constructor(x) { this.x = x; } get() { this.x } }
+     class B extends A { get() { super.get() * 2 } }
+     new B(21).get()",
+    "var f = function([a, b], ...rest) { a + b + rest.len() }; f([1, 2], 3, 4)",
+    "var sum = 0; for (let x of [1, 2, 3]) { sum = sum + x; } sum",
+    "try { throw \"x\"; } catch (e) { e } finally { 1 }",
+    "var f = function(x) { 1 / x }; f(0)",
+    "var f = function(a, b, c) { [a, b, c] }; var xs = [2, 3]; f(1, ...xs)",
+];
+
+fn is_invalid(res: &Result<String, ExecutionError>) -> bool {
+    matches!(*res, Err(ExecutionError::InvalidBytecode(_)))
+}
+
+// An instruction tag and its operands.
+type Instruction<'a> = (u8, &'a [u32]);
+
+// A program of `t.js` with the integer constant 1, `slots` slots and the
+// instructions `ops`, at no position.
+fn program(slots: u32, ops: &[Instruction]) -> Vec<u8> {
+    let mut program = b"\x7fPLC".to_vec();
+    program.extend_from_slice(&6u32.to_le_bytes());
+    program.extend_from_slice(&[0; 4]);
+    program.extend_from_slice(&4u32.to_le_bytes());
+    program.extend_from_slice(b"t.js");
+    program.extend_from_slice(&1u32.to_le_bytes());
+    program.push(0);
+    program.extend_from_slice(&1i64.to_le_bytes());
+    program.extend_from_slice(&slots.to_le_bytes());
+    program.extend_from_slice(&(ops.len() as u32).to_le_bytes());
+    for &(tag, operands) in ops {
+        program.push(tag);
+        for operand in operands {
+            program.extend_from_slice(&operand.to_le_bytes());
+        }
+    }
+    program.extend(ops.iter().map(|_| 0));
+    seal(&mut program);
+    program
+}
+
+fn rejection(program: &[u8]) -> String {
+    match Executor::new().execute_compiled(program) {
+        Err(ExecutionError::InvalidBytecode(msg)) => msg,
+        other => panic!("ran to {:?}", other),
+    }
+}
+
+// The FNV-1a hash the header holds of the rest of the file.
+fn seal(program: &mut [u8]) {
+    let sum = program[12..]
+        .iter()
+        .fold(0x811c_9dc5u32, |hash, &byte| (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193));
+    program[8..12].copy_from_slice(&sum.to_le_bytes());
+}
+
+#[test]
+fn compiled_programs_run_like_their_source() {
+    for code in PROGRAMS.iter() {
+        let program = compile(code).unwrap();
+        assert_eq!(Executor::new().execute_compiled(&program),
+                   Executor::new().execute(code),
+                   "running {:?}",
+                   code);
+    }
+}
+
+#[test]
+fn compiled_programs_share_the_executor_state() {
+    let mut exec = Executor::new();
+    exec.execute("var n = 40;").unwrap();
+    let program = compile("n = n + 2; n").unwrap();
+    assert_eq!(exec.execute_compiled(&program), Ok("42".to_owned()));
+    assert_eq!(exec.execute("n"), Ok("42".to_owned()));
+}
+
+#[test]
+fn syntax_errors_are_reported_when_compiling() {
+    assert!(matches!(compile("var = 1;"), Err(ExecutionError::Syntax(_))));
+}
+
+#[test]
+fn other_format_versions_are_rejected() {
+    let mut program = compile("1").unwrap();
+    program[4] += 1;
+    let res = Executor::new().execute_compiled(&program);
+    assert_eq!(res,
+               Err(ExecutionError::InvalidBytecode("invalid bytecode: unsupported format version \
+                                                    7, expected 6"
+                                                       .to_owned())));
+}
+
+#[test]
+fn damaged_programs_are_rejected() {
+    let program = compile(PROGRAMS[3]).unwrap();
+    for at in 0..program.len() {
+        let mut damaged = program.clone();
+        damaged[at] ^= 0x20;
+        let res = Executor::new().execute_compiled(&damaged);
+        assert!(is_invalid(&res), "flipping byte {} gave {:?}", at, res);
+    }
+    for len in 0..program.len() {
+        let res = Executor::new().execute_compiled(&program[..len]);
+        assert!(is_invalid(&res), "cutting at {} gave {:?}", len, res);
+    }
+
+    let mut longer = program.clone();
+    longer.push(0);
+    seal(&mut longer);
+    assert!(is_invalid(&Executor::new().execute_compiled(&longer)));
+}
+
+#[test]
+fn instructions_must_refer_to_existing_constants() {
+    let mut program = compile("1").unwrap();
+    // The index of the constant the only instruction pushes.
+    program[46] = 7;
+    seal(&mut program);
+    let res = Executor::new().execute_compiled(&program);
+    assert_eq!(res,
+               Err(ExecutionError::InvalidBytecode("invalid bytecode: constant 7 is not a value"
+                                                       .to_owned())));
+}
+
+#[test]
+fn sources_are_not_compiled_programs() {
+    let res = Executor::new().execute_compiled(b"1 + 2");
+    assert_eq!(res,
+               Err(ExecutionError::InvalidBytecode("invalid bytecode: not a compiled program"
+                                                       .to_owned())));
+}
+
+#[test]
+fn crafted_programs_run() {
+    let program = program(1,
+                          &[(43, &[]),
+                            (0, &[0]),
+                            (54, &[0, 0, 0]),
+                            (0, &[0]),
+                            (0, &[0]),
+                            (33, &[1]),
+                            (44, &[])]);
+    assert_eq!(Executor::new().execute_compiled(&program), Ok("{1: 1}".to_owned()));
+}
+
+#[test]
+fn instructions_must_not_take_more_values_than_the_stack_has() {
+    let cases: &[&[Instruction]] = &[&[(0, &[0]), (33, &[0x8000_0001])],
+                                     &[(0, &[0]), (30, &[0xffff_fffe])],
+                                     &[(0, &[0]), (32, &[2])],
+                                     &[(0, &[0]), (36, &[0x7fff_ffff])],
+                                     &[(0, &[0]), (39, &[2])],
+                                     &[(0, &[0]), (19, &[])]];
+    for ops in cases {
+        assert_eq!(rejection(&program(0, ops)),
+                   "invalid bytecode: instruction 1 takes more values than there are",
+                   "running {:?}",
+                   ops);
+    }
+}
+
+#[test]
+fn slots_must_be_in_the_code() {
+    let declare = |slots, slot| program(slots, &[(43, &[]), (0, &[0]), (54, &[0, slot, 0])]);
+    assert_eq!(rejection(&declare(1, 0x7fff_ffff)),
+               "invalid bytecode: slot 2147483647 out of the 1 of the code");
+    assert_eq!(rejection(&declare(0x7fff_ffff, 0)),
+               "invalid bytecode: 2147483647 slots is more than the file declares");
+
+    let outside = program(1, &[(0, &[0]), (54, &[0, 0, 0]), (3, &[])]);
+    assert_eq!(rejection(&outside),
+               "invalid bytecode: instruction 1 declares out of the code");
+    let deep = program(1, &[(0, &[0]), (54, &[1000, 0, 0]), (3, &[])]);
+    assert_eq!(rejection(&deep),
+               "invalid bytecode: scope 1000 out is deeper than scopes nest");
+    let unopened = program(0, &[(44, &[]), (3, &[])]);
+    assert_eq!(rejection(&unopened), "invalid bytecode: instruction 0 leaves no block");
+}
+
+#[test]
+fn paths_through_the_code_must_agree_on_the_stack() {
+    let merge = program(0, &[(1, &[]), (29, &[3]), (0, &[0]), (0, &[0])]);
+    assert_eq!(rejection(&merge),
+               "invalid bytecode: instruction 3 is reached with different stack heights");
+    let empty = program(0, &[(3, &[]), (4, &[])]);
+    assert_eq!(rejection(&empty), "invalid bytecode: the code ends without a value");
+}
+
+#[test]
+fn try_statements_must_be_well_formed() {
+    let cases: &[(&[Instruction], &str)] =
+        &[(&[(48, &[0, 1, 1]), (3, &[])], "try blocks out of order"),
+          (&[(48, &[2, 1, 2]), (3, &[]), (3, &[])], "try blocks out of order"),
+          (&[(48, &[2, 2, 9]), (3, &[])], "jump to 9 out of the code"),
+          (&[(48, &[3, 3, 3]), (48, &[2, 4, 4]), (3, &[]), (3, &[])],
+           "try statement at 1 is not nested"),
+          (&[(48, &[3, 3, 3]), (1, &[]), (1, &[])],
+           "block of the try statement at 0 leaves no value"),
+          (&[(28, &[3]), (48, &[4, 4, 4]), (3, &[]), (3, &[]), (3, &[])],
+           "jump from 0 into the try statement at 1")];
+    for &(ops, msg) in cases {
+        assert_eq!(rejection(&program(0, ops)), format!("invalid bytecode: {}", msg));
+    }
+}
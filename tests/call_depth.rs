@@ -0,0 +1,152 @@
+extern crate plasma;
+
+use std::thread;
+
+use plasma::interpreter::{Engine, ExecutionError, Executor};
+
+// Runs `code` on both engines, each on a thread with the default stack, so
+// the default limits are tested against the smallest stack hosts are likely
+// to run it on. Where runs stop differs between engines, as each call of the
+// tree-walker takes native stack, so their results are given side by side.
+fn execute_on_default_stack(code: &'static str) -> Vec<Result<String, ExecutionError>> {
+    [Engine::TreeWalker, Engine::Vm]
+        .iter()
+        .map(|&engine| {
+                 thread::spawn(move || Executor::new().with_engine(engine).execute(code))
+                     .join()
+                     .unwrap()
+             })
+        .collect()
+}
+
+#[test]
+fn runaway_recursion_fails_gracefully() {
+    let codes = ["var f = function(n) { 1 + f(n + 1) }; f(0);",
+                 "class A { constructor(n) { this.next = new A(n + 1); } } new A(0);",
+                 "var f = function(n) { try { 1 + f(n + 1) } finally { n } }; f(0);",
+                 // Builtins calling back into scripts recurse natively on
+                 // both engines.
+                 "var f = function(n) { [n].map(function(x) { f(x + 1) }) }; f(0);",
+                 "var f = function(n) { [n].reduce(function(a, x) { f(x + 1) }, 0) }; f(0);"];
+    for code in codes.iter() {
+        for res in execute_on_default_stack(code) {
+            let err = res.unwrap_err().to_string();
+            assert!(err.starts_with("ERROR: maximum call stack size exceeded"), "{}", err);
+        }
+    }
+}
+
+#[test]
+fn stack_overflow_is_catchable() {
+    let results = execute_on_default_stack("var f = function() { f() };
+                                            var msg = \"\";
+                                            try { f(); } catch (e) { msg = e.message; }
+                                            [msg, [1, 2].map(x => x * 2)];");
+    for res in results {
+        assert_eq!(res, Ok("[maximum call stack size exceeded, [2, 4]]".to_owned()));
+    }
+}
+
+#[test]
+fn ordinary_recursion_runs_by_default() {
+    let code = "var sum = function(n) { if (n == 0) { 0 } else { n + sum(n - 1) } };
+                [sum(70), [3].map(function(x) { sum(x * 20) })]";
+    for res in execute_on_default_stack(code) {
+        assert_eq!(res, Ok("[2485, [1830]]".to_owned()));
+    }
+    // Calls between compiled functions take no native stack at all.
+    let code = "var sum = function(n) { if (n == 0) { 0 } else { n + sum(n - 1) } }; sum(900)";
+    let res = thread::spawn(move || Executor::new().with_engine(Engine::Vm).execute(code))
+        .join()
+        .unwrap();
+    assert_eq!(res, Ok("405450".to_owned()));
+}
+
+#[test]
+fn native_stack_is_configurable() {
+    let code = "var sum = function(n) { if (n == 0) { 0 } else { n + sum(n - 1) } }; sum(900)";
+    for engine in [Engine::TreeWalker, Engine::Vm].iter() {
+        let engine = *engine;
+        let res = thread::Builder::new()
+            .stack_size(64 << 20)
+            .spawn(move || {
+                       Executor::new()
+                           .with_engine(engine)
+                           .with_max_stack(32 << 20)
+                           .execute(code)
+                   })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(res, Ok("405450".to_owned()), "on {:?}", engine);
+
+        let mut exec = Executor::new().with_engine(engine).with_max_stack(0);
+        let err = exec.execute("var f = function() { 1 }; [1].map(f)").unwrap_err();
+        assert!(err.to_string().starts_with("ERROR: maximum call stack size exceeded"),
+                "{}",
+                err);
+    }
+}
+
+#[test]
+fn max_call_depth_is_configurable() {
+    let mut exec = Executor::new().with_max_call_depth(10);
+    let code = "var count = function(n) { if (n == 0) { 0 } else { 1 + count(n - 1) } };";
+    exec.execute(code).unwrap();
+
+    assert_eq!(exec.execute("count(9);"), Ok("9".to_owned()));
+    assert!(exec.execute("count(10);").is_err());
+    // The stack unwinds with the error, so the next script starts afresh.
+    assert_eq!(exec.execute("count(9);"), Ok("9".to_owned()));
+}
+
+#[test]
+fn tail_calls_run_in_constant_stack() {
+    let code = "var count = function(n, total) {
+                    if (n == 0) { return total; }
+                    return count(n - 1, total + 1);
+                };
+                var even = function(n) { return n == 0 ? true : odd(n - 1); };
+                var odd = function(n) { return n == 0 ? false : even(n - 1); };
+                class Walker {
+                    walk(n) { if (n == 0) { return this; } return this.walk(n - 1); }
+                }
+                [count(1000000, 0), even(1001), new Walker().walk(1000)]";
+    for engine in [Engine::TreeWalker, Engine::Vm].iter() {
+        let engine = *engine;
+        let res = thread::spawn(move || Executor::new().with_engine(engine).execute(code))
+            .join()
+            .unwrap();
+        assert_eq!(res, Ok("[1000000, False, Walker {}]".to_owned()), "on {:?}", engine);
+    }
+}
+
+#[test]
+fn returns_in_a_try_block_are_not_tail_calls() {
+    // The error of the call too deep is caught by the frame making it.
+    let code = "var depth = function(n) { try { return depth(n + 1); } catch (e) { return n; } };
+                depth(1)";
+    for engine in [Engine::TreeWalker, Engine::Vm].iter() {
+        let mut exec = Executor::new().with_engine(*engine).with_max_call_depth(10);
+        assert_eq!(exec.execute(code), Ok("10".to_owned()), "on {:?}", engine);
+    }
+}
+
+#[test]
+fn vm_calls_keep_their_frames_off_the_native_stack() {
+    // Far deeper than a thread spawned with the default stack could go if
+    // each call recursed, through calls, constructors and try blocks.
+    let code = "var f = function(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } };
+                class A { constructor(n) { this.n = n == 0 ? 0 : new A(n - 1).n + 1; } }
+                var g = function(n) { try { n == 0 ? 0 : 1 + g(n - 1) } catch (e) { -1 } };
+                [f(20000), new A(20000).n, g(20000)]";
+    let res = thread::spawn(move || {
+                                Executor::new()
+                                    .with_engine(Engine::Vm)
+                                    .with_max_call_depth(30000)
+                                    .execute(code)
+                            })
+        .join()
+        .unwrap();
+    assert_eq!(res, Ok("[20000, 20000, 20000]".to_owned()));
+}
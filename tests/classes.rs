@@ -0,0 +1,53 @@
+extern crate plasma;
+
+mod common;
+
+use common::{run, run_err};
+
+#[test]
+fn a_semicolon_may_follow_a_class() {
+    assert_eq!(run("class A { get() { 1 } }; new A().get()"), Ok("1".to_owned()));
+}
+
+const ANIMALS: &str = "class Animal {
+                           constructor(name) { this.name = name; }
+                           speak() { this.name + \" makes a sound\" }
+                       }
+                       class Dog extends Animal {
+                           constructor(name) { super(name); this.kind = \"dog\"; }
+                           speak() { super.speak() + \" (woof)\" }
+                       }";
+
+#[test]
+fn subclasses_call_up_with_super() {
+    let code = format!("{} var d = new Dog(\"Rex\"); [d.speak(), d.kind, d]", ANIMALS);
+    assert_eq!(run(&code),
+               Ok("[Rex makes a sound (woof), dog, Dog {name: Rex, kind: dog}]".to_owned()));
+}
+
+#[test]
+fn constructors_and_methods_are_inherited() {
+    assert_eq!(run("class A { hi() { \"A\" } } class B extends A {} var b = new B(); [b.hi(), b]"),
+               Ok("[A, B {}]".to_owned()));
+    assert_eq!(run("class A { constructor(x) { this.x = x; } } class B extends A {} new B(3).x"),
+               Ok("3".to_owned()));
+}
+
+#[test]
+fn super_is_only_called_from_subclass_constructors() {
+    assert_eq!(run_err("super(1)"),
+               "ERROR: 'super' call outside of a subclass constructor\n    at <script>:1:1");
+    assert_eq!(run_err("class A { constructor() { super(1); } } new A()"),
+               "ERROR: 'super' call outside of a subclass constructor\n    \
+                at new A (<script>:1:27)\n    at <script>:1:41");
+}
+
+#[test]
+fn classes_are_only_instantiated_with_new() {
+    assert!(run_err("class A { constructor(x) { this.x = x; } } A(1)")
+                .starts_with("ERROR: class A cannot be invoked without 'new'"));
+    assert!(run_err("var five = 5; new five()").starts_with("ERROR: 5 is not a class"));
+    assert!(run_err("class B extends 1 {}").starts_with("ERROR: class B cannot extend 1"));
+    assert!(run_err("class A { constructor(a, b) { this.s = a + b; } } new A(1)")
+                .starts_with("ERROR: wrong number of arguments: expected 2, got 1"));
+}
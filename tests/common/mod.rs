@@ -0,0 +1,27 @@
+// Helpers shared by the integration tests, each of which uses only some.
+#![allow(dead_code)]
+
+// Sixteen million iterations, which no test waits for the end of.
+pub const ENDLESS: &str = "var xs = [0];
+                           for (var i of [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]) {
+                               xs = [...xs, ...xs];
+                           }
+                           for (var a of xs) { for (var b of xs) { a + b; } }";
+
+use plasma::interpreter::{Engine, ExecutionError, Executor};
+
+// Runs `code` on both engines, which must agree, and returns what it gave.
+pub fn run(code: &str) -> Result<String, ExecutionError> {
+    let walked = Executor::new().with_engine(Engine::TreeWalker).execute(code);
+    let compiled = Executor::new().with_engine(Engine::Vm).execute(code);
+    assert_eq!(walked, compiled, "running {:?}", code);
+    walked
+}
+
+// The message of the error `code` fails with, on both engines.
+pub fn run_err(code: &str) -> String {
+    match run(code) {
+        Err(ExecutionError::Runtime(msg)) => msg,
+        res => panic!("running {:?} gave {:?}", code, res),
+    }
+}
@@ -0,0 +1,37 @@
+extern crate plasma;
+
+mod common;
+
+use common::{run, run_err};
+
+#[test]
+fn a_semicolon_may_follow_a_loop() {
+    assert_eq!(run("var n = 0; for (var [a, b] of [[1, 2]]) { n = a + b; }; n"),
+               Ok("3".to_owned()));
+}
+
+#[test]
+fn patterns_bind_nested_values() {
+    assert_eq!(run("var [a, [b, c], ...rest] = [1, [2, 3], 4, 5]; [a, b, c, rest]"),
+               Ok("[1, 2, 3, [4, 5]]".to_owned()));
+    assert_eq!(run("var {x, y: [first]} = {x: 1, y: [2, 3]}; [x, first]"),
+               Ok("[1, 2]".to_owned()));
+    assert_eq!(run("var f = function([a, b], {c}) { a + b + c }; f([1, 2], {c: 3})"),
+               Ok("6".to_owned()));
+    assert_eq!(run("var s = 0; for (const {k} of [{k: 1}, {k: 2}]) { s = s + k; } s"),
+               Ok("3".to_owned()));
+}
+
+#[test]
+fn shapes_that_do_not_match_are_errors() {
+    assert_eq!(run_err("var [a, b] = [1];"),
+               "ERROR: cannot destructure 1 elements into [a, b]\n    at <script>:1:1");
+    assert_eq!(run_err("var {missing} = {};"),
+               "ERROR: cannot destructure missing property 'missing'\n    at <script>:1:1");
+    assert!(run_err("var [a] = 1;").starts_with("ERROR: cannot destructure INTEGER as an array"));
+    assert!(run_err("var {a} = 1;").starts_with("ERROR: cannot destructure INTEGER as an object"));
+    assert!(run_err("var f = function([a, b]) { a }; f([1])")
+                .starts_with("ERROR: cannot destructure 1 elements into [a, b]"));
+    assert!(run_err("for (var [a] of [1]) { a }")
+                .starts_with("ERROR: cannot destructure INTEGER as an array"));
+}
@@ -0,0 +1,206 @@
+extern crate plasma;
+
+use std::thread;
+
+use plasma::interpreter::{Engine, ExecutionError, Executor};
+
+// Scripts both engines must agree on, down to the traces of their errors.
+const PROGRAMS: &[&str] = &[
+    // Values and operators.
+    "1 + 2 * 3 - 4 / 2",
+    "2 ^ 10",
+    "-(3 - 5)",
+    "!true == false",
+    "!![]",
+    "1 < 2 == 3 > 4",
+    "\"foo\" + \"bar\"",
+    "\"n = \" + 42",
+    "\"a\" == \"a\"",
+    "\"a\" != \"b\"",
+    "[1, \"two\", [3], {four: 4}]",
+    "({a: 1, b: \"x\", c: [1, 2]})",
+    "true ? 1 : 2",
+    "false ? 1 : 0 ? 2 : 3",
+    "",
+    "var x = 1;",
+    // Scopes and declarations.
+    "var x = 1; x = x + 1; x",
+    "let a = 1; if (true) { let a = 2; a = 3; } a",
+    "var v = 1; if (true) { var v = 2; } v",
+    "const c = 1; c = 2;",
+    "let d = 1; let d = 2;",
+    "undefined + 1",
+    "var x; x",
+    "var x = 1; var x; x",
+    "var f = function() { var y = 2; if (true) { var y; } y }; f()",
+    "if (true) { 1; 2; var y = 3; }",
+    "if (1 > 2) { 10 }",
+    "if (1 < 2) { 10 } else { 20 }",
+    "if (false) { 1 } else { var z = 2; }",
+    // Functions and closures.
+    "var add = function(a, b) { a + b }; add(1, 2)",
+    "var f = function() { return; }; f()",
+    "var g = function(x) { if (x > 1) { return x; } 0 }; [g(5), g(0)]",
+    "var counter = function() { var n = 0; () => { n = n + 1; n } };
+     var c = counter(); c(); c(); c()",
+    "var fib = function(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)",
+    "var f = function(a, b = a * 2, ...rest) { [a, b, rest] }; [f(1), f(1, 5, 6, 7)]",
+    "var f = function(a, b) { a }; f(1)",
+    "var f = function([a, b], {c}) { a + b + c }; f([1, 2], {c: 3})",
+    "var f = function(x) { x }; f",
+    "var named = function() {}; var other = named; [named, other]",
+    "((x, y) => x * y)(6, 7)",
+    "var sq = x => x * x; [1, 2, 3].map(sq)",
+    "[1, 2, 3, 4].filter(x => x > 2).reduce((a, b) => a + b, 0)",
+    "\" Hi \".trim().upper().len()",
+    "\"a,b,c\".split(\",\")",
+    "({a: 1, b: 2}).keys()",
+    "1(2)",
+    // Spreading and destructuring.
+    "var xs = [1, 2]; [0, ...xs, 3, ...xs]",
+    "var f = function(a, b, c) { a + b + c }; var xs = [2, 3]; f(1, ...xs)",
+    "[...1]",
+    "var [a, [b, c], ...rest] = [1, [2, 3], 4, 5]; [a, b, c, rest]",
+    "var {x, y: [first]} = {x: 1, y: [2, 3]}; [x, first]",
+    "var [a, b] = [1];",
+    "var {missing} = {};",
+    "var [a] = 1;",
+    // Loops.
+    "var sum = 0; for (var x of [1, 2, 3]) { sum = sum + x; } sum",
+    "var out = \"\"; for (let c of \"abc\") { out = c + out; } out",
+    "for (var x of [1]) { x }",
+    "for (var x of 5) { x }",
+    "var fs = []; for (let i of [1, 2]) { fs = [...fs, () => i]; } fs.map(f => f())",
+    "var find = function(xs) { for (var x of xs) { if (x > 1) { return x; } } -1 };
+     [find([1, 2, 3]), find([0])]",
+    "for (const [k, v] of [[1, 2], [3, 4]]) { k + v }",
+    "var xs = [1, 2]; for (var x of xs) { if (x < 3) { xs.push(x + 2); } } xs",
+    // Objects and classes.
+    "var o = {a: 1}; o.b = 2; o.a = o.a + 1; o",
+    "var o = {}; o.missing",
+    "1.foo",
+    "var n = 1; n.x = 2;",
+    "class Point {
+         constructor(x, y) { this.x = x; this.y = y; }
+         sum() { this.x + this.y }
+     }
+     var p = new Point(1, 2);
+     [p.sum(), p.x, p]",
+    "class Animal {
+         constructor(name) { this.name = name; }
+         speak() { this.name + \" makes a sound\" }
+     }
+     class Dog extends Animal {
+         constructor(name) { super(name); this.kind = \"dog\"; }
+         speak() { super.speak() + \" (woof)\" }
+     }
+     var d = new Dog(\"Rex\");
+     [d.speak(), d.kind, d]",
+    "class A {} var a = new A(); a",
+    "class A { constructor(x) { this.x = x; } } A(1)",
+    "class B extends 1 {}",
+    "var five = 5; new five()",
+    "class A {} class A {}",
+    "super(1)",
+    "class Counter {
+         constructor() { this.n = 0; }
+         inc() { this.n = this.n + 1; this }
+     }
+     new Counter().inc().inc().n",
+    "var o = {f: function() { this }}; o.f() == o",
+    "var o = {v: 1, f: function() { var g = () => this.v; g() }}; o.f()",
+    // Arrays are shared by reference.
+    "var a = [1]; var b = a; [b.push(2, 3), a, a == b, a == [1, 2, 3]]",
+    "var add = function(xs, x) { xs.push(x) }; var ys = []; add(ys, 1); add(ys, 2); ys",
+    "var o = {xs: [1]}; var {xs} = o; xs.push(2); var [...copy] = xs; copy.push(3); [o, copy]",
+    "var s = \"a\"; var t = s; t = t + \"b\"; [s, t, s == \"a\"]",
+    // Errors.
+    "1 / 0",
+    "9223372036854775807 + 1",
+    "-9223372036854775807 - 2",
+    "2 ^ -1",
+    "true + 1",
+    "\"a\" - \"b\"",
+    "throw \"boom\";",
+    "throw {message: \"custom\"};",
+    "try { throw \"x\"; } catch (e) { e }",
+    "try { 1 / 0; } catch (e) { [e.message, e] }",
+    "try { undefined; } catch { \"caught\" }",
+    "try { 1 } finally { 2 }",
+    "try { throw 1; } catch (e) { throw e + 1; }",
+    "try { throw 1; } finally { 3 }",
+    "var f = function() { try { return 1; } finally { 2 } }; f()",
+    "var f = function() { try { return 1; } finally { return 2; } }; f()",
+    "var f = function() { try { throw 1; } catch (e) { return e; } finally { 5 } }; f()",
+    "var log = [];
+     try { try { throw 1; } finally { log = [...log, 1]; } } catch (e) { log = [...log, e]; }
+     log",
+    "try { throw [1, 2]; } catch ([a, b]) { a + b }",
+    "var inner = function() { 1 / 0 };
+     var outer = function() { inner() };
+     outer()",
+    "var f = function(n) { if (n == 0) { undefined } else { f(n - 1) } }; f(3)",
+    // Frames of tail calls are gone from traces.
+    "var g = function(n) { if (n == 0) { return 1 / 0; } return g(n - 1); };
+     var h = function() { g(3) };
+     h()",
+    "var f = function(a, b) { a }; var g = function() { return f(); }; g()",
+    "var g = function() { return [1].nope(); }; g()",
+    "[1, 2].map(x => x / 0)",
+    "var fail = function() { 1 / 0 }; var id = function(x) { x }; id(fail())",
+    "var f = function(a, b = 1 / 0) { a }; f(1)",
+    "class A { constructor() { this.x = 1 / 0; } } new A()",
+    "var f = function() { f() }; f()",
+    "var f = function() { f() }; try { f(); } catch (e) { e.message }",
+];
+
+fn execute(engine: Engine, code: &str) -> Result<String, ExecutionError> {
+    Executor::new().with_engine(engine).execute(code)
+}
+
+#[test]
+fn the_vm_agrees_with_the_tree_walker() {
+    // Runaway recursion needs the native stack of a main thread.
+    thread::Builder::new()
+        .stack_size(8 << 20)
+        .spawn(|| for code in PROGRAMS.iter() {
+                   assert_eq!(execute(Engine::Vm, code),
+                              execute(Engine::TreeWalker, code),
+                              "running {:?}",
+                              code);
+               })
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn the_vm_keeps_state_between_executions() {
+    let mut exec = Executor::new().with_engine(Engine::Vm);
+    exec.execute("var n = 1; let inc = function() { n = n + 1; };").unwrap();
+    exec.execute("inc(); inc();").unwrap();
+    assert_eq!(exec.execute("n"), Ok("3".to_owned()));
+}
+
+#[test]
+fn functions_cross_engines() {
+    let mut exec = Executor::new().with_engine(Engine::Vm);
+    exec.execute("var double = function(x) { x * 2 };").unwrap();
+    let mut exec = exec.with_engine(Engine::TreeWalker);
+    exec.execute("var triple = function(x) { x * 3 };").unwrap();
+    let mut exec = exec.with_engine(Engine::Vm);
+    assert_eq!(exec.execute("[double(triple(1)), [1].map(triple)]"),
+               Ok("[6, [3]]".to_owned()));
+}
+
+#[test]
+fn arrays_are_shared_by_reference() {
+    for engine in [Engine::TreeWalker, Engine::Vm].iter() {
+        let mut exec = Executor::new().with_engine(*engine);
+        exec.execute("var a = [1]; var b = a; b.push(2);").unwrap();
+        assert_eq!(exec.execute("[a, a == b, a == [1, 2]]"),
+                   Ok("[[1, 2], True, False]".to_owned()),
+                   "on {:?}",
+                   engine);
+    }
+}
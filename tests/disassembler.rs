@@ -0,0 +1,81 @@
+extern crate plasma;
+
+use plasma::interpreter::{disassemble, ExecutionError};
+
+#[test]
+fn lists_instructions_with_positions_and_constants() {
+    let listing = disassemble("var x = 1;\nx + \"a\"").unwrap();
+    let expected = "== <script> ==
+0000     1:9  CONSTANT        0    ; 1
+0001     1:1  NAME_FUNCTION   1    ; \"x\"
+0002     1:1  DECLARE_VAR     1    ; \"x\"
+0003     2:1  GET_VAR         1    ; \"x\"
+0004     2:5  CONSTANT        2    ; \"a\"
+0005     2:1  ADD
+";
+    assert_eq!(listing, expected);
+}
+
+#[test]
+fn lists_nested_functions_and_methods() {
+    let listing = disassemble("var f = function(a) { () => a };
+                               class A { get() { 1 } }")
+        .unwrap();
+    assert!(listing.contains("CLOSURE         0    ; function <script>/0 (arity 1)"),
+            "{}",
+            listing);
+    assert!(listing.contains("CLOSURE         0    ; arrow <script>/0/0 (arity 0)"),
+            "{}",
+            listing);
+    assert!(listing.contains("\n== <script>/0 ==\n"), "{}", listing);
+    assert!(listing.contains("\n== <script>/0/0 ==\n"), "{}", listing);
+    // The parameter is one scope out of the body of the arrow function, the
+    // blocks around declaring nothing and having no scope of their own.
+    assert!(listing.contains("GET_LOCAL       0    ; \"a\" at depth 1, slot 0"),
+            "{}",
+            listing);
+    assert!(listing.contains("CLASS           2    ; class A"), "{}", listing);
+    assert!(listing.contains("\n== <script>/2/get ==\n"), "{}", listing);
+}
+
+#[test]
+fn jumps_show_their_targets() {
+    let listing = disassemble("if (true) { 1 } else { 2 }").unwrap();
+    assert!(listing.contains("JUMP_IF_FALSE   -> 0004"), "{}", listing);
+}
+
+#[test]
+fn only_blocks_declaring_names_enter_a_scope() {
+    let listing = disassemble("{ 1 }").unwrap();
+    assert!(!listing.contains("ENTER_BLOCK"), "{}", listing);
+    let listing = disassemble("{ let x = 1; { x } }").unwrap();
+    assert_eq!(listing.matches("ENTER_BLOCK").count(), 1, "{}", listing);
+    assert!(listing.contains("GET_LOCAL       1    ; \"x\" at depth 0, slot 0"),
+            "{}",
+            listing);
+}
+
+#[test]
+fn syntax_errors_are_reported() {
+    match disassemble("var = 1;") {
+        Err(ExecutionError::Syntax(_)) => {}
+        res => panic!("unexpected {:?}", res),
+    }
+}
+
+#[test]
+fn tail_calls_are_marked() {
+    let listing = disassemble("var f = function(n) { g(n) + 1; return g(n) };
+                               var g = function(n) { return n.m() }")
+        .unwrap();
+    assert!(listing.contains("  CALL            1\n"), "{}", listing);
+    assert!(listing.contains("  TAIL_CALL       1\n"), "{}", listing);
+    assert!(listing.contains("  TAIL_CALL_METHOD 0, 1    ; \"m\""), "{}", listing);
+}
+
+#[test]
+fn spread_arguments_are_passed_as_an_array() {
+    let listing = disassemble("f(1, ...xs)").unwrap();
+    assert!(listing.contains("  CONCAT          2\n"), "{}", listing);
+    assert!(listing.contains("  CALL            spread\n"), "{}", listing);
+}
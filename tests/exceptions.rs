@@ -0,0 +1,64 @@
+extern crate plasma;
+
+mod common;
+
+use common::{run, run_err};
+
+#[test]
+fn a_semicolon_may_follow_a_try_statement() {
+    assert_eq!(run("var r = 0; try { throw 1; } catch (e) { r = e; }; r"), Ok("1".to_owned()));
+    assert_eq!(run("var r = 0; try { r = 1; } finally { r = r + 1; }; r"), Ok("2".to_owned()));
+}
+
+#[test]
+fn try_catch_and_finally_run_in_order() {
+    assert_eq!(run("var log = [];
+                    try { log.push(\"try\"); throw \"x\"; log.push(\"no\"); }
+                    catch (e) { log.push(\"catch \" + e); }
+                    finally { log.push(\"finally\"); }
+                    log"),
+               Ok("[try, catch x, finally]".to_owned()));
+    assert_eq!(run("var log = [];
+                    try { try { throw 1; } finally { log.push(\"inner\"); } }
+                    catch (e) { log.push(\"outer \" + e); }
+                    log"),
+               Ok("[inner, outer 1]".to_owned()));
+}
+
+#[test]
+fn finally_runs_after_a_return() {
+    assert_eq!(run("var log = [];
+                    var f = function() {
+                        try { log.push(1); return \"t\"; } finally { log.push(2); }
+                    };
+                    [f(), log]"),
+               Ok("[t, [1, 2]]".to_owned()));
+    assert_eq!(run("var f = function() { try { throw 1; } catch (e) { return e; } finally { 5 } };
+                    f()"),
+               Ok("1".to_owned()));
+    // A finally block that returns itself overrides the outcome.
+    assert_eq!(run("var f = function() { try { return 1; } finally { return 2; } }; f()"),
+               Ok("2".to_owned()));
+}
+
+#[test]
+fn the_value_of_a_try_statement_is_that_of_its_block() {
+    assert_eq!(run("try { 1 } finally { 2 }"), Ok("1".to_owned()));
+    assert_eq!(run("try { undefined; } catch { \"caught\" }"), Ok("caught".to_owned()));
+    assert_eq!(run("try { throw [1, 2]; } catch ([a, b]) { a + b }"), Ok("3".to_owned()));
+}
+
+#[test]
+fn runtime_errors_are_caught_as_objects() {
+    assert_eq!(run("try { 1 / 0; } catch (e) { [e.message, e.stack] }"),
+               Ok("[division by zero, Error: division by zero\n    at <script>:1:7]".to_owned()));
+}
+
+#[test]
+fn uncaught_errors_stop_the_script() {
+    assert_eq!(run_err("throw \"boom\";"), "ERROR: boom\n    at <script>:1:1");
+    assert_eq!(run_err("throw {message: \"custom\"};"), "ERROR: custom\n    at <script>:1:1");
+    assert_eq!(run_err("try { throw 1; } catch (e) { throw e + 1; }"),
+               "ERROR: 2\n    at <script>:1:30");
+    assert_eq!(run_err("try { throw 1; } finally { 3 }"), "ERROR: 1\n    at <script>:1:7");
+}
@@ -0,0 +1,40 @@
+extern crate plasma;
+
+mod common;
+
+use common::{run, run_err};
+
+#[test]
+fn arrow_functions_take_expression_or_block_bodies() {
+    let code = "var add = (a, b) => a + b; var sq = x => { return x * x; }; [add(1, 2), sq(3)]";
+    assert_eq!(run(code), Ok("[3, 9]".to_owned()));
+    assert_eq!(run("(() => 7)()"), Ok("7".to_owned()));
+}
+
+#[test]
+fn defaults_fill_missing_arguments() {
+    assert_eq!(run("var f = function(a, b = 2) { a + b }; [f(1), f(1, 5)]"),
+               Ok("[3, 6]".to_owned()));
+    assert_eq!(run("var f = (a, b = a + 1, ...r) => [a, b, r]; f(1)"),
+               Ok("[1, 2, []]".to_owned()));
+}
+
+#[test]
+fn rest_parameters_collect_the_other_arguments() {
+    assert_eq!(run("var f = function(a, ...rest) { rest }; [f(1), f(1, 2, 3)]"),
+               Ok("[[], [2, 3]]".to_owned()));
+    assert_eq!(run("var f = function(a, b) { [a, b] }; f(1, 2, 3)"),
+               Ok("[1, 2]".to_owned()));
+}
+
+#[test]
+fn missing_arguments_are_arity_errors() {
+    assert_eq!(run_err("var f = (a, b) => a + b; f(1)"),
+               "ERROR: wrong number of arguments: expected 2, got 1\n    at <script>:1:26");
+    assert!(run_err("var f = x => x * 2; f()")
+                .starts_with("ERROR: wrong number of arguments: expected 1, got 0"));
+    assert!(run_err("var f = function(a, b = 2) { a + b }; f()")
+                .starts_with("ERROR: wrong number of arguments: expected at least 1, got 0"));
+    assert!(run_err("var f = function(a, ...rest) { rest }; f()")
+                .starts_with("ERROR: wrong number of arguments: expected at least 1, got 0"));
+}
@@ -0,0 +1,116 @@
+extern crate plasma;
+
+use std::panic::{self, AssertUnwindSafe};
+
+use plasma::interpreter::{Engine, ExecutionError, Executor};
+
+// Source fragments the random programs are stitched from, covering every
+// token the lexer knows plus a few it does not.
+const FRAGMENTS: &[&str] = &["var", "let", "const", "function", "class", "extends", "new",
+                             "super", "this", "if", "else", "return", "for", "of", "throw",
+                             "try", "catch", "finally", "true", "false", "x", "y", "f", "len",
+                             "map", "0", "1", "42", "9223372036854775807", "\"s\"", "\"",
+                             "=", "==", "!=", "=>", "+", "-", "*", "/", "^", "!", "<", ">",
+                             "...", "..", ".", ",", ";", ":", "?", "(", ")", "[", "]", "{",
+                             "}", "@", "é", "\n"];
+
+// A xorshift generator, seeded so that failures are reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+fn execute_without_panic(code: &str) {
+    for &engine in [Engine::TreeWalker, Engine::Vm].iter() {
+        let run = || Executor::new().with_engine(engine).execute(code);
+        let result = panic::catch_unwind(AssertUnwindSafe(run));
+        assert!(result.is_ok(), "executing {:?} with {:?} panicked", code, engine);
+    }
+}
+
+#[test]
+fn random_token_streams_never_panic() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    for _ in 0..5000 {
+        let len = 1 + rng.below(40);
+        let code = (0..len)
+            .map(|_| FRAGMENTS[rng.below(FRAGMENTS.len())])
+            .collect::<Vec<_>>()
+            .join(" ");
+        execute_without_panic(&code);
+    }
+}
+
+#[test]
+fn edge_cases_never_panic() {
+    let cases = ["",
+                 "\"unterminated",
+                 "var s = \"héllo\"; s.upper();",
+                 "let x = 1",
+                 "if (true) { 1",
+                 "9223372036854775807 + 1",
+                 "-9223372036854775807 - 2",
+                 "2 ^ 64",
+                 "2 ^ -1",
+                 "1 / 0",
+                 "99999999999999999999",
+                 "[1, 2].map()",
+                 "[].reduce((a, b) => a)",
+                 "super(1)",
+                 "new 1",
+                 "throw"];
+    for code in cases.iter() {
+        execute_without_panic(code);
+    }
+}
+
+#[test]
+fn deeply_nested_input_is_a_syntax_error() {
+    let cases = [format!("{}1", "-".repeat(20000)),
+                 "[".repeat(3000),
+                 format!("{}1{}", "(".repeat(3000), ")".repeat(3000)),
+                 format!("{}{}", "{".repeat(3000), "}".repeat(3000)),
+                 format!("var {} = 1", "[".repeat(3000)),
+                 format!("x{}", " = x".repeat(3000)),
+                 "function() { return ".repeat(3000),
+                 // Chains nest their left operands as deeply.
+                 format!("{}1", "1+".repeat(20000)),
+                 format!("f{}", "()".repeat(3000)),
+                 format!("x{}", ".y".repeat(3000))];
+    for code in cases.iter() {
+        execute_without_panic(code);
+        match Executor::new().execute(code) {
+            Err(ExecutionError::Syntax(ref msg)) if !msg.contains('\n') => {
+                assert!(msg.starts_with("parse error: nesting deeper than 256 levels at "),
+                        "parsing {:.20}... gave {}",
+                        code,
+                        msg)
+            }
+            res => panic!("parsing {:.20}... gave {:?}", code, res),
+        }
+    }
+}
+
+#[test]
+fn nesting_within_the_limit_runs() {
+    let code = format!("{}1{}", "[".repeat(200), "]".repeat(200));
+    let sum = format!("{}1", "1+".repeat(200));
+    for &engine in [Engine::TreeWalker, Engine::Vm].iter() {
+        for &optimize in [false, true].iter() {
+            let mut exec = Executor::new().with_engine(engine).with_optimizer(optimize);
+            let result = exec.execute(&code).unwrap();
+            assert_eq!(result, format!("{}1{}", "[".repeat(200), "]".repeat(200)));
+            assert_eq!(exec.execute(&sum), Ok("201".to_owned()));
+        }
+    }
+}
@@ -0,0 +1,24 @@
+extern crate plasma;
+
+mod common;
+
+use common::{run, run_err};
+
+#[test]
+fn unknown_identifiers_are_errors_where_they_are_used() {
+    assert_eq!(run_err("var a = 1;\nvar b = a +\n    missing;"),
+               "ERROR: identifier not found: missing\n    at <script>:3:5");
+    assert_eq!(run_err("[1, nope]"), "ERROR: identifier not found: nope\n    at <script>:1:5");
+    assert_eq!(run_err("nope = 1"), "ERROR: identifier not found: nope\n    at <script>:1:1");
+    assert_eq!(run_err("var g = function() {\n  return   zz;\n};\ng()"),
+               "ERROR: identifier not found: zz\n    at g (<script>:2:12)\n    at <script>:4:1");
+}
+
+#[test]
+fn calls_with_unknown_arguments_are_not_made() {
+    assert_eq!(run_err("var f = function(x) { x }; f(nope)"),
+               "ERROR: identifier not found: nope\n    at <script>:1:30");
+    assert_eq!(run("var called = false; var f = function() { called = true; };
+                    try { f(nope); } catch (e) { [called, e.message] }"),
+               Ok("[False, identifier not found: nope]".to_owned()));
+}
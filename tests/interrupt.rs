@@ -0,0 +1,88 @@
+extern crate plasma;
+
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use plasma::interpreter::{self, Engine, ExecutionError, Executor};
+
+use common::ENDLESS;
+
+fn interrupt_later(exec: &Executor) {
+    let handle = exec.interrupt_handle();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        handle.interrupt();
+    });
+}
+
+#[test]
+fn interrupting_stops_a_running_script() {
+    for engine in [Engine::TreeWalker, Engine::Vm] {
+        let mut exec = Executor::new().with_engine(engine);
+        interrupt_later(&exec);
+        match exec.execute(ENDLESS) {
+            Err(ExecutionError::Cancelled(msg)) => {
+                assert!(msg.starts_with("ERROR: execution cancelled"), "{}", msg)
+            }
+            res => panic!("unexpected {:?} on {:?}", res, engine),
+        }
+    }
+}
+
+#[test]
+fn cancellation_cannot_be_caught() {
+    let code = ENDLESS.replace("{ for (var b of xs) { a + b; } }",
+                               "{ try { for (var b of xs) { a + b; } } catch (e) { 0 } }");
+    for engine in [Engine::TreeWalker, Engine::Vm] {
+        let mut exec = Executor::new().with_engine(engine);
+        interrupt_later(&exec);
+        let res = exec.execute(&code);
+        assert!(matches!(res, Err(ExecutionError::Cancelled(_))), "{:?} on {:?}", res, engine);
+    }
+}
+
+#[test]
+fn the_executor_is_usable_after_a_cancellation() {
+    for engine in [Engine::TreeWalker, Engine::Vm] {
+        let mut exec = Executor::new().with_engine(engine);
+        interrupt_later(&exec);
+        assert!(exec.execute(ENDLESS).is_err());
+        assert_eq!(exec.execute("[1, 2, 3].map(x => x * 2);"),
+                   Ok("[2, 4, 6]".to_owned()),
+                   "on {:?}",
+                   engine);
+    }
+}
+
+#[test]
+fn interrupts_raised_before_running_are_not_lost() {
+    // As if raised while the script was parsed or compiled, which happens
+    // before it runs. The timeout only keeps a failure from hanging.
+    for engine in [Engine::TreeWalker, Engine::Vm] {
+        let mut exec = Executor::new().with_engine(engine).with_timeout(Duration::from_secs(5));
+        exec.interrupt_handle().interrupt();
+        let res = exec.execute(ENDLESS);
+        assert!(matches!(res, Err(ExecutionError::Cancelled(_))), "{:?} on {:?}", res, engine);
+        assert_eq!(exec.execute("1 + 1"), Ok("2".to_owned()));
+    }
+
+    let program = interpreter::compile(ENDLESS).unwrap();
+    let mut exec = Executor::new().with_timeout(Duration::from_secs(5));
+    exec.interrupt_handle().interrupt();
+    let res = exec.execute_compiled(&program);
+    assert!(matches!(res, Err(ExecutionError::Cancelled(_))), "{:?}", res);
+}
+
+#[test]
+fn interrupts_of_a_finished_run_do_not_stop_the_next() {
+    for engine in [Engine::TreeWalker, Engine::Vm] {
+        let mut exec = Executor::new().with_engine(engine);
+        // A script with no calls or loops runs to its end even when
+        // interrupted, which must not carry over.
+        exec.interrupt_handle().interrupt();
+        assert_eq!(exec.execute("1 + 1"), Ok("2".to_owned()));
+        assert_eq!(exec.execute("[1, 2].map(x => x + 1)"), Ok("[2, 3]".to_owned()));
+    }
+}
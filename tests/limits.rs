@@ -0,0 +1,72 @@
+extern crate plasma;
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use plasma::interpreter::{Engine, ExecutionError, Executor};
+
+use common::ENDLESS;
+
+fn is_exhausted(res: &Result<String, ExecutionError>) -> bool {
+    matches!(*res, Err(ExecutionError::ResourceExhausted(_)))
+}
+
+#[test]
+fn running_out_of_fuel_stops_the_script() {
+    let mut exec = Executor::new().with_fuel(10000);
+    let res = exec.execute(ENDLESS);
+    assert!(is_exhausted(&res), "{:?}", res);
+}
+
+#[test]
+fn fuel_exhaustion_cannot_be_caught() {
+    let mut exec = Executor::new().with_fuel(10000);
+    let res = exec.execute(&format!("try {{ {} }} catch (e) {{ 1 }}", ENDLESS));
+    assert!(is_exhausted(&res), "{:?}", res);
+}
+
+#[test]
+fn every_execution_gets_the_whole_fuel() {
+    let mut exec = Executor::new().with_fuel(100);
+    for _ in 0..10 {
+        assert_eq!(exec.execute("[1, 2, 3].map(x => x * 2);"),
+                   Ok("[2, 4, 6]".to_owned()));
+    }
+}
+
+#[test]
+fn long_running_scripts_time_out() {
+    let mut exec = Executor::new().with_timeout(Duration::from_millis(50));
+    let start = Instant::now();
+    let res = exec.execute(ENDLESS);
+    assert!(is_exhausted(&res), "{:?}", res);
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn printing_shared_values_is_limited_like_running() {
+    // Two million leaves printed from a few dozen arrays.
+    let shared = "var a = [1]; for (var i of \"xxxxxxxxxxxxxxxxxxxxx\") { a = [a, a]; }";
+    for engine in [Engine::TreeWalker, Engine::Vm] {
+        for print in ["\"\" + a", "a.join(\",\")", "a"] {
+            let code = format!("{} {}", shared, print);
+            let start = Instant::now();
+            let res = Executor::new().with_engine(engine).with_fuel(100000).execute(&code);
+            assert!(is_exhausted(&res), "{:?}", res);
+            let res = Executor::new()
+                .with_engine(engine)
+                .with_timeout(Duration::from_millis(100))
+                .execute(&code);
+            assert!(is_exhausted(&res), "{:?}", res);
+            let res = Executor::new().with_engine(engine).with_memory_limit(1 << 20).execute(&code);
+            match res {
+                Err(ExecutionError::Runtime(msg)) => {
+                    assert!(msg.starts_with("ERROR: out of memory"), "{}", msg)
+                }
+                res => panic!("printing {} on {:?} gave {:?}", print, engine, res),
+            }
+            assert!(start.elapsed() < Duration::from_secs(5), "{:?}", start.elapsed());
+        }
+    }
+}
@@ -0,0 +1,101 @@
+extern crate plasma;
+
+use plasma::interpreter::{Engine, ExecutionError, Executor};
+
+const TWENTY: &str = "[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]";
+
+#[test]
+fn growing_a_string_past_the_limit_fails() {
+    let mut exec = Executor::new().with_memory_limit(1 << 20);
+    let code = format!("var s = \"x\"; for (var i of {}) {{ s = s + s; }}", TWENTY);
+    match exec.execute(&code) {
+        Err(ExecutionError::Runtime(msg)) => assert!(msg.starts_with("ERROR: out of memory")),
+        res => panic!("unexpected {:?}", res),
+    }
+}
+
+#[test]
+fn running_out_of_memory_is_catchable() {
+    let mut exec = Executor::new().with_memory_limit(1 << 20);
+    let code = format!("var grow = function() {{
+                            var xs = [0];
+                            for (var i of {}) {{ xs = [...xs, ...xs]; }}
+                        }};
+                        var msg = \"\";
+                        try {{ grow(); }} catch (e) {{ msg = e.message; }}
+                        msg;",
+                       TWENTY);
+    assert_eq!(exec.execute(&code), Ok("out of memory".to_owned()));
+    // What the failed call built is gone with it.
+    assert!(exec.memory_usage() < 1 << 16, "{}", exec.memory_usage());
+}
+
+#[test]
+fn usage_follows_the_values_held() {
+    let mut exec = Executor::new();
+    let before = exec.memory_usage();
+    exec.execute("var xs = [0];
+                  for (var i of [0, 0, 0, 0, 0, 0, 0, 0, 0, 0]) { xs = [...xs, ...xs]; }")
+        .unwrap();
+    // 1024 slots, of a few words each.
+    let holding = exec.memory_usage();
+    assert!(holding > before + 1024 * 32, "{} -> {}", before, holding);
+
+    exec.execute("xs = [];").unwrap();
+    assert!(exec.memory_usage() < holding / 10, "{}", exec.memory_usage());
+}
+
+#[test]
+fn copies_share_what_they_hold() {
+    let mut exec = Executor::new();
+    exec.execute(&format!("var s = \"x\"; for (var i of {}) {{ s = s + s; }}
+                           var xs = [s, s];",
+                          TWENTY))
+        .unwrap();
+    let holding = exec.memory_usage();
+    assert!(holding > 1 << 20, "{}", holding);
+    exec.execute("var copies = [xs, xs, s, s, s, s];").unwrap();
+    assert!(exec.memory_usage() < holding + 1024, "{} -> {}", holding, exec.memory_usage());
+}
+
+#[test]
+fn cycles_are_freed_once_runs_end() {
+    for engine in [Engine::TreeWalker, Engine::Vm] {
+        let mut exec = Executor::new().with_engine(engine).with_memory_limit(1 << 16);
+        exec.execute("var greet = function() {
+                          var o = {name: \"plasma\", greet: function() { o.name }};
+                          o.greet()
+                      };")
+            .unwrap();
+        let before = exec.memory_usage();
+        for _ in 0..10000 {
+            assert_eq!(exec.execute("greet()"), Ok("plasma".to_owned()));
+        }
+        assert_eq!(exec.memory_usage(), before, "on {:?}", engine);
+    }
+}
+
+#[test]
+fn scopes_the_globals_reach_are_kept() {
+    for engine in [Engine::TreeWalker, Engine::Vm] {
+        let mut exec = Executor::new().with_engine(engine);
+        exec.execute("var counter = function() {
+                           var n = 0;
+                           var c = {next: function() { n = n + 1; n }};
+                           c
+                       };
+                       var c = counter();
+                       var fs = {};
+                       { let k = 5; fs.add = function() { k + c.next() }; }
+                       var all = [fs];
+                       class Box {
+                           constructor(f) { this.f = f; }
+                           get() { this.f() }
+                       }
+                       var box = new Box(function() { var [first] = all; first.add() });")
+            .unwrap();
+        assert_eq!(exec.execute("c.next()"), Ok("1".to_owned()));
+        assert_eq!(exec.execute("fs.add()"), Ok("7".to_owned()));
+        assert_eq!(exec.execute("box.get()"), Ok("8".to_owned()));
+    }
+}
@@ -0,0 +1,60 @@
+extern crate plasma;
+
+mod common;
+
+use common::{run, run_err};
+
+#[test]
+fn strings_arrays_and_hashes_have_methods() {
+    assert_eq!(run("[\"abc\".upper(), \"ABC\".lower(), \" x \".trim(), \"h\u{e9}llo\".len()]"),
+               Ok("[ABC, abc, x, 5]".to_owned()));
+    assert_eq!(run("[\"a,b\".split(\",\"), \"ab\".split(\"\")]"),
+               Ok("[[a, b], [a, b]]".to_owned()));
+    assert_eq!(run("[[1, 2, 3].map((x, i) => x * i), [1, 2, 3].filter(x => x > 1), [1, 2].len()]"),
+               Ok("[[0, 2, 6], [2, 3], 2]".to_owned()));
+    assert_eq!(run("[[1, 2, 3].reduce((a, b) => a + b), [1, 2].reduce((a, b) => a + b, 10)]"),
+               Ok("[6, 13]".to_owned()));
+    assert_eq!(run("var h = {a: 1, b: 2}; [h.keys(), h.values(), h.len()]"),
+               Ok("[[a, b], [1, 2], 2]".to_owned()));
+}
+
+#[test]
+fn properties_come_before_methods() {
+    assert_eq!(run("var o = {len: function() { 42 }}; o.len()"), Ok("42".to_owned()));
+}
+
+#[test]
+fn missing_methods_name_the_type() {
+    assert_eq!(run_err("\"abc\".nope()"),
+               "ERROR: STRING has no method 'nope'\n    at <script>:1:1");
+    assert!(run_err("[].nope()").starts_with("ERROR: ARRAY has no method 'nope'"));
+    assert!(run_err("({}).nope()").starts_with("ERROR: HASH has no method 'nope'"));
+    assert!(run_err("1.nope()").starts_with("ERROR: INTEGER has no method 'nope'"));
+}
+
+#[test]
+fn arguments_are_checked() {
+    assert!(run_err("\"a\".split(1)")
+                .starts_with("ERROR: argument to 'split' must be STRING, got INTEGER"));
+    assert!(run_err("\"a\".upper(1)")
+                .starts_with("ERROR: wrong number of arguments: expected 0, got 1"));
+    assert!(run_err("[1].reduce()")
+                .starts_with("ERROR: wrong number of arguments: expected 1 or 2, got 0"));
+    assert!(run_err("[].reduce((a, b) => a)")
+                .starts_with("ERROR: reduce of empty array with no initial value"));
+}
+
+#[test]
+fn join_separates_with_commas_by_default() {
+    assert_eq!(run("[1, \"a\", [2]].join()"), Ok("1,a,[2]".to_owned()));
+    assert_eq!(run("[1, 2].join(\" - \")"), Ok("1 - 2".to_owned()));
+    assert!(run_err("[1].join(\",\", \";\")")
+                .starts_with("ERROR: wrong number of arguments: expected 0 or 1, got 2"));
+}
+
+#[test]
+fn push_needs_a_value() {
+    assert_eq!(run("var xs = [1]; [xs.push(2, 3), xs]"), Ok("[3, [1, 2, 3]]".to_owned()));
+    assert!(run_err("[1].push()")
+                .starts_with("ERROR: wrong number of arguments: expected at least 1, got 0"));
+}
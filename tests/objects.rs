@@ -0,0 +1,55 @@
+extern crate plasma;
+
+mod common;
+
+use common::{run, run_err};
+
+#[test]
+fn properties_are_read_and_assigned() {
+    assert_eq!(run("var o = {a: 1}; o.b = 2; o.a = o.a + 1; o"),
+               Ok("{a: 2, b: 2}".to_owned()));
+    assert_eq!(run("var o = {x: {y: 1}}; o.x.y = 2; o"), Ok("{x: {y: 2}}".to_owned()));
+    assert_eq!(run("var a = 1; var o = {a, b: a + 1, \"c\": 3}; o"),
+               Ok("{a: 1, b: 2, c: 3}".to_owned()));
+    assert_eq!(run("var o = {}; o.missing"), Ok("null".to_owned()));
+}
+
+#[test]
+fn only_objects_have_properties() {
+    assert_eq!(run_err("var n = 1; n.x = 2;"),
+               "ERROR: cannot set property 'x' of INTEGER\n    at <script>:1:12");
+    assert_eq!(run_err("1.foo"),
+               "ERROR: cannot read property 'foo' of INTEGER\n    at <script>:1:1");
+}
+
+#[test]
+fn methods_see_their_object_as_this() {
+    assert_eq!(run("var o = {v: 1, get: function() { this.v }}; o.get()"),
+               Ok("1".to_owned()));
+    assert_eq!(run("var o = {n: 0, inc: function() { this.n = this.n + 1; this }};
+                    o.inc().inc().n"),
+               Ok("2".to_owned()));
+    // Arrow functions keep the `this` of where they are made.
+    assert_eq!(run("var o = {v: 1, f: function() { var g = () => this.v; g() }}; o.f()"),
+               Ok("1".to_owned()));
+    // A method called on its own has no `this`.
+    assert_eq!(run("var o = {get: function() { this }}; var g = o.get; g()"),
+               Ok("null".to_owned()));
+}
+
+#[test]
+fn values_holding_themselves_are_printed_once() {
+    assert_eq!(run("var a = [1]; a.push(a); a"), Ok("[1, [...]]".to_owned()));
+    assert_eq!(run("var h = {}; h.self = h; h.list = [h]; h"),
+               Ok("{self: {...}, list: [{...}]}".to_owned()));
+    assert_eq!(run("class P { constructor() { this.me = this; } } new P()"),
+               Ok("P {me: P {...}}".to_owned()));
+    // Values shared without a cycle are printed in full each time.
+    assert_eq!(run("var x = [1]; [x, x, {k: x}]"), Ok("[[1], [1], {k: [1]}]".to_owned()));
+
+    assert_eq!(run("var a = [1]; a.push(a); try { \"<\" + a + \">\" } catch (e) { e }"),
+               Ok("<[1, [...]]>".to_owned()));
+    assert_eq!(run("var h = {}; h.self = h; \"\" + h"), Ok("{self: {...}}".to_owned()));
+    assert_eq!(run("var a = [1, 2]; a.push(a); a.join(\";\")"),
+               Ok("1;2;[1, 2, [...]]".to_owned()));
+}
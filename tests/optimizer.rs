@@ -0,0 +1,83 @@
+extern crate plasma;
+
+use plasma::interpreter::{Engine, ExecutionError, Executor};
+
+// Scripts that must run the same with and without the optimizer, down to
+// the traces of their errors.
+const PROGRAMS: &[&str] = &[
+    "60 * 60 * 24",
+    "1 + 2 * 3 - 4 / 2",
+    "2 ^ 10 - -(3 - 5)",
+    "\"a\" + \"b\" + 1",
+    "\"n = \" + (40 + 2)",
+    "1 < 2 == 3 > 4",
+    "\"a\" == \"a\" != false",
+    "!!0",
+    "!!!true",
+    "var x = 0; [!!x, !!!x, !x ? 1 : 2, x ? 1 : 2]",
+    "var x = []; if (!!x) { 1 } else { 2 }",
+    "if (false) { 1 }",
+    "if (false) { 1 } else { 2 }",
+    "if (1 > 2) { 1 } else if (!!1) { 2 } else { 3 }",
+    "if (true) { let a = 1; a } else { undefined }",
+    "if (0) { var v = 1; } v",
+    "if (false) { var w = 1; } w",
+    "var f = function(n) { if (false) { return 0; } n * (60 * 60) }; f(2)",
+    "[1, 2, 3].map(x => x * (2 + 3))",
+    "var s = \"\"; for (let c of \"ab\" + \"cd\") { s = c + s; } s",
+    // Operations that fail are left to fail when run.
+    "9223372036854775807 + 1",
+    "-(-9223372036854775807 - 1)",
+    "1 / (2 - 2)",
+    "2 ^ -1",
+    "\"a\" - \"b\"",
+    "1 + true",
+    "-\"a\"",
+    "var f = function() { 9223372036854775807 * 2 }; 1",
+    "var f = function() { 9223372036854775807 * 2 }; f()",
+    "try { 1 / 0 } catch (e) { e.message }",
+];
+
+fn execute(engine: Engine, optimize: bool, code: &str) -> Result<String, ExecutionError> {
+    Executor::new().with_engine(engine).with_optimizer(optimize).execute(code)
+}
+
+#[test]
+fn optimized_scripts_run_the_same() {
+    for engine in [Engine::TreeWalker, Engine::Vm].iter() {
+        for code in PROGRAMS.iter() {
+            assert_eq!(execute(*engine, true, code),
+                       execute(*engine, false, code),
+                       "running {:?} on {:?}",
+                       code,
+                       engine);
+        }
+    }
+}
+
+#[test]
+fn overflow_is_raised_when_run() {
+    let mut exec = Executor::new().with_optimizer(true);
+    exec.execute("var big = function() { 9223372036854775807 + 1 };").unwrap();
+    let err = exec.execute("big()").unwrap_err();
+    assert!(err.to_string().contains("integer overflow: 9223372036854775807 + 1"),
+            "got {}",
+            err);
+}
+
+#[test]
+fn folded_expressions_take_fewer_steps() {
+    let code = "var total = 0; for (let i of [1, 2, 3]) { total = total + 60 * 60 * 24; } total";
+    let fuel = 36;
+    let unoptimized = Executor::new().with_fuel(fuel).execute(code);
+    assert!(unoptimized.is_err(), "got {:?}", unoptimized);
+    let optimized = Executor::new().with_fuel(fuel).with_optimizer(true).execute(code);
+    assert_eq!(optimized, Ok("259200".to_owned()));
+}
+
+#[test]
+fn functions_show_their_folded_source() {
+    let mut exec = Executor::new().with_optimizer(true);
+    assert_eq!(exec.execute("function(x) { if (false) { x } else { x * (2 + 3) } }"),
+               Ok("function(x, ) {\niftrue (x * 5)\n}".to_owned()));
+}
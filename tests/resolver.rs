@@ -0,0 +1,109 @@
+extern crate plasma;
+
+mod common;
+
+use plasma::interpreter::{check, Engine, ExecutionError, Executor};
+
+use common::run;
+
+fn syntax_error(msg: &str) -> Result<String, ExecutionError> {
+    Err(ExecutionError::Syntax(format!("resolve error: {}", msg)))
+}
+
+#[test]
+fn duplicate_declarations_are_reported_before_running() {
+    assert_eq!(run("let d = 1; let d = 2;"),
+               syntax_error("identifier 'd' has already been declared at 1:16"));
+    assert_eq!(run("var f = function() { if (true) { const c = 1; class c {} } };"),
+               syntax_error("identifier 'c' has already been declared at 1:53"));
+    assert_eq!(run("var v = 1; let v = 2;"),
+               syntax_error("identifier 'v' has already been declared at 1:16"));
+    assert_eq!(run("var v = 1; var v = 2; v"), Ok("2".to_owned()));
+    assert_eq!(run("var f = function(a, a) {};"),
+               syntax_error("identifier 'a' has already been declared at 1:21"));
+    assert_eq!(run("var f = function(a, [b, {c: a}]) {};"),
+               syntax_error("identifier 'a' has already been declared at 1:29"));
+    assert_eq!(run("var f = function(a, ...a) {};"),
+               syntax_error("identifier 'a' has already been declared at 1:24"));
+    assert_eq!(run("var f = function(a) { var a; a }; f(1)"), Ok("1".to_owned()));
+    assert_eq!(run("let s = 1; if (true) { let s = 2; } s"), Ok("1".to_owned()));
+}
+
+#[test]
+fn checking_reports_the_errors_running_would() {
+    assert_eq!(check("let d = 1; let d = 2;").map(|_| String::new()),
+               syntax_error("identifier 'd' has already been declared at 1:16"));
+    assert!(matches!(check("var = 1;"), Err(ExecutionError::Syntax(_))));
+    // Nothing runs, so runtime errors are not reported.
+    assert_eq!(check("nope();"), Ok(()));
+}
+
+#[test]
+fn use_before_declaration_is_reported_before_running() {
+    assert_eq!(run("x; let x = 1;"),
+               syntax_error("cannot access 'x' before its declaration at 1:1"));
+    assert_eq!(run("let a = 1; if (true) { let b = a; let a = 2; }"),
+               syntax_error("cannot access 'a' before its declaration at 1:32"));
+    assert_eq!(run("let y = y + 1;"),
+               syntax_error("cannot access 'y' before its declaration at 1:9"));
+    assert_eq!(run("new A(); class A {}"),
+               syntax_error("cannot access 'A' before its declaration at 1:5"));
+}
+
+#[test]
+fn functions_may_use_variables_declared_after_them() {
+    assert_eq!(run("if (true) { let f = () => x; let x = 1; f() }"),
+               Ok("1".to_owned()));
+    assert_eq!(run("if (true) { let f = () => x; f(); let x = 1; }"),
+               Err(ExecutionError::Runtime("ERROR: identifier not found: x\n    at f \
+                                            (<script>:1:27)\n    at <script>:1:30"
+                                               .to_owned())));
+}
+
+#[test]
+fn var_declarations_belong_to_their_function() {
+    assert_eq!(run("var f = function(n) {
+                        if (n > 0) { var x = n; }
+                        for (var i of [1, 2]) { x = x + i; }
+                        [x, i]
+                    };
+                    f(1)"),
+               Ok("[4, 2]".to_owned()));
+    assert_eq!(run("var g = function(a, b = a + 1) { var a = a * 10; [a, b] }; g(1)"),
+               Ok("[10, 2]".to_owned()));
+}
+
+#[test]
+fn closures_see_their_own_bindings() {
+    assert_eq!(run("var make = function(n) {
+                        let fs = [];
+                        for (let i of [1, 2, 3]) { fs = [...fs, () => i * n]; }
+                        fs
+                    };
+                    make(10).map(f => f())"),
+               Ok("[10, 20, 30]".to_owned()));
+    assert_eq!(run("var outer = function() {
+                        let x = 1;
+                        var inner = function() { let y = x; x = x + 1; [y, x] };
+                        inner();
+                        inner()
+                    };
+                    outer()"),
+               Ok("[2, 3]".to_owned()));
+    assert_eq!(run("var f = function() { const c = 1; var g = () => { c = 2; }; g() }; f()"),
+               Err(ExecutionError::Runtime("ERROR: assignment to constant variable 'c'\n    at g \
+                                            (<script>:1:51)\n    at f (<script>:1:61)\n    \
+                                            at <script>:1:68"
+                                               .to_owned())));
+}
+
+#[test]
+fn globals_are_shared_between_executions() {
+    for engine in [Engine::TreeWalker, Engine::Vm].iter() {
+        let mut exec = Executor::new().with_engine(*engine);
+        exec.execute("let total = 0; var add = function(n) { let t = total + n; total = t; };")
+            .unwrap();
+        exec.execute("add(2); add(3);").unwrap();
+        assert_eq!(exec.execute("total"), Ok("5".to_owned()));
+    }
+}
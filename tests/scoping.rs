@@ -0,0 +1,70 @@
+extern crate plasma;
+
+mod common;
+
+use plasma::interpreter::{Engine, ExecutionError, Executor};
+
+use common::{run, run_err};
+
+#[test]
+fn a_brace_starting_a_statement_opens_a_block() {
+    assert_eq!(run("let x = 1; { let x = 2; } x"), Ok("1".to_owned()));
+    assert_eq!(run("var v = 1; { var v = 2; } v"), Ok("2".to_owned()));
+    assert_eq!(run("{ 1; 2 }"), Ok("2".to_owned()));
+    assert_eq!(run("({a: 1}).a"), Ok("1".to_owned()));
+}
+
+#[test]
+fn let_and_const_live_in_their_block() {
+    assert_eq!(run("let a = 1; if (true) { let a = 2; a = 3; } a"), Ok("1".to_owned()));
+    assert_eq!(run("let x = 1; { x = 2; } x"), Ok("2".to_owned()));
+    assert_eq!(run("{ let a = 1; } a"),
+               Err(ExecutionError::Runtime("ERROR: identifier not found: a\n    at <script>:1:16"
+                                               .to_owned())));
+    assert!(run_err("if (true) { const k = 1; } k").starts_with("ERROR: identifier not found: k"));
+}
+
+#[test]
+fn var_is_scoped_to_the_function() {
+    assert_eq!(run("var f = function() { if (true) { var v = 1; } v }; f()"),
+               Ok("1".to_owned()));
+    assert!(run_err("var f = function() { var v = 1; }; f(); v")
+                .starts_with("ERROR: identifier not found: v"));
+}
+
+#[test]
+fn redeclaring_a_var_without_a_value_keeps_it() {
+    assert_eq!(run("var x = 1; var x; x"), Ok("1".to_owned()));
+    assert_eq!(run("var f = function() { var y = 2; var y; y }; f()"), Ok("2".to_owned()));
+    assert_eq!(run("var g = function() {
+                        var n = 0;
+                        for (var i of [1, 2, 3]) { var k; if (i == 1) { k = 5; } n = n + k; }
+                        n
+                    };
+                    g()"),
+               Ok("15".to_owned()));
+    for engine in [Engine::TreeWalker, Engine::Vm] {
+        let mut exec = Executor::new().with_engine(engine);
+        exec.execute("var z = 3;").unwrap();
+        assert_eq!(exec.execute("var z; z"), Ok("3".to_owned()), "on {:?}", engine);
+    }
+}
+
+#[test]
+fn constants_cannot_be_reassigned() {
+    assert_eq!(run("const c = 1; c = 2;"),
+               Err(ExecutionError::Runtime("ERROR: assignment to constant variable 'c'\n    \
+                                            at <script>:1:14"
+                                               .to_owned())));
+    assert!(run_err("var f = () => { c = 1; }; const c = 0; f()")
+                .starts_with("ERROR: assignment to constant variable 'c'"));
+}
+
+#[test]
+fn redeclaring_in_a_block_is_an_error() {
+    assert_eq!(run("let d = 1; let d = 2;"),
+               Err(ExecutionError::Syntax("resolve error: identifier 'd' has already been \
+                                           declared at 1:16"
+                                              .to_owned())));
+    assert_eq!(run("let d = 1; { let d = 2; } d"), Ok("1".to_owned()));
+}
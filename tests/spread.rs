@@ -0,0 +1,27 @@
+extern crate plasma;
+
+mod common;
+
+use common::{run, run_err};
+
+#[test]
+fn arrays_spread_into_array_literals() {
+    assert_eq!(run("var xs = [1, 2]; [0, ...xs, 3, ...xs]"),
+               Ok("[0, 1, 2, 3, 1, 2]".to_owned()));
+    assert_eq!(run("[...[]]"), Ok("[]".to_owned()));
+}
+
+#[test]
+fn arrays_spread_into_arguments() {
+    assert_eq!(run("var f = function(a, b, c) { [a, b, c] }; var xs = [2, 3]; f(1, ...xs)"),
+               Ok("[1, 2, 3]".to_owned()));
+    assert_eq!(run("var f = function(...r) { r }; f(...[], ...[1], 2)"),
+               Ok("[1, 2]".to_owned()));
+}
+
+#[test]
+fn only_arrays_spread() {
+    assert_eq!(run_err("[...1]"), "ERROR: cannot spread INTEGER\n    at <script>:1:1");
+    assert!(run_err("var f = function(a) { a }; f(...\"ab\")")
+                .starts_with("ERROR: cannot spread STRING"));
+}
@@ -0,0 +1,29 @@
+extern crate plasma;
+
+mod common;
+
+use common::run;
+
+#[test]
+fn the_condition_binds_looser_than_operators() {
+    assert_eq!(run("1 + 1 == 2 ? 3 + 4 : 5"), Ok("7".to_owned()));
+    assert_eq!(run("1 < 2 ? \"a\" : \"b\""), Ok("a".to_owned()));
+}
+
+#[test]
+fn assignments_bind_looser_than_the_condition() {
+    assert_eq!(run("var x = false ? 1 : 2; x"), Ok("2".to_owned()));
+    assert_eq!(run("var x; x = true ? 1 : 2; x"), Ok("1".to_owned()));
+}
+
+#[test]
+fn nested_conditions_group_to_the_right() {
+    assert_eq!(run("false ? 1 : 0 ? 2 : 3"), Ok("2".to_owned()));
+    assert_eq!(run("false ? 1 : false ? 2 : 3"), Ok("3".to_owned()));
+    assert_eq!(run("true ? false ? 1 : 2 : 3"), Ok("2".to_owned()));
+}
+
+#[test]
+fn zero_and_empty_arrays_are_truthy() {
+    assert_eq!(run("[0 ? 1 : 2, [] ? 1 : 2, false ? 1 : 2]"), Ok("[1, 1, 2]".to_owned()));
+}
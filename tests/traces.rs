@@ -0,0 +1,97 @@
+extern crate plasma;
+
+mod common;
+
+use plasma::interpreter::{compile, compile_named, Engine, ExecutionError, Executor};
+
+use common::run_err;
+
+// The error `code` fails with when read from `file.js`, on both engines.
+fn trace(code: &str) -> String {
+    let mut results = [Engine::TreeWalker, Engine::Vm].iter().map(|engine| {
+        match Executor::new().with_engine(*engine).with_source_name("file.js").execute(code) {
+            Err(ExecutionError::Runtime(msg)) => msg,
+            res => panic!("running {:?} on {:?} gave {:?}", code, engine, res),
+        }
+    });
+    let walked = results.next().unwrap();
+    assert_eq!(Some(walked.clone()), results.next(), "running {:?}", code);
+    walked
+}
+
+#[test]
+fn frames_name_the_function_and_where_it_was_called() {
+    assert_eq!(trace("var mult = function(a, b) { a * b / 0 };
+var callback = function(f, a, b) { f(a, b) };
+callback(mult, 10, 0)"),
+               "ERROR: division by zero
+    at mult (file.js:1:29)
+    at callback (file.js:2:36)
+    at file.js:3:1");
+}
+
+#[test]
+fn constructors_methods_and_anonymous_functions_have_their_own_names() {
+    assert_eq!(trace("class P { constructor(x) { this.x = x / 0; } }
+var make = () => new P(1);
+make()"),
+               "ERROR: division by zero
+    at new P (file.js:1:37)
+    at make (file.js:2:18)
+    at file.js:3:1");
+    assert_eq!(trace("class A { m() { 1 / 0 } }\nnew A().m()"),
+               "ERROR: division by zero\n    at A.m (file.js:1:17)\n    at file.js:2:1");
+    assert_eq!(trace("[1].map(function(x) { x.nope() })"),
+               "ERROR: INTEGER has no method 'nope'
+    at <anonymous> (file.js:1:23)
+    at file.js:1:1");
+}
+
+#[test]
+fn scripts_are_named_script_by_default() {
+    assert_eq!(run_err("1 / 0"), "ERROR: division by zero\n    at <script>:1:1");
+}
+
+#[test]
+fn tail_calls_keep_the_frame_of_their_last_caller() {
+    assert_eq!(trace("var mult = function(a, b) { return a * b / 0; };
+var callback = function(f, a, b) { return f(a, b); };
+callback(mult, 10, 0)"),
+               "ERROR: division by zero
+    at mult (file.js:1:36)
+    at callback (file.js:2:43)
+    at file.js:3:1");
+    assert_eq!(trace("var f = function(n) { if (n == 0) { return 1 / 0; } return f(n - 1); };
+var g = function() { return f(3); };
+g()"),
+               "ERROR: division by zero
+    at f (file.js:1:44)
+    at f (file.js:1:60)
+    ... 3 tail calls elided
+    at file.js:3:1");
+    assert_eq!(trace("var f = function(n) { if (n == 0) { return n.nope(); } return f(n - 1); };
+f(1)"),
+               "ERROR: INTEGER has no method 'nope'
+    at f (file.js:1:44)
+    at f (file.js:1:63)
+    at file.js:2:1");
+}
+
+#[test]
+fn compiled_programs_name_the_source_they_were_compiled_from() {
+    let program = compile_named("var f = function() { 1 / 0 };\nf()", "lib.js").unwrap();
+    let mut exec = Executor::new().with_source_name("main.js");
+    assert_eq!(exec.execute_compiled(&program),
+               Err(ExecutionError::Runtime("ERROR: division by zero
+    at f (lib.js:1:22)
+    at lib.js:2:1"
+                                               .to_owned())));
+    // The name is the Executor's again for the scripts it runs after.
+    assert_eq!(exec.execute("1 / 0"),
+               Err(ExecutionError::Runtime("ERROR: division by zero\n    at main.js:1:1"
+                                               .to_owned())));
+    let program = compile("1 / 0").unwrap();
+    assert_eq!(exec.execute_compiled(&program),
+               Err(ExecutionError::Runtime("ERROR: division by zero\n    at <script>:1:1"
+                                               .to_owned())));
+}